@@ -0,0 +1,109 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use emvsign::tlv::{
+    decoders,
+    elements::{ElementType, ELEMENTS},
+    encoders, FieldMap, Value,
+};
+
+fn field(tag: u16, value: Value) -> Vec<u8> {
+    let mut out = Vec::new();
+    encoders::write_field(tag, &value, &mut out);
+    out
+}
+
+/// SELECT response for a typical credit application
+fn fci() -> Vec<u8> {
+    let mut discretionary = FieldMap::new();
+    discretionary.insert(0x9f4d, Value::Binary(vec![0x0b, 0x0a]));
+    discretionary.insert(0x9f0a, Value::Binary(vec![0x00, 0x01, 0x05, 0x01]));
+
+    let mut proprietary = FieldMap::new();
+    proprietary.insert(0x50, Value::AlphanumericSpecial("VISA CREDIT".to_string()));
+    proprietary.insert(0x87, Value::Binary(vec![0x01]));
+    proprietary.insert(
+        0x9f38,
+        Value::Binary(vec![0x9f, 0x66, 0x04, 0x9f, 0x02, 0x06, 0x9f, 0x37, 0x04]),
+    );
+    proprietary.insert(0x5f2d, Value::Alphanumeric("enfr".to_string()));
+    proprietary.insert(0xbf0c, Value::Template(discretionary));
+
+    let mut fci = FieldMap::new();
+    fci.insert(
+        0x84,
+        Value::Binary(vec![0xa0, 0x00, 0x00, 0x00, 0x03, 0x10, 0x10]),
+    );
+    fci.insert(0xa5, Value::Template(proprietary));
+    field(0x6f, Value::Template(fci))
+}
+
+/// Record holding the issuer certificate and the usual cardholder data
+fn record() -> Vec<u8> {
+    let mut fields = FieldMap::new();
+    fields.insert(
+        0x5a,
+        Value::DigitString(vec![4, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1]),
+    );
+    fields.insert(0x5f20, Value::AlphanumericSpecial("DOE/JANE".to_string()));
+    fields.insert(0x5f24, Value::Binary(vec![0x30, 0x12, 0x31]));
+    fields.insert(0x5f28, Value::Numeric(840));
+    fields.insert(0x5f34, Value::Numeric(1));
+    fields.insert(0x8e, Value::Binary(vec![0; 14]));
+    fields.insert(0x8f, Value::Binary(vec![0x09]));
+    fields.insert(0x90, Value::Binary((0..=175).collect()));
+    fields.insert(0x92, Value::Binary(vec![0xa5; 36]));
+    fields.insert(0x9f07, Value::Binary(vec![0xff, 0x00]));
+    fields.insert(0x9f0d, Value::Binary(vec![0xb0, 0x50, 0xbc, 0x88, 0x00]));
+    fields.insert(0x9f0e, Value::Binary(vec![0x00, 0x00, 0x00, 0x00, 0x00]));
+    fields.insert(0x9f0f, Value::Binary(vec![0xb0, 0x70, 0xbc, 0x98, 0x00]));
+    fields.insert(0x9f32, Value::Binary(vec![0x03]));
+    fields.insert(0x9f42, Value::Numeric(840));
+    field(0x70, Value::Template(fields))
+}
+
+/// A PSE directory with many applications, each with nested discretionary data
+fn nested() -> Vec<u8> {
+    let mut directory = FieldMap::new();
+    for idx in 0..32u8 {
+        let mut discretionary = FieldMap::new();
+        discretionary.insert(0x9f0a, Value::Binary(vec![0x00, 0x01, 0x05, idx]));
+        discretionary.insert(0x9f2a, Value::Binary(vec![idx]));
+
+        let mut application = FieldMap::new();
+        application.insert(
+            0x4f,
+            Value::Binary(vec![0xa0, 0x00, 0x00, 0x00, 0x03, 0x10, idx]),
+        );
+        application.insert(0x50, Value::AlphanumericSpecial(format!("APP {}", idx)));
+        application.insert(0x87, Value::Binary(vec![idx & 0x0f]));
+        application.insert(0x73, Value::Template(discretionary));
+        directory.insert(0x61, Value::Template(application));
+    }
+    field(0x70, Value::Template(directory))
+}
+
+/// Walk every field of a template, descending into nested templates, without decoding
+fn walk_raw(raw: &[u8]) -> usize {
+    decoders::raw_fields(raw)
+        .map(|field| {
+            let (tag, value) = field.unwrap();
+            match ELEMENTS.get(&tag).map(|elem| elem.typ) {
+                Some(ElementType::Template) => 1 + walk_raw(value),
+                _ => 1,
+            }
+        })
+        .sum()
+}
+
+fn bench_tlv(c: &mut Criterion) {
+    for (name, fixture) in [("fci", fci()), ("record", record()), ("nested", nested())] {
+        c.bench_function(&format!("read_field/{}", name), |b| {
+            b.iter(|| decoders::read_field(black_box(&fixture)).unwrap())
+        });
+        c.bench_function(&format!("raw_fields/{}", name), |b| {
+            b.iter(|| walk_raw(black_box(&fixture)))
+        });
+    }
+}
+
+criterion_group!(benches, bench_tlv);
+criterion_main!(benches);
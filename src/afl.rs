@@ -0,0 +1,165 @@
+use std::{fmt::Display, ops::RangeInclusive};
+
+use serde::Serialize;
+
+use crate::tlv::DecodeError;
+
+/// One entry of the Application File Locator (0x94), see EMV 4.4 Book 3 section 10.2
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+pub struct AflEntry {
+    pub sfi: u8,
+    pub first_record: u8,
+    pub last_record: u8,
+    /// Number of records starting at `first_record` used for offline data authentication
+    pub sda_records: u8,
+}
+
+impl AflEntry {
+    pub fn records(&self) -> RangeInclusive<u8> {
+        self.first_record..=self.last_record
+    }
+
+    /// Whether `record` is included in the static data to be authenticated
+    pub fn is_sda_record(&self, record: u8) -> bool {
+        record >= self.first_record && record - self.first_record < self.sda_records
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Afl {
+    pub entries: Vec<AflEntry>,
+}
+
+impl Afl {
+    pub fn parse(raw: &[u8]) -> Result<Afl, DecodeError> {
+        let chunks = raw.chunks_exact(4);
+        if !chunks.remainder().is_empty() {
+            return Err(DecodeError::InvalidAfl(chunks.len(), "truncated entry"));
+        }
+
+        let entries = chunks
+            .enumerate()
+            .map(|(idx, entry)| {
+                // The SFI is in the top 5 bits, the rest are reserved
+                if entry[0] & 0b111 != 0 {
+                    return Err(DecodeError::InvalidAfl(idx, "reserved SFI bits set"));
+                }
+                // SFI 0 means the current file, 31 is reserved (Book 3 section 10.2)
+                if !(1..=30).contains(&(entry[0] >> 3)) {
+                    return Err(DecodeError::InvalidAfl(idx, "SFI out of range"));
+                }
+                // Records are numbered from 1 and 0xff is reserved (ISO 7816-4 section 7.3.3)
+                if entry[1] == 0 || entry[2] == 0xff {
+                    return Err(DecodeError::InvalidAfl(idx, "record number out of range"));
+                }
+                if entry[1] > entry[2] {
+                    return Err(DecodeError::InvalidAfl(
+                        idx,
+                        "first record is after last record",
+                    ));
+                }
+                if entry[3] > entry[2] - entry[1] + 1 {
+                    return Err(DecodeError::InvalidAfl(
+                        idx,
+                        "more records for offline data authentication than in the entry",
+                    ));
+                }
+                Ok(AflEntry {
+                    sfi: entry[0] >> 3,
+                    first_record: entry[1],
+                    last_record: entry[2],
+                    sda_records: entry[3],
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Afl { entries })
+    }
+}
+
+impl Display for AflEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "SFI 0x{:02x} records 0x{:02x}-0x{:02x} ({} for offline data authentication)",
+            self.sfi, self.first_record, self.last_record, self.sda_records
+        )
+    }
+}
+
+impl Display for Afl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for entry in &self.entries {
+            writeln!(f, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_afl() {
+        let afl = Afl::parse(&[0x08, 0x01, 0x03, 0x01, 0x10, 0x02, 0x02, 0x00]).unwrap();
+        assert_eq!(
+            afl.entries,
+            vec![
+                AflEntry {
+                    sfi: 1,
+                    first_record: 1,
+                    last_record: 3,
+                    sda_records: 1,
+                },
+                AflEntry {
+                    sfi: 2,
+                    first_record: 2,
+                    last_record: 2,
+                    sda_records: 0,
+                },
+            ]
+        );
+        assert!(afl.entries[0].is_sda_record(1));
+        assert!(!afl.entries[0].is_sda_record(2));
+        assert!(!afl.entries[1].is_sda_record(2));
+    }
+
+    #[test]
+    fn test_parse_afl_invalid() {
+        assert_eq!(
+            Afl::parse(&[0x08, 0x01, 0x03]),
+            Err(DecodeError::InvalidAfl(0, "truncated entry"))
+        );
+        assert_eq!(
+            Afl::parse(&[0x08, 0x01, 0x03, 0x01, 0x09, 0x01, 0x01, 0x00]),
+            Err(DecodeError::InvalidAfl(1, "reserved SFI bits set"))
+        );
+        assert_eq!(
+            Afl::parse(&[0x00, 0x01, 0x01, 0x00]),
+            Err(DecodeError::InvalidAfl(0, "SFI out of range"))
+        );
+        assert_eq!(
+            Afl::parse(&[0xf8, 0x01, 0x01, 0x00]),
+            Err(DecodeError::InvalidAfl(0, "SFI out of range"))
+        );
+        assert_eq!(
+            Afl::parse(&[0x08, 0x00, 0x01, 0x00]),
+            Err(DecodeError::InvalidAfl(0, "record number out of range"))
+        );
+        assert_eq!(
+            Afl::parse(&[0x08, 0x01, 0x02, 0x03]),
+            Err(DecodeError::InvalidAfl(
+                0,
+                "more records for offline data authentication than in the entry"
+            ))
+        );
+        assert_eq!(
+            Afl::parse(&[0x08, 0x03, 0x01, 0x00]),
+            Err(DecodeError::InvalidAfl(
+                0,
+                "first record is after last record"
+            ))
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use std::fmt::Display;
+
+use crate::tlv::DecodeError;
+
+/// Offline data authentication methods, in order of preference
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OfflineAuthentication {
+    /// Combined DDA/Application Cryptogram Generation
+    Cda,
+    /// Dynamic Data Authentication
+    Dda,
+    /// Static Data Authentication
+    Sda,
+    None,
+}
+
+impl Display for OfflineAuthentication {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            OfflineAuthentication::Cda => "CDA",
+            OfflineAuthentication::Dda => "DDA",
+            OfflineAuthentication::Sda => "SDA",
+            OfflineAuthentication::None => "no offline data authentication",
+        })
+    }
+}
+
+/// Application Interchange Profile (0x82), see EMV 4.4 Book 3 Annex C1
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Aip(pub [u8; 2]);
+
+impl Aip {
+    pub fn sda_supported(&self) -> bool {
+        self.0[0] & 0x40 != 0
+    }
+
+    pub fn dda_supported(&self) -> bool {
+        self.0[0] & 0x20 != 0
+    }
+
+    pub fn cardholder_verification_supported(&self) -> bool {
+        self.0[0] & 0x10 != 0
+    }
+
+    pub fn terminal_risk_management_required(&self) -> bool {
+        self.0[0] & 0x08 != 0
+    }
+
+    pub fn issuer_authentication_supported(&self) -> bool {
+        self.0[0] & 0x04 != 0
+    }
+
+    pub fn cda_supported(&self) -> bool {
+        self.0[0] & 0x01 != 0
+    }
+
+    /// The best offline data authentication method the card supports
+    pub fn offline_authentication(&self) -> OfflineAuthentication {
+        if self.cda_supported() {
+            OfflineAuthentication::Cda
+        } else if self.dda_supported() {
+            OfflineAuthentication::Dda
+        } else if self.sda_supported() {
+            OfflineAuthentication::Sda
+        } else {
+            OfflineAuthentication::None
+        }
+    }
+}
+
+impl TryFrom<&[u8]> for Aip {
+    type Error = DecodeError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        value
+            .try_into()
+            .map(Aip)
+            .map_err(|_| DecodeError::WrongType(0x82, "2 byte AIP"))
+    }
+}
+
+impl Display for Aip {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let features = [
+            (self.sda_supported(), "SDA"),
+            (self.dda_supported(), "DDA"),
+            (self.cda_supported(), "CDA"),
+            (
+                self.cardholder_verification_supported(),
+                "cardholder verification",
+            ),
+            (
+                self.terminal_risk_management_required(),
+                "terminal risk management",
+            ),
+            (
+                self.issuer_authentication_supported(),
+                "issuer authentication",
+            ),
+        ];
+        write!(f, "AIP {}:", hex::encode(self.0))?;
+        for (_, name) in features.iter().filter(|(supported, _)| *supported) {
+            write!(f, " {}", name)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offline_authentication() {
+        assert_eq!(
+            Aip([0x7d, 0x00]).offline_authentication(),
+            OfflineAuthentication::Cda
+        );
+        assert_eq!(
+            Aip([0x5c, 0x00]).offline_authentication(),
+            OfflineAuthentication::Sda
+        );
+        assert_eq!(
+            Aip([0x18, 0x00]).offline_authentication(),
+            OfflineAuthentication::None
+        );
+        assert_eq!(
+            Aip([0x7c, 0x00]).to_string(),
+            "AIP 7c00: SDA DDA cardholder verification terminal risk management issuer \
+             authentication"
+        );
+    }
+}
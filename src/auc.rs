@@ -0,0 +1,115 @@
+use std::fmt::Display;
+
+use crate::tlv::DecodeError;
+
+/// Application Usage Control (0x9f07): where and for what the issuer allows the application to be
+/// used, see EMV 4.4 Book 3 Annex C2. Domestic means the terminal's country matches the Issuer
+/// Country Code (0x5f28).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Auc(pub [u8; 2]);
+
+impl Auc {
+    pub fn domestic_cash(&self) -> bool {
+        self.0[0] & 0x80 != 0
+    }
+
+    pub fn international_cash(&self) -> bool {
+        self.0[0] & 0x40 != 0
+    }
+
+    pub fn domestic_goods(&self) -> bool {
+        self.0[0] & 0x20 != 0
+    }
+
+    pub fn international_goods(&self) -> bool {
+        self.0[0] & 0x10 != 0
+    }
+
+    pub fn domestic_services(&self) -> bool {
+        self.0[0] & 0x08 != 0
+    }
+
+    pub fn international_services(&self) -> bool {
+        self.0[0] & 0x04 != 0
+    }
+
+    pub fn atms(&self) -> bool {
+        self.0[0] & 0x02 != 0
+    }
+
+    /// Valid at terminals other than ATMs
+    pub fn other_terminals(&self) -> bool {
+        self.0[0] & 0x01 != 0
+    }
+
+    pub fn domestic_cashback(&self) -> bool {
+        self.0[1] & 0x80 != 0
+    }
+
+    pub fn international_cashback(&self) -> bool {
+        self.0[1] & 0x40 != 0
+    }
+
+    /// Names of the allowed uses
+    pub fn flags(&self) -> Vec<&'static str> {
+        [
+            (self.domestic_cash(), "domestic cash"),
+            (self.international_cash(), "international cash"),
+            (self.domestic_goods(), "domestic goods"),
+            (self.international_goods(), "international goods"),
+            (self.domestic_services(), "domestic services"),
+            (self.international_services(), "international services"),
+            (self.atms(), "ATMs"),
+            (self.other_terminals(), "terminals other than ATMs"),
+            (self.domestic_cashback(), "domestic cashback"),
+            (self.international_cashback(), "international cashback"),
+        ]
+        .into_iter()
+        .filter(|(allowed, _)| *allowed)
+        .map(|(_, name)| name)
+        .collect()
+    }
+}
+
+impl TryFrom<&[u8]> for Auc {
+    type Error = DecodeError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        value
+            .try_into()
+            .map(Auc)
+            .map_err(|_| DecodeError::WrongType(0x9f07, "2 byte Application Usage Control"))
+    }
+}
+
+impl Display for Auc {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "AUC {}: {}",
+            hex::encode(self.0),
+            self.flags().join(", ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auc() {
+        let auc = Auc::try_from(&[0xab, 0x80][..]).unwrap();
+        assert!(auc.domestic_cash());
+        assert!(!auc.international_cash());
+        assert!(auc.atms());
+        assert!(auc.domestic_cashback());
+        assert!(!auc.international_cashback());
+        assert_eq!(
+            auc.to_string(),
+            "AUC ab80: domestic cash, domestic goods, domestic services, ATMs, terminals other \
+             than ATMs, domestic cashback"
+        );
+        assert!(Auc::try_from(&[0xff][..]).is_err());
+    }
+}
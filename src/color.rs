@@ -0,0 +1,96 @@
+use std::fmt::Display;
+
+/// Whether output should be colored by default: `stream` is a terminal and `NO_COLOR` is not set
+/// (<https://no-color.org>)
+pub fn should_color(stream: &impl std::io::IsTerminal) -> bool {
+    std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()) && stream.is_terminal()
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Style {
+    /// Names of data elements
+    Tag,
+    /// Decoded values
+    Value,
+    /// Meaning of a value, such as the currency for a currency code
+    Note,
+    Pass,
+    Warning,
+    Error,
+}
+
+impl Style {
+    fn code(self) -> &'static str {
+        match self {
+            Style::Tag => "36",
+            Style::Value => "32",
+            Style::Note => "2",
+            Style::Pass => "1;32",
+            Style::Warning => "1;33",
+            Style::Error => "1;31",
+        }
+    }
+}
+
+/// Displays the inner value with a [`Style`] if color was enabled when it was created, see
+/// [`Painter::paint`]
+pub struct Painted<T> {
+    style: Style,
+    value: T,
+    color: bool,
+}
+
+impl<T: Display> Display for Painted<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.color {
+            write!(f, "\x1b[{}m{}\x1b[0m", self.style.code(), self.value)
+        } else {
+            self.value.fmt(f)
+        }
+    }
+}
+
+/// Whether to color output with ANSI escapes. The default is off so that library users and piped
+/// output get plain text.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Painter {
+    pub color: bool,
+}
+
+impl Painter {
+    pub fn new(color: bool) -> Painter {
+        Painter { color }
+    }
+
+    /// Color unless `disabled`, if [`should_color`] says so for `stream`
+    pub fn for_stream(stream: &impl std::io::IsTerminal, disabled: bool) -> Painter {
+        Painter::new(!disabled && should_color(stream))
+    }
+
+    /// Wrap `value` so it displays with `style` when color is on and as-is otherwise. The value
+    /// should not contain newlines, since the escapes would cover the indentation.
+    pub fn paint<T: Display>(self, style: Style, value: T) -> Painted<T> {
+        Painted {
+            style,
+            value,
+            color: self.color,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paint() {
+        assert_eq!(
+            Painter::default().paint(Style::Error, "oops").to_string(),
+            "oops"
+        );
+        assert_eq!(
+            Painter::new(true).paint(Style::Error, "oops").to_string(),
+            "\x1b[1;31moops\x1b[0m"
+        );
+    }
+}
@@ -0,0 +1,205 @@
+use std::fmt::Display;
+
+use anyhow::Context;
+use log::info;
+
+use crate::{
+    exchange::{AcType, CardTransport},
+    processing_options::{self, ProcessingResult},
+    tlv::{FieldMap, OptionsMap, Value},
+    transaction::{self, ApplicationCryptogram},
+};
+
+/// Terminal Transaction Qualifiers (0x9f66) sent when the terminal config doesn't set any: qVSDC
+/// and contact chip supported, online PIN and signature supported, online cryptogram required, and
+/// consumer device CVM supported. See EMV Contactless Book C-3 Annex A.
+pub const DEFAULT_TTQ: [u8; 4] = [0x36, 0x80, 0x40, 0x00];
+
+/// Contactless kernel, which decides how the transaction runs after GET PROCESSING OPTIONS. See
+/// EMV Contactless Book B section 3.3.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Kernel {
+    /// Kernel 2: records are read and the cryptogram comes from GENERATE AC as with contact
+    Mastercard,
+    /// Kernel 3: the cryptogram is returned by GET PROCESSING OPTIONS, often with no AFL
+    Visa,
+    /// Any other kernel, handled like whichever of the above the card's responses look like
+    Other(u8),
+}
+
+impl Kernel {
+    /// The kernel from the Kernel Identifier (0x9f2a) of a PPSE entry, see
+    /// [`crate::pse::ApplicationTemplate::kernel_id`]. Without one the kernel is picked from the
+    /// RID of `aid`.
+    pub fn from_id(kernel_id: Option<&[u8]>, aid: &[u8]) -> Kernel {
+        // The short kernel ID is the low 6 bits of the first byte
+        match kernel_id.and_then(|id| id.first()).map(|id| id & 0x3f) {
+            Some(2) => Kernel::Mastercard,
+            Some(3) => Kernel::Visa,
+            Some(id) => Kernel::Other(id),
+            None => Self::from_aid(aid),
+        }
+    }
+
+    /// The kernel for the payment system that owns the RID of `aid`
+    pub fn from_aid(aid: &[u8]) -> Kernel {
+        match aid.get(..5) {
+            Some([0xa0, 0x00, 0x00, 0x00, 0x04]) => Kernel::Mastercard,
+            Some([0xa0, 0x00, 0x00, 0x00, 0x03]) => Kernel::Visa,
+            _ => Kernel::Other(0),
+        }
+    }
+}
+
+impl Display for Kernel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Kernel::Mastercard => write!(f, "Mastercard (kernel 2)"),
+            Kernel::Visa => write!(f, "Visa (kernel 3)"),
+            Kernel::Other(0) => write!(f, "unknown kernel"),
+            Kernel::Other(id) => write!(f, "kernel {}", id),
+        }
+    }
+}
+
+/// Everything read during a contactless transaction by [`do_contactless_transaction`]
+#[derive(Debug, Clone)]
+pub struct ContactlessResult {
+    pub kernel: Kernel,
+    /// The fields from the GET PROCESSING OPTIONS response and every record in the AFL
+    pub processing: ProcessingResult,
+    pub cryptogram: ApplicationCryptogram,
+}
+
+/// The cryptogram returned in the GET PROCESSING OPTIONS response by Visa cards, or `None` if
+/// there isn't one and GENERATE AC is needed. A missing CID means an ARQC.
+pub fn cryptogram_from_fields(fields: &FieldMap) -> anyhow::Result<Option<ApplicationCryptogram>> {
+    let Some(cryptogram) = fields.get(&0x9f26).and_then(Value::as_binary) else {
+        return Ok(None);
+    };
+    let cryptogram = cryptogram
+        .try_into()
+        .context("Application Cryptogram is not 8 bytes")?;
+    let atc = fields
+        .get(&0x9f36)
+        .and_then(Value::as_binary)
+        .and_then(|atc| <[u8; 2]>::try_from(atc).ok())
+        .context("Card sent a cryptogram without a 2 byte ATC")?;
+    let cid = match fields.get(&0x9f27).and_then(Value::as_binary) {
+        Some(&[cid]) => cid,
+        Some(_) => anyhow::bail!("Cryptogram Information Data is not 1 byte"),
+        None => 0x80,
+    };
+
+    Ok(Some(ApplicationCryptogram {
+        cid,
+        atc: u16::from_be_bytes(atc),
+        cryptogram: Some(cryptogram),
+        signed_dynamic_data: fields
+            .get(&0x9f4b)
+            .and_then(Value::as_binary)
+            .map(<[u8]>::to_vec),
+        issuer_application_data: fields
+            .get(&0x9f10)
+            .and_then(Value::as_binary)
+            .map(<[u8]>::to_vec),
+        response_data: Vec::new(),
+    }))
+}
+
+/// Select `aid` and run a contactless transaction asking for an ARQC. The Terminal Transaction
+/// Qualifiers default to [`DEFAULT_TTQ`] if `state` doesn't have any. The AFL is optional, since
+/// Visa cards may send everything needed in the GET PROCESSING OPTIONS response.
+pub fn do_contactless_transaction<C: CardTransport + ?Sized>(
+    card: &mut C,
+    aid: &[u8],
+    kernel: Kernel,
+    state: &mut OptionsMap,
+) -> anyhow::Result<ContactlessResult> {
+    state
+        .entry(0x9f66)
+        .or_insert_with(|| Value::Binary(DEFAULT_TTQ.to_vec()));
+
+    let fci = processing_options::select_application(card, aid)?;
+    let fields = processing_options::get_processing_options(card, &fci, state)?;
+    if !fields.contains_key(&0x82) {
+        anyhow::bail!("Failed to read AIP");
+    }
+    let afl = fields
+        .get(&0x94)
+        .and_then(Value::as_binary)
+        .unwrap_or_default()
+        .to_vec();
+    let processing = processing_options::read_records(card, fields, &afl)?;
+    info!("Read {} records for {}", processing.records.len(), kernel);
+
+    let gpo_cryptogram = match kernel {
+        Kernel::Mastercard => None,
+        Kernel::Visa | Kernel::Other(_) => cryptogram_from_fields(&processing.fields)?,
+    };
+    let cryptogram = match gpo_cryptogram {
+        Some(cryptogram) => cryptogram,
+        None if kernel == Kernel::Visa => {
+            anyhow::bail!("Card did not return a cryptogram from GET PROCESSING OPTIONS")
+        }
+        None => transaction::generate_ac(card, &processing.fields, state, AcType::Arqc, false)?,
+    };
+
+    Ok(ContactlessResult {
+        kernel,
+        processing,
+        cryptogram,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        exchange::ADPUCommand,
+        replay::{ReplayTransport, TranscriptEntry},
+    };
+
+    #[test]
+    fn test_kernel() {
+        assert_eq!(Kernel::from_id(Some(&[0x03]), &[]), Kernel::Visa);
+        assert_eq!(
+            Kernel::from_id(None, &[0xa0, 0x00, 0x00, 0x00, 0x04, 0x10, 0x10]),
+            Kernel::Mastercard
+        );
+        assert_eq!(Kernel::from_id(Some(&[0x05]), &[]), Kernel::Other(5));
+    }
+
+    #[test]
+    fn test_visa_transaction() {
+        let aid = [0xa0, 0x00, 0x00, 0x00, 0x03, 0x10, 0x10];
+        let mut card = ReplayTransport::new(vec![
+            TranscriptEntry::new(
+                &ADPUCommand::select(&aid),
+                b"\x6f\x11\x84\x07\xa0\x00\x00\x00\x03\x10\x10\xa5\x06\x9f\x38\x03\x9f\x66\x04\x90\
+                  \x00",
+            ),
+            TranscriptEntry::new(
+                &ADPUCommand::get_processing_options(&[0x83, 0x04, 0x36, 0x80, 0x40, 0x00]),
+                b"\x77\x19\x82\x02\x20\x00\x9f\x36\x02\x00\x2a\x9f\x26\x08\x01\x02\x03\x04\x05\x06\
+                  \x07\x08\x9f\x10\x02\xab\xcd\x90\x00",
+            ),
+        ]);
+
+        let mut state = OptionsMap::new();
+        let result = do_contactless_transaction(&mut card, &aid, Kernel::Visa, &mut state).unwrap();
+        assert_eq!(
+            result.cryptogram,
+            ApplicationCryptogram {
+                cid: 0x80,
+                atc: 42,
+                cryptogram: Some([1, 2, 3, 4, 5, 6, 7, 8]),
+                signed_dynamic_data: None,
+                issuer_application_data: Some(vec![0xab, 0xcd]),
+                response_data: Vec::new(),
+            }
+        );
+        assert!(result.processing.records.is_empty());
+        assert_eq!(card.remaining(), 0);
+    }
+}
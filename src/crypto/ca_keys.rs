@@ -1,8 +1,9 @@
 use chrono::NaiveDate;
-use crypto_bigint::U2048;
 use lazy_static::lazy_static;
 use std::collections::HashMap;
 
+use super::Modulus;
+
 #[derive(Debug, PartialEq, Eq, Copy, Clone, Hash)]
 pub struct KeyId {
     pub rid: [u8; 5],
@@ -13,7 +14,7 @@ pub struct KeyId {
 pub struct KeyData {
     pub expiry: NaiveDate,
     pub exponent: u32,
-    pub modulus: U2048,
+    pub modulus: Modulus,
 }
 
 macro_rules! keys_map {
@@ -27,7 +28,7 @@ macro_rules! keys_map {
                 KeyData {
                     expiry: NaiveDate::from_ymd_opt($year, $month, $day).unwrap(),
                     exponent: $exponent,
-                    modulus: U2048::from_be_hex(&format!("{:0>512}", $modulus.replace(" ", "")))
+                    modulus: Modulus::from_be_hex(&format!("{:0>1$}", $modulus.replace(" ", ""), Modulus::BYTES * 2))
                 }
             )
         ,)*])
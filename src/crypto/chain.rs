@@ -1,35 +1,132 @@
+use std::fmt::Display;
+
 use chrono::NaiveDate;
 use crypto_bigint::modular::runtime_mod::DynResidue;
 use crypto_bigint::modular::runtime_mod::DynResidueParams;
 use crypto_bigint::prelude::*;
-use crypto_bigint::U2048;
 
-use log::debug;
+use log::{debug, warn};
 use sha1::Digest;
 use sha1::Sha1;
 
+use crate::pan::{display_pan, luhn_valid, DisplayPan};
 use crate::tlv::decoders::compressed_numeric;
 use crate::tlv::decoders::numeric;
 use crate::tlv::FieldMap;
 use crate::tlv::Value;
 use crate::util::left_pad_slice;
 
-use super::{KeyId, VerifyError, CA_KEYS};
+use super::{KeyId, Modulus, VerifyError, CA_KEYS, MAX_CERTIFICATE_BYTES};
 
-fn certificate_to_bigint(certificate: &[u8]) -> Result<U2048, VerifyError> {
-    if certificate.len() > 248 {
+pub(super) fn certificate_to_bigint(certificate: &[u8]) -> Result<Modulus, VerifyError> {
+    if certificate.len() > MAX_CERTIFICATE_BYTES {
         return Err(VerifyError::CertificateTooLarge(certificate.len()));
     }
 
-    let mut arr = [0u8; 256];
-    arr[256 - certificate.len()..].copy_from_slice(certificate);
+    let mut arr = [0u8; Modulus::BYTES];
+    arr[Modulus::BYTES - certificate.len()..].copy_from_slice(certificate);
+
+    Ok(Modulus::from_be_slice(&arr))
+}
+
+/// Recover data signed with the RSA key `modulus`, `exponent`. The signed data must be exactly as
+/// long as the modulus. (Book 2 Annex B2.1)
+pub(super) fn recover(
+    signed: &[u8],
+    modulus: Modulus,
+    exponent: u32,
+) -> Result<Vec<u8>, VerifyError> {
+    // Make sure the modulus is the same length as the signed data
+    // This will also be the length of the recovered data
+    let recovered_len = modulus.bits_vartime().div_ceil(8);
+    if recovered_len != signed.len() {
+        return Err(VerifyError::CertificateLengthMismatch {
+            mod_size: recovered_len,
+            cert_size: signed.len(),
+        });
+    }
+
+    let signed = certificate_to_bigint(signed)?;
+
+    // A very annoying way of doing (signed ** exponent) % modulus
+    let recovered_arr = DynResidue::new(&signed, DynResidueParams::new(&modulus))
+        .pow_bounded_exp(&Modulus::from_u32(exponent), 32)
+        .retrieve()
+        .to_be_bytes();
+
+    let recovered = recovered_arr[Modulus::BYTES - recovered_len..].to_vec();
+    debug!("Recovered {}", hex::encode(&recovered));
+    Ok(recovered)
+}
+
+/// Recovered Data Header, the first byte of all signed data (Book 2 Annex A2.1)
+pub(super) const RECOVERED_DATA_HEADER: u8 = 0x6a;
+/// Recovered Data Trailer, the last byte of all signed data
+pub(super) const RECOVERED_DATA_TRAILER: u8 = 0xbc;
+
+/// Signed Data Format of an issuer public key certificate, the second byte after recovery
+pub(super) const FORMAT_ISSUER_CERTIFICATE: u8 = 0x02;
+/// Signed Data Format of Signed Static Application Data
+pub(super) const FORMAT_STATIC_DATA: u8 = 0x03;
+/// Signed Data Format of an ICC or ICC PIN Encipherment public key certificate
+pub(super) const FORMAT_ICC_CERTIFICATE: u8 = 0x04;
+/// Signed Data Format of Signed Dynamic Application Data
+pub(super) const FORMAT_DYNAMIC_DATA: u8 = 0x05;
 
-    Ok(U2048::from_be_slice(&arr))
+/// Hash Algorithm Indicator for SHA-1, the only one defined (Book 2 Annex B3)
+pub(super) const HASH_ALGORITHM_SHA1: u8 = 0x01;
+/// Public Key Algorithm Indicator for RSA, the only one defined
+pub(super) const PKI_ALGORITHM_RSA: u8 = 0x01;
+
+/// Check that recovered data is at least `min_len` bytes and has the header, `format`, and
+/// trailer (Book 2 section 5.3 steps 2-4)
+pub(super) fn check_header(
+    recovered: &[u8],
+    format: u8,
+    min_len: usize,
+) -> Result<(), VerifyError> {
+    if recovered.len() < min_len {
+        return Err(VerifyError::InvalidData);
+    }
+    check_bytes(
+        recovered,
+        &[
+            (0, RECOVERED_DATA_HEADER),
+            (1, format),
+            (recovered.len() - 1, RECOVERED_DATA_TRAILER),
+        ],
+    )
 }
 
-fn date_ym(mmyy: &[u8]) -> Result<NaiveDate, VerifyError> {
-    let mut year = 2000 + numeric(&mmyy[1..2]).map_err(|_| VerifyError::InvalidData)?;
-    let mut month = numeric(&mmyy[0..1]).map_err(|_| VerifyError::InvalidData)?;
+/// Check the fixed bytes of recovered data, given as (position, expected value)
+pub(super) fn check_bytes(recovered: &[u8], expected: &[(usize, u8)]) -> Result<(), VerifyError> {
+    match expected
+        .iter()
+        .find(|&&(position, value)| recovered[position] != value)
+    {
+        Some(&(position, expected)) => Err(VerifyError::BadCertificateFormat {
+            position,
+            expected,
+            actual: recovered[position],
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Compare the hash found in recovered data with the hash of the data it signs
+pub(super) fn check_hash(expected: &[u8], actual: &[u8]) -> Result<(), VerifyError> {
+    if expected != actual {
+        return Err(VerifyError::HashMismatch {
+            expected: expected.to_vec(),
+            actual: actual.to_vec(),
+        });
+    }
+    Ok(())
+}
+
+pub(super) fn date_ym(mmyy: &[u8]) -> Result<NaiveDate, VerifyError> {
+    let mut year = 2000 + numeric(&mmyy[1..2])?;
+    let mut month = numeric(&mmyy[0..1])?;
     if month == 12 {
         year += 1;
         month = 1;
@@ -39,17 +136,42 @@ fn date_ym(mmyy: &[u8]) -> Result<NaiveDate, VerifyError> {
         .ok_or(VerifyError::InvalidData)
 }
 
+/// The contents of an issuer or ICC public key certificate after recovery, see EMV Book 2 tables 6
+/// and 14
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RecoveredCertificate {
+    /// Issuer identifier (leftmost PAN digits) or the full application PAN
+    pub subject: Vec<u8>,
+    pub expiry: NaiveDate,
+    pub serial: [u8; 3],
+    pub exponent: u32,
+    pub modulus: Modulus,
+    /// Hash algorithm indicator, 0x01 is SHA-1
+    pub hash_algorithm: u8,
+    /// Public key algorithm indicator, 0x01 is RSA
+    pub pki_algorithm: u8,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum CertificateKind {
+    Issuer,
+    Icc,
+    /// ICC PIN Encipherment Public Key, same format as the ICC certificate (Book 2 section 7.1)
+    IccPin,
+}
+
 fn parse_certificate(
-    is_icc: bool,
-    parent_modulus: U2048,
+    kind: CertificateKind,
+    parent_modulus: Modulus,
     parent_exponent: u32,
     options: &FieldMap,
     extra_signed_data: &[u8],
-) -> Result<(Vec<u8>, NaiveDate, [u8; 3], u32, U2048), VerifyError> {
-    let (child_certificate_tag, child_exponent_tag, child_remainder_tag) = if !is_icc {
-        (0x90, 0x9f32, 0x92)
-    } else {
-        (0x9f46, 0x9f47, 0x9f48)
+) -> Result<RecoveredCertificate, VerifyError> {
+    let is_icc = kind != CertificateKind::Issuer;
+    let (child_certificate_tag, child_exponent_tag, child_remainder_tag) = match kind {
+        CertificateKind::Issuer => (0x90, 0x9f32, 0x92),
+        CertificateKind::Icc => (0x9f46, 0x9f47, 0x9f48),
+        CertificateKind::IccPin => (0x9f2d, 0x9f2e, 0x9f2f),
     };
     let child_certificate_slice = options
         .get(&child_certificate_tag)
@@ -71,64 +193,38 @@ fn parse_certificate(
     // For the issuer public key we just have the IIN (start of the PAN)
     let pan_len = if is_icc { 10 } else { 4 };
 
-    // Step 1: Make sure the parent modulus is the same length as the encrypted child certificate
-    // This will also be the length of the recovered data
-    let recovered_len = (parent_modulus.bits_vartime() + 7) / 8;
-    if recovered_len != child_certificate_slice.len() {
-        return Err(VerifyError::CertificateLengthMismatch {
-            mod_size: recovered_len,
-            cert_size: child_certificate_slice.len(),
-        });
-    }
-
-    // Step 2: recover the certificate
-    let child_certificate = certificate_to_bigint(child_certificate_slice)?;
-
-    // A very annoying way of doing (issuer_certificate ** exponent) % modulus
-    // See EMV Book 2 Annex B2.1
-    let recovered_arr = DynResidue::new(&child_certificate, DynResidueParams::new(&parent_modulus))
-        .pow_bounded_exp(&U2048::from_u32(parent_exponent), 32)
-        .retrieve()
-        .to_be_bytes();
+    // Steps 1-2: recover the certificate
+    let recovered = &recover(child_certificate_slice, parent_modulus, parent_exponent)?;
+    let recovered_len = recovered.len();
 
-    let recovered = &recovered_arr[256 - recovered_len..];
-
-    debug!("Recovered {}", hex::encode(recovered));
-
-    // Steps 3-4, 11: Make sure we understand the cert type
-    if !is_icc
-        && (recovered[0] != 0x6a
-            || recovered[1] != 0x02
-            || recovered[11] != 0x01
-            || recovered[12] != 0x01)
-        || is_icc
-            && (recovered[0] != 0x6a
-                || recovered[1] != 0x04
-                || recovered[17] != 0x01
-                || recovered[18] != 0x01)
-    {
-        return Err(VerifyError::InvalidSignature);
-    }
+    // Steps 2-4, 11: Make sure we understand the cert type. Everything up to the exponent length,
+    // then the hash and trailer, must fit.
+    let format = if is_icc {
+        FORMAT_ICC_CERTIFICATE
+    } else {
+        FORMAT_ISSUER_CERTIFICATE
+    };
+    check_header(recovered, format, 32 + pan_len)?;
+    check_bytes(
+        recovered,
+        &[
+            (7 + pan_len, HASH_ALGORITHM_SHA1),
+            (8 + pan_len, PKI_ALGORITHM_RSA),
+        ],
+    )?;
 
     // Steps 5-7: Check the hash
     let mut hasher = Sha1::new();
     hasher.update(&recovered[1..recovered_len - 21]);
     hasher.update(child_remainder);
     hasher.update(child_exponent_slice);
+    // For the ICC certificate this is the static data to be authenticated, which already includes
+    // anything from the SDA tag list (0x9f4a)
     hasher.update(extra_signed_data);
-    // If is_icc is true then we're doing CDA/DDA, in which case only 0x82 (AIP) is allowed
-    // If this isn't true then we'll have an invalid signature anyway, so just assume that it's only 0x82
-    if is_icc && options.contains_key(&0x9f4a) {
-        hasher.update(
-            options
-                .get(&0x82)
-                .and_then(Value::as_binary)
-                .unwrap_or_default(),
-        )
-    }
-    if hasher.finalize()[..] != recovered[recovered_len - 21..recovered_len - 1] {
-        return Err(VerifyError::InvalidSignature);
-    }
+    check_hash(
+        &recovered[recovered_len - 21..recovered_len - 1],
+        &hasher.finalize(),
+    )?;
 
     // Step 8: Check if PAN matches
     let cert_pan =
@@ -146,7 +242,7 @@ fn parse_certificate(
     // Step 11: Format everything and return
     let child_modulus_len = usize::from(recovered[9 + pan_len]);
 
-    let child_modulus_len = if child_modulus_len <= recovered_len - 32 - pan_len {
+    let child_modulus = if child_modulus_len <= recovered_len - 32 - pan_len {
         certificate_to_bigint(&recovered[11 + pan_len..11 + pan_len + child_modulus_len])?
     } else {
         certificate_to_bigint(&recovered[11 + pan_len..recovered_len - 21])?
@@ -158,13 +254,15 @@ fn parse_certificate(
         return Err(VerifyError::InvalidData);
     }
 
-    Ok((
-        cert_pan,
-        date_ym(&recovered[2 + pan_len..4 + pan_len])?,
-        recovered[4 + pan_len..7 + pan_len].try_into().unwrap(),
-        u32::from_be_bytes(left_pad_slice(child_exponent_slice)),
-        child_modulus_len,
-    ))
+    Ok(RecoveredCertificate {
+        subject: cert_pan,
+        expiry: date_ym(&recovered[2 + pan_len..4 + pan_len])?,
+        serial: recovered[4 + pan_len..7 + pan_len].try_into().unwrap(),
+        exponent: u32::from_be_bytes(left_pad_slice(child_exponent_slice)),
+        modulus: child_modulus,
+        hash_algorithm: recovered[7 + pan_len],
+        pki_algorithm: recovered[8 + pan_len],
+    })
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -173,7 +271,7 @@ pub struct IssuerPublicKey {
     pub expiry: NaiveDate,
     pub serial_number: [u8; 3],
     pub exponent: u32,
-    pub modulus: U2048,
+    pub modulus: Modulus,
 }
 
 impl IssuerPublicKey {
@@ -188,26 +286,51 @@ impl IssuerPublicKey {
             .get(&KeyId { rid, index })
             .ok_or(VerifyError::UnknownCAKey { rid, index })?;
 
-        let (iin, expiry, serial_number, exponent, modulus) =
-            parse_certificate(false, ca_key.modulus, ca_key.exponent, options, &[])?;
+        let certificate = parse_certificate(
+            CertificateKind::Issuer,
+            ca_key.modulus,
+            ca_key.exponent,
+            options,
+            &[],
+        )?;
 
-        Ok(Self {
-            iin,
-            expiry,
-            serial_number,
-            exponent,
-            modulus,
-        })
+        Ok(Self::from(certificate))
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+impl From<RecoveredCertificate> for IssuerPublicKey {
+    fn from(certificate: RecoveredCertificate) -> Self {
+        Self {
+            iin: certificate.subject,
+            expiry: certificate.expiry,
+            serial_number: certificate.serial,
+            exponent: certificate.exponent,
+            modulus: certificate.modulus,
+        }
+    }
+}
+
+impl Display for IssuerPublicKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Issuer public key for IIN {}, expires {}, serial {}, exponent {}, {} bit modulus",
+            display_pan(&self.iin, false),
+            self.expiry.format("%Y-%m"),
+            hex::encode(self.serial_number),
+            self.exponent,
+            self.modulus.bits_vartime()
+        )
+    }
+}
+
+#[derive(PartialEq, Eq, Clone)]
 pub struct ICCPublicKey {
     pub pan: Vec<u8>,
     pub expiry: NaiveDate,
     pub serial_number: [u8; 3],
     pub exponent: u32,
-    pub modulus: U2048,
+    pub modulus: Modulus,
 }
 
 impl ICCPublicKey {
@@ -216,20 +339,129 @@ impl ICCPublicKey {
         sda_data: &[u8],
         options: &FieldMap,
     ) -> Result<Self, VerifyError> {
-        let (pan, expiry, serial_number, exponent, modulus) = parse_certificate(
-            true,
+        let certificate = parse_certificate(
+            CertificateKind::Icc,
             issuer_key.modulus,
             issuer_key.exponent,
             options,
             sda_data,
         )?;
 
-        Ok(Self {
-            pan,
-            expiry,
-            serial_number,
-            exponent,
-            modulus,
-        })
+        // A bad check digit is more likely a decoding bug than a real PAN
+        if !luhn_valid(&certificate.subject) {
+            warn!("PAN recovered from ICC certificate fails Luhn check");
+        }
+
+        Ok(Self::from(certificate))
+    }
+
+    /// Recover the key used to encipher the offline PIN. This is the ICC PIN Encipherment Public
+    /// Key (0x9f2d) if the card has one, otherwise the ICC public key. (Book 2 section 7.1)
+    pub fn pin_from_options(
+        issuer_key: &IssuerPublicKey,
+        sda_data: &[u8],
+        options: &FieldMap,
+    ) -> Result<Self, VerifyError> {
+        if !options.contains_key(&0x9f2d) {
+            return Self::from_options(issuer_key, sda_data, options);
+        }
+
+        // Unlike the ICC certificate, no static data is signed
+        let certificate = parse_certificate(
+            CertificateKind::IccPin,
+            issuer_key.modulus,
+            issuer_key.exponent,
+            options,
+            &[],
+        )?;
+        Ok(Self::from(certificate))
+    }
+}
+
+impl From<RecoveredCertificate> for ICCPublicKey {
+    fn from(certificate: RecoveredCertificate) -> Self {
+        Self {
+            pan: certificate.subject,
+            expiry: certificate.expiry,
+            serial_number: certificate.serial,
+            exponent: certificate.exponent,
+            modulus: certificate.modulus,
+        }
+    }
+}
+
+/// Verify the Signed Static Application Data (0x93) with the issuer key and return the Data
+/// Authentication Code. `sda_data` is the static data to be authenticated from the AFL records.
+/// See EMV Book 2 section 5.4
+pub fn verify_sda(
+    issuer_key: &IssuerPublicKey,
+    options: &FieldMap,
+    sda_data: &[u8],
+) -> Result<[u8; 2], VerifyError> {
+    let signed = options
+        .get(&0x93)
+        .and_then(Value::as_binary)
+        .ok_or(VerifyError::MissingTag(0x93))?;
+
+    // Steps 1-2: Recover the signed data
+    let recovered = recover(signed, issuer_key.modulus, issuer_key.exponent)?;
+    let recovered_len = recovered.len();
+
+    // Steps 2-4: Check the trailer, header, and format
+    check_header(&recovered, FORMAT_STATIC_DATA, 26)?;
+
+    // Steps 5-7: Check the hash
+    let mut hasher = Sha1::new();
+    hasher.update(&recovered[1..recovered_len - 21]);
+    hasher.update(sda_data);
+    check_hash(
+        &recovered[recovered_len - 21..recovered_len - 1],
+        &hasher.finalize(),
+    )?;
+
+    Ok(recovered[3..5].try_into().unwrap())
+}
+
+/// How a key expiring on `expiry` compares to `today`, e.g. `EXPIRED` or `expires in 30 days`.
+/// Expired keys are still usable here, this is only for information.
+pub fn expiry_note(expiry: NaiveDate, today: NaiveDate) -> String {
+    match (expiry - today).num_days() {
+        days if days < 0 => "EXPIRED".to_string(),
+        0 => "expires today".to_string(),
+        1 => "expires tomorrow".to_string(),
+        days => format!("expires in {} days", days),
+    }
+}
+
+/// Like the derived implementation, except the PAN is masked
+impl std::fmt::Debug for ICCPublicKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ICCPublicKey")
+            .field("pan", &display_pan(&self.pan, false))
+            .field("expiry", &self.expiry)
+            .field("serial_number", &self.serial_number)
+            .field("exponent", &self.exponent)
+            .field("modulus", &self.modulus)
+            .finish()
+    }
+}
+
+impl Display for ICCPublicKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_pan(f, false)
+    }
+}
+
+impl DisplayPan for ICCPublicKey {
+    fn fmt_pan(&self, f: &mut std::fmt::Formatter<'_>, show_pan: bool) -> std::fmt::Result {
+        write!(
+            f,
+            "ICC public key for PAN {}, expires {}, serial {}, exponent {}, {} bit modulus",
+            display_pan(&self.pan, show_pan),
+            self.expiry.format("%Y-%m"),
+            hex::encode(self.serial_number),
+            self.exponent,
+            self.modulus.bits_vartime()
+        )
     }
 }
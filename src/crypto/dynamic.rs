@@ -0,0 +1,95 @@
+use sha1::{Digest, Sha1};
+
+use super::{
+    chain::{
+        check_hash, check_header, recover, ICCPublicKey, FORMAT_DYNAMIC_DATA, HASH_ALGORITHM_SHA1,
+    },
+    VerifyError,
+};
+
+/// Signed Dynamic Application Data after recovery, see EMV Book 2 tables 17 and 22
+struct RecoveredDynamicData {
+    recovered: Vec<u8>,
+}
+
+impl RecoveredDynamicData {
+    /// Recover `sdad` with the ICC key and check the header, trailer, and format. (Book 2 section
+    /// 6.5.2 steps 1-4)
+    fn recover(icc_key: &ICCPublicKey, sdad: &[u8]) -> Result<Self, VerifyError> {
+        let recovered = recover(sdad, icc_key.modulus, icc_key.exponent)?;
+        let recovered_len = recovered.len();
+        check_header(&recovered, FORMAT_DYNAMIC_DATA, 26)?;
+        // Only SHA-1 is defined
+        if recovered[2] != HASH_ALGORITHM_SHA1 {
+            return Err(VerifyError::InvalidData);
+        }
+        // The ICC Dynamic Data has to fit before the hash
+        if 4 + recovered[3] as usize > recovered_len - 21 {
+            return Err(VerifyError::InvalidData);
+        }
+
+        Ok(Self { recovered })
+    }
+
+    /// Check the hash over the recovered data followed by `terminal_data` (steps 5-7)
+    fn check_hash(&self, terminal_data: &[u8]) -> Result<(), VerifyError> {
+        let recovered_len = self.recovered.len();
+        let mut hasher = Sha1::new();
+        hasher.update(&self.recovered[1..recovered_len - 21]);
+        hasher.update(terminal_data);
+        check_hash(
+            &self.recovered[recovered_len - 21..recovered_len - 1],
+            &hasher.finalize(),
+        )
+    }
+
+    fn icc_dynamic_data(&self) -> &[u8] {
+        &self.recovered[4..4 + self.recovered[3] as usize]
+    }
+
+    /// The ICC Dynamic Number, which is at the start of the ICC Dynamic Data
+    fn icc_dynamic_number(&self) -> Result<&[u8], VerifyError> {
+        let data = self.icc_dynamic_data();
+        let len = *data.first().ok_or(VerifyError::InvalidData)? as usize;
+        data.get(1..1 + len).ok_or(VerifyError::InvalidData)
+    }
+}
+
+/// Verify the Signed Dynamic Application Data returned by INTERNAL AUTHENTICATE. `ddol_data` is
+/// the data sent with the command. Returns the ICC Dynamic Number. See EMV Book 2 section 6.5
+pub fn verify_dda(
+    icc_key: &ICCPublicKey,
+    sdad: &[u8],
+    ddol_data: &[u8],
+) -> Result<Vec<u8>, VerifyError> {
+    let recovered = RecoveredDynamicData::recover(icc_key, sdad)?;
+    recovered.check_hash(ddol_data)?;
+    Ok(recovered.icc_dynamic_number()?.to_vec())
+}
+
+/// Verify the Signed Dynamic Application Data returned by GENERATE AC with CDA and return the
+/// Application Cryptogram inside it. `cid` is the Cryptogram Information Data from the response
+/// and `transaction_data` is the PDOL data, CDOL data, and the other response fields concatenated
+/// as described in Book 2 section 6.6.2 step 10. See EMV Book 2 section 6.6
+pub fn verify_cda(
+    icc_key: &ICCPublicKey,
+    sdad: &[u8],
+    unpredictable_number: &[u8],
+    cid: u8,
+    transaction_data: &[u8],
+) -> Result<[u8; 8], VerifyError> {
+    let recovered = RecoveredDynamicData::recover(icc_key, sdad)?;
+    recovered.check_hash(unpredictable_number)?;
+
+    // ICC Dynamic Number, then CID, AC, and Transaction Data Hash Code (Book 2 table 22)
+    let dynamic_data = recovered.icc_dynamic_data();
+    let number_len = 1 + recovered.icc_dynamic_number()?.len();
+    let rest = dynamic_data
+        .get(number_len..number_len + 29)
+        .ok_or(VerifyError::InvalidData)?;
+    if rest[0] != cid {
+        return Err(VerifyError::InvalidData);
+    }
+    check_hash(&rest[9..29], &Sha1::digest(transaction_data))?;
+    Ok(rest[1..9].try_into().unwrap())
+}
@@ -0,0 +1,28 @@
+use rand::RngCore;
+
+use super::{chain::recover, chain::ICCPublicKey, VerifyError};
+
+/// Encipher a PIN block for VERIFY with the ICC PIN Encipherment Public Key (or ICC public key).
+/// `challenge` is the ICC Unpredictable Number from GET CHALLENGE, the rest of the block is
+/// filled from `rng`. See EMV Book 2 section 7.2
+pub fn encipher_pin(
+    key: &ICCPublicKey,
+    pin_block: &[u8; 8],
+    challenge: &[u8; 8],
+    rng: &mut impl RngCore,
+) -> Result<Vec<u8>, VerifyError> {
+    let key_len = key.modulus.bits_vartime().div_ceil(8);
+    if key_len < 17 {
+        return Err(VerifyError::InvalidData);
+    }
+
+    // Header 0x7f keeps the data smaller than the modulus
+    let mut data = vec![0u8; key_len];
+    data[0] = 0x7f;
+    data[1..9].copy_from_slice(pin_block);
+    data[9..17].copy_from_slice(challenge);
+    rng.fill_bytes(&mut data[17..]);
+
+    // Encryption with the public key is the same operation as recovering signed data
+    recover(&data, key.modulus, key.exponent)
+}
@@ -1,14 +1,36 @@
 use std::{error::Error, fmt::Display};
 
-#[derive(Debug, PartialEq, Copy, Clone)]
+use crate::tlv::DecodeError;
+
+use super::MAX_CERTIFICATE_BYTES;
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum VerifyError {
-    UnknownCAKey { rid: [u8; 5], index: u8 },
+    UnknownCAKey {
+        rid: [u8; 5],
+        index: u8,
+    },
     CertificateTooLarge(usize),
-    CertificateLengthMismatch { mod_size: usize, cert_size: usize },
-    InvalidSignature,
+    CertificateLengthMismatch {
+        mod_size: usize,
+        cert_size: usize,
+    },
+    /// The hash in the recovered data, `expected`, is not the hash of the signed data, `actual`
+    HashMismatch {
+        expected: Vec<u8>,
+        actual: Vec<u8>,
+    },
+    /// A fixed byte of the recovered data, such as the header or format, is wrong
+    BadCertificateFormat {
+        position: usize,
+        expected: u8,
+        actual: u8,
+    },
     InvalidData,
     MissingTag(u16),
     UnmatchedPAN,
+    /// A field in the recovered data failed to decode
+    Decode(DecodeError),
 }
 
 impl Display for VerifyError {
@@ -21,9 +43,27 @@ impl Display for VerifyError {
                 index
             ),
             VerifyError::CertificateTooLarge(size) => {
-                write!(f, "Certificate was {} bytes, max 248", size)
+                write!(
+                    f,
+                    "Certificate was {} bytes, max {}",
+                    size, MAX_CERTIFICATE_BYTES
+                )
             }
-            VerifyError::InvalidSignature => write!(f, "Signature was invalid"),
+            VerifyError::HashMismatch { expected, actual } => write!(
+                f,
+                "Signature was invalid, recovered hash is {} but data hashes to {}",
+                hex::encode(expected),
+                hex::encode(actual)
+            ),
+            VerifyError::BadCertificateFormat {
+                position,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Signature was invalid, recovered byte {} is {:#04x} instead of {:#04x}",
+                position, actual, expected
+            ),
             VerifyError::CertificateLengthMismatch {
                 mod_size,
                 cert_size,
@@ -39,8 +79,17 @@ impl Display for VerifyError {
             VerifyError::MissingTag(tag) => {
                 write!(f, "Processing Options missing tag {:#04x}", tag)
             }
+            VerifyError::Decode(err) => {
+                write!(f, "Signature validated, but failed to decode data: {}", err)
+            }
         }
     }
 }
 
 impl Error for VerifyError {}
+
+impl From<DecodeError> for VerifyError {
+    fn from(err: DecodeError) -> Self {
+        VerifyError::Decode(err)
+    }
+}
@@ -0,0 +1,121 @@
+use crypto_bigint::Encoding;
+use sha1::{Digest, Sha1};
+
+use crate::tlv::encoders::write_tl;
+
+use super::{
+    chain::{ICCPublicKey, IssuerPublicKey},
+    Modulus,
+};
+
+/// DER encoding of the rsaEncryption OID, 1.2.840.113549.1.1.1
+const RSA_ENCRYPTION_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+
+/// DER happens to use the same tag and length encoding as EMV for the single byte tags needed here
+fn write_der(tag: u8, value: &[u8], out: &mut Vec<u8>) {
+    write_tl(tag.into(), value.len(), out);
+    out.extend_from_slice(value);
+}
+
+/// Write a positive big-endian number as a DER INTEGER using as few bytes as possible
+fn write_der_uint(bytes: &[u8], out: &mut Vec<u8>) {
+    let skip = bytes.iter().take_while(|&&b| b == 0).count();
+    let mut value = Vec::with_capacity(bytes.len() - skip + 1);
+    // A set high bit would make the number negative
+    if bytes.get(skip).is_none_or(|&b| b & 0x80 != 0) {
+        value.push(0);
+    }
+    value.extend_from_slice(&bytes[skip..]);
+    write_der(0x02, &value, out);
+}
+
+/// Encode an RSA public key as an X.509 SubjectPublicKeyInfo (RFC 3279 section 2.3.1)
+pub fn spki_der(modulus: &Modulus, exponent: u32) -> Vec<u8> {
+    let mut rsa_key = Vec::new();
+    write_der_uint(&modulus.to_be_bytes(), &mut rsa_key);
+    write_der_uint(&exponent.to_be_bytes(), &mut rsa_key);
+
+    // The bit string starts with the number of unused bits in the last byte
+    let mut bit_string = vec![0];
+    write_der(0x30, &rsa_key, &mut bit_string);
+
+    let mut algorithm = Vec::new();
+    write_der(0x06, RSA_ENCRYPTION_OID, &mut algorithm);
+    write_der(0x05, &[], &mut algorithm);
+
+    let mut spki = Vec::new();
+    write_der(0x30, &algorithm, &mut spki);
+    write_der(0x03, &bit_string, &mut spki);
+
+    let mut out = Vec::new();
+    write_der(0x30, &spki, &mut out);
+    out
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64_ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Wrap a DER SubjectPublicKeyInfo in a `PUBLIC KEY` PEM block, as read by OpenSSL
+pub fn spki_pem(der: &[u8]) -> String {
+    let encoded = base64(der);
+    let mut pem = String::from("-----BEGIN PUBLIC KEY-----\n");
+    for line in encoded.as_bytes().chunks(64) {
+        pem.push_str(std::str::from_utf8(line).unwrap());
+        pem.push('\n');
+    }
+    pem.push_str("-----END PUBLIC KEY-----\n");
+    pem
+}
+
+/// SHA-1 of a DER SubjectPublicKeyInfo, the same as `openssl pkey -pubin -outform DER | sha1sum`
+pub fn spki_fingerprint(der: &[u8]) -> [u8; 20] {
+    Sha1::digest(der).into()
+}
+
+impl IssuerPublicKey {
+    pub fn to_spki_der(&self) -> Vec<u8> {
+        spki_der(&self.modulus, self.exponent)
+    }
+
+    pub fn fingerprint(&self) -> [u8; 20] {
+        spki_fingerprint(&self.to_spki_der())
+    }
+
+    pub fn to_spki_pem(&self) -> String {
+        spki_pem(&self.to_spki_der())
+    }
+}
+
+impl ICCPublicKey {
+    pub fn to_spki_der(&self) -> Vec<u8> {
+        spki_der(&self.modulus, self.exponent)
+    }
+
+    pub fn fingerprint(&self) -> [u8; 20] {
+        spki_fingerprint(&self.to_spki_der())
+    }
+
+    pub fn to_spki_pem(&self) -> String {
+        spki_pem(&self.to_spki_der())
+    }
+}
@@ -1,6 +1,22 @@
 pub mod ca_keys;
 pub mod chain;
+pub mod dynamic;
+pub mod encipher;
 pub mod errors;
+pub mod export;
+#[cfg(feature = "crypto-sym")]
+pub mod session;
+#[cfg(test)]
+mod tests;
 
 pub use self::ca_keys::*;
 pub use self::errors::VerifyError;
+
+/// Integer type used for RSA moduli and certificate recovery. Widening this (e.g. to `U4096`) is
+/// all that is needed to support larger keys.
+pub type Modulus = crypto_bigint::U2048;
+
+/// Longest certificate or signature EMV allows, since CA keys are at most 1984 bits (EMV 4.4 Book
+/// 2 section 5.1). This is separate from the width of [`Modulus`], which only has to be at least
+/// this big.
+pub const MAX_CERTIFICATE_BYTES: usize = 248;
@@ -0,0 +1,54 @@
+use des::{
+    cipher::{generic_array::GenericArray, BlockDecrypt, BlockEncrypt, KeyInit},
+    Des, TdesEde2,
+};
+
+/// Encrypt one block with double length 3DES, the DES3 function of EMV 4.4 Book 2 Annex A1.1
+fn des3(key: &[u8; 16], block: [u8; 8]) -> [u8; 8] {
+    let mut block = GenericArray::from(block);
+    TdesEde2::new(GenericArray::from_slice(key)).encrypt_block(&mut block);
+    block.into()
+}
+
+/// Derive an application cryptogram session key from the ICC master key and the Application
+/// Transaction Counter (0x9f36) with the Common Session Key derivation of EMV 4.4 Book 2 Annex
+/// A1.3. No parity adjustment is done, which DES ignores anyway.
+pub fn derive_session_key(mk: &[u8; 16], atc: &[u8; 2]) -> [u8; 16] {
+    let diversify = |branch| {
+        let mut r = [0; 8];
+        r[..2].copy_from_slice(atc);
+        r[2] = branch;
+        des3(mk, r)
+    };
+
+    let mut sk = [0; 16];
+    sk[..8].copy_from_slice(&diversify(0xf0));
+    sk[8..].copy_from_slice(&diversify(0x0f));
+    sk
+}
+
+/// MAC over `data` with the ISO 9797-1 MAC Algorithm 3 and padding method 2 used for application
+/// cryptograms, see EMV 4.4 Book 2 Annex A1.2. `data` is the concatenation of the CDOL1 values
+/// the cryptogram covers, usually [`crate::tlv::dol::Dol::encode`] output followed by the AIP
+/// and ATC.
+pub fn compute_arqc(sk: &[u8; 16], data: &[u8]) -> [u8; 8] {
+    let (left, right) = sk.split_at(8);
+    let left = Des::new(GenericArray::from_slice(left));
+    let right = Des::new(GenericArray::from_slice(right));
+
+    let mut padded = data.to_vec();
+    padded.push(0x80);
+    padded.resize(padded.len().div_ceil(8) * 8, 0);
+
+    // CBC with the left key, then decrypt and encrypt the final block for the 3DES strength
+    let mut mac = GenericArray::from([0; 8]);
+    for block in padded.chunks(8) {
+        for (mac_byte, data_byte) in mac.iter_mut().zip(block) {
+            *mac_byte ^= data_byte;
+        }
+        left.encrypt_block(&mut mac);
+    }
+    right.decrypt_block(&mut mac);
+    left.encrypt_block(&mut mac);
+    mac.into()
+}
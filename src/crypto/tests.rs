@@ -0,0 +1,237 @@
+use sha1::{Digest, Sha1};
+
+use super::*;
+
+#[test]
+fn test_certificate_to_bigint_max_size() {
+    let certificate = [0xffu8; MAX_CERTIFICATE_BYTES];
+    assert_eq!(
+        chain::certificate_to_bigint(&certificate),
+        Ok(Modulus::MAX >> (8 * (Modulus::BYTES - MAX_CERTIFICATE_BYTES)))
+    )
+}
+
+#[test]
+fn test_certificate_to_bigint_too_large() {
+    let certificate = [0x01u8; MAX_CERTIFICATE_BYTES + 1];
+    assert_eq!(
+        chain::certificate_to_bigint(&certificate),
+        Err(VerifyError::CertificateTooLarge(MAX_CERTIFICATE_BYTES + 1))
+    )
+}
+
+#[test]
+fn test_date_ym() {
+    assert_eq!(
+        chain::date_ym(&[0x12, 0x30]),
+        Ok(chrono::NaiveDate::from_ymd_opt(2030, 12, 31).unwrap())
+    );
+    // The bad digit is reported instead of a bare InvalidData
+    assert_eq!(
+        chain::date_ym(&[0x1a, 0x30]),
+        Err(VerifyError::Decode(crate::tlv::DecodeError::BadBcd(0xa)))
+    );
+    assert_eq!(chain::date_ym(&[0x13, 0x30]), Err(VerifyError::InvalidData));
+}
+
+#[test]
+fn test_check_bytes() {
+    let recovered = [0x6a, 0x02, 0x12, 0xbc];
+    assert_eq!(
+        chain::check_bytes(&recovered, &[(0, 0x6a), (3, 0xbc)]),
+        Ok(())
+    );
+    assert_eq!(
+        chain::check_bytes(&recovered, &[(0, 0x6a), (1, 0x04)]),
+        Err(VerifyError::BadCertificateFormat {
+            position: 1,
+            expected: 0x04,
+            actual: 0x02
+        })
+    );
+    assert_eq!(
+        VerifyError::HashMismatch {
+            expected: vec![0x01, 0x02],
+            actual: vec![0x03, 0x04]
+        }
+        .to_string(),
+        "Signature was invalid, recovered hash is 0102 but data hashes to 0304"
+    );
+}
+
+#[test]
+fn test_check_header() {
+    let mut recovered = vec![0; 26];
+    recovered[0] = chain::RECOVERED_DATA_HEADER;
+    recovered[1] = chain::FORMAT_STATIC_DATA;
+    recovered[25] = chain::RECOVERED_DATA_TRAILER;
+    assert_eq!(
+        chain::check_header(&recovered, chain::FORMAT_STATIC_DATA, 26),
+        Ok(())
+    );
+    assert_eq!(
+        chain::check_header(&recovered, chain::FORMAT_STATIC_DATA, 27),
+        Err(VerifyError::InvalidData)
+    );
+
+    recovered[25] = 0xbd;
+    assert_eq!(
+        chain::check_header(&recovered, chain::FORMAT_STATIC_DATA, 26),
+        Err(VerifyError::BadCertificateFormat {
+            position: 25,
+            expected: 0xbc,
+            actual: 0xbd
+        })
+    );
+}
+
+#[test]
+fn test_expiry_note() {
+    let date = |y, m, d| chrono::NaiveDate::from_ymd_opt(y, m, d).unwrap();
+    let today = date(2026, 10, 16);
+    assert_eq!(chain::expiry_note(date(2026, 9, 30), today), "EXPIRED");
+    assert_eq!(chain::expiry_note(today, today), "expires today");
+    assert_eq!(
+        chain::expiry_note(date(2026, 11, 30), today),
+        "expires in 45 days"
+    );
+}
+
+#[test]
+fn test_ca_keys_fit_modulus() {
+    for (id, key) in CA_KEYS.iter() {
+        assert!(
+            key.modulus.bits_vartime() <= Modulus::BITS,
+            "CA key {:?} does not fit",
+            id
+        );
+    }
+}
+
+// Throwaway 1024 bit key, only used to sign the test data below
+const TEST_MODULUS: &str = "991006189d609e9200085a43f5d9293facb935bf9fbf65f40b3dbcd43e181c98c5eee9ca17e9c4b502d16e8a777131a62b494654b4acf810f613cd5ccb9686a2c6630960cb6a4961e01206a29821b703e1705ac05f978035b0cc48b4af083eed06b87f8aa5694860aba3dc74844aa219291c7c2201eb67db9851898f213093f7";
+const TEST_DDA_SDAD: &str = "6a94f900db092a01bbce5f3fd4c0375c3b8fdd7ea9aabc43a0606b26a9c5245dd9ab090e983d9f368fa31c799c7f42a946100f23e9854e831fa27edc2582782143b9c910e53053752d1bdd615e189da76fb6df9e2f88264e1e330cbe009300f5d77c7db9a9545619f1e2bfc9cb223fcc2a6fc57908b07d9df508872525c1fa7d";
+const TEST_CDA_SDAD: &str = "18e8615881536b7f3badecdeaa962bdb89a62a8cd1634bd2f6ca88e3fca184622d57992816f45bd3eeceecb5c41b6bbd4440a1fa6cfa741b290241be1f3289bf6ae1c4198d8b795902989e950b16affc70093d6f3ca9d8be44b49e4ad93d7b59729ea9794e06aca6a7cbec30325e93cfe1bfe5186c8d25a76425dea6ad8b279a";
+const TEST_ENCIPHERED_PIN: &str = "13f7c9bbfc413e6650a725615ae6a0da8a9c1b32a9ac8727fb85adc6db9430108e58bbb6fb5bc9265cc0cb5e2a66c7693a6e12d5eebd0492f5ff9ec868d5685a8e2ff1bba01181361c53906e20a00ad5378414f9f8595885846f32729f03b015569aeb661174c2a63926692f09280f8b425f8bb4491d40034c369c6f5e10dd18";
+
+fn test_icc_key() -> chain::ICCPublicKey {
+    chain::ICCPublicKey {
+        pan: vec![4, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
+        expiry: chrono::NaiveDate::from_ymd_opt(2030, 12, 31).unwrap(),
+        serial_number: [0x00, 0x00, 0x01],
+        exponent: 65537,
+        modulus: Modulus::from_be_hex(&format!("{:0>1$}", TEST_MODULUS, Modulus::BYTES * 2)),
+    }
+}
+
+#[test]
+fn test_verify_dda() {
+    let sdad = hex::decode(TEST_DDA_SDAD).unwrap();
+    assert_eq!(
+        dynamic::verify_dda(&test_icc_key(), &sdad, &[0x01, 0x02, 0x03, 0x04]),
+        Ok(vec![0x11, 0x22, 0x33, 0x44])
+    );
+    assert!(matches!(
+        dynamic::verify_dda(&test_icc_key(), &sdad, &[0x01, 0x02, 0x03, 0x05]),
+        Err(VerifyError::HashMismatch { .. })
+    ));
+}
+
+#[test]
+fn test_verify_cda() {
+    let sdad = hex::decode(TEST_CDA_SDAD).unwrap();
+    let unpredictable_number = [0xde, 0xad, 0xbe, 0xef];
+    let transaction_data = [0x83, 0x00, 0x11, 0x22];
+    assert_eq!(
+        dynamic::verify_cda(
+            &test_icc_key(),
+            &sdad,
+            &unpredictable_number,
+            0x80,
+            &transaction_data
+        ),
+        Ok([0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08])
+    );
+    // Wrong CID
+    assert_eq!(
+        dynamic::verify_cda(
+            &test_icc_key(),
+            &sdad,
+            &unpredictable_number,
+            0x40,
+            &transaction_data
+        ),
+        Err(VerifyError::InvalidData)
+    );
+    // Transaction data doesn't match the hash
+    assert_eq!(
+        dynamic::verify_cda(
+            &test_icc_key(),
+            &sdad,
+            &unpredictable_number,
+            0x80,
+            &[0x83, 0x00]
+        ),
+        Err(VerifyError::HashMismatch {
+            expected: Sha1::digest(transaction_data).to_vec(),
+            actual: Sha1::digest([0x83, 0x00]).to_vec(),
+        })
+    );
+}
+
+#[test]
+fn test_encipher_pin() {
+    // Zero padding so the result is reproducible
+    let mut rng = rand::rngs::mock::StepRng::new(0, 0);
+    let pin_block = [0x24, 0x12, 0x34, 0xff, 0xff, 0xff, 0xff, 0xff];
+    let challenge = [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08];
+    assert_eq!(
+        encipher::encipher_pin(&test_icc_key(), &pin_block, &challenge, &mut rng),
+        Ok(hex::decode(TEST_ENCIPHERED_PIN).unwrap())
+    );
+}
+
+#[test]
+fn test_export_spki() {
+    // Generated with openssl rsa -RSAPublicKey_in -pubout from the test key
+    const TEST_SPKI: &str = "30819f300d06092a864886f70d010101050003818d0030818902818100991006189d609e9200085a43f5d9293facb935bf9fbf65f40b3dbcd43e181c98c5eee9ca17e9c4b502d16e8a777131a62b494654b4acf810f613cd5ccb9686a2c6630960cb6a4961e01206a29821b703e1705ac05f978035b0cc48b4af083eed06b87f8aa5694860aba3dc74844aa219291c7c2201eb67db9851898f213093f70203010001";
+    let der = test_icc_key().to_spki_der();
+    assert_eq!(hex::encode(&der), TEST_SPKI);
+
+    assert_eq!(
+        export::spki_pem(&der),
+        "-----BEGIN PUBLIC KEY-----\n\
+         MIGfMA0GCSqGSIb3DQEBAQUAA4GNADCBiQKBgQCZEAYYnWCekgAIWkP12Sk/rLk1\n\
+         v5+/ZfQLPbzUPhgcmMXu6coX6cS1AtFuindxMaYrSUZUtKz4EPYTzVzLloaixmMJ\n\
+         YMtqSWHgEgaimCG3A+FwWsBfl4A1sMxItK8IPu0GuH+KpWlIYKuj3HSESqIZKRx8\n\
+         IgHrZ9uYUYmPITCT9wIDAQAB\n\
+         -----END PUBLIC KEY-----\n"
+    );
+    assert_eq!(
+        hex::encode(test_icc_key().fingerprint()),
+        "e2bea0fc784ceca46102ea47229eb0ac920d50ac"
+    );
+}
+
+#[cfg(feature = "crypto-sym")]
+#[test]
+fn test_session_key_and_arqc() {
+    use super::session::{compute_arqc, derive_session_key};
+
+    let mk = hex::decode("0123456789abcdeffedcba9876543210").unwrap();
+    let sk = derive_session_key(mk[..].try_into().unwrap(), &[0x00, 0x01]);
+    assert_eq!(hex::encode(sk), "848c35717f66d40944f9286ff19abffd");
+
+    // Amount, other amount, country, TVR, currency, date, type, unpredictable number, AIP, ATC
+    let data =
+        hex::decode("000000001000000000000000082600000000000826230101001234567858000001").unwrap();
+    assert_eq!(hex::encode(compute_arqc(&sk, &data)), "127bed454f513f8b");
+    // Whole blocks still get a padding block
+    assert_eq!(
+        hex::encode(compute_arqc(
+            &sk,
+            &[0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77]
+        )),
+        "2602f1fc815d174b"
+    );
+}
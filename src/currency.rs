@@ -0,0 +1,216 @@
+use crate::tlv::{TagMap, Value};
+
+/// ISO 4217 numeric code, alphabetic code, minor unit exponent, and symbol
+const CURRENCIES: &[(u16, &str, u8, Option<&str>)] = &[
+    (36, "AUD", 2, Some("$")),
+    (48, "BHD", 3, None),
+    (124, "CAD", 2, Some("$")),
+    (156, "CNY", 2, Some("¥")),
+    (203, "CZK", 2, None),
+    (208, "DKK", 2, None),
+    (344, "HKD", 2, Some("$")),
+    (348, "HUF", 2, None),
+    (352, "ISK", 0, None),
+    (356, "INR", 2, Some("₹")),
+    (376, "ILS", 2, Some("₪")),
+    (392, "JPY", 0, Some("¥")),
+    (410, "KRW", 0, Some("₩")),
+    (414, "KWD", 3, None),
+    (458, "MYR", 2, None),
+    (484, "MXN", 2, Some("$")),
+    (554, "NZD", 2, Some("$")),
+    (578, "NOK", 2, None),
+    (634, "QAR", 2, None),
+    (643, "RUB", 2, Some("₽")),
+    (682, "SAR", 2, None),
+    (702, "SGD", 2, Some("$")),
+    (710, "ZAR", 2, None),
+    (752, "SEK", 2, None),
+    (756, "CHF", 2, None),
+    (764, "THB", 2, Some("฿")),
+    (784, "AED", 2, None),
+    (826, "GBP", 2, Some("£")),
+    (840, "USD", 2, Some("$")),
+    (901, "TWD", 2, Some("$")),
+    (933, "BYN", 2, None),
+    (946, "RON", 2, None),
+    (949, "TRY", 2, Some("₺")),
+    (978, "EUR", 2, Some("€")),
+    (985, "PLN", 2, None),
+    (986, "BRL", 2, Some("R$")),
+];
+
+/// Alphabetic code and minor unit exponent for an ISO 4217 numeric currency code
+pub fn currency_name(code: u16) -> Option<(&'static str, u8)> {
+    CURRENCIES
+        .iter()
+        .find(|(numeric, _, _, _)| *numeric == code)
+        .map(|&(_, alpha, exponent, _)| (alpha, exponent))
+}
+
+/// Decimal places assumed for amounts when there is no currency exponent
+pub const DEFAULT_EXPONENT: u8 = 2;
+
+/// The currency code and exponent that apply to amounts in `fields`: the Transaction Currency
+/// Code (0x5f2a) and Exponent (0x5f36), or with `reference` the Transaction Reference Currency
+/// Code (0x9f3c) and Exponent (0x9f3d). The exponent is [`DEFAULT_EXPONENT`] if it is missing or
+/// not a single digit.
+pub fn amount_currency(fields: &impl TagMap, reference: bool) -> (Option<u16>, u8) {
+    let (currency_tag, exponent_tag) = if reference {
+        (0x9f3c, 0x9f3d)
+    } else {
+        (0x5f2a, 0x5f36)
+    };
+    let currency = fields
+        .get_tag(currency_tag)
+        .and_then(Value::as_numeric)
+        .and_then(|&code| code.try_into().ok());
+    // The exponent is n1, which is the same in BCD and binary
+    let exponent = match fields.get_tag(exponent_tag) {
+        Some(Value::Numeric(exponent)) => (*exponent).try_into().ok(),
+        Some(Value::Binary(exponent)) => match exponent[..] {
+            [exponent] => Some(exponent),
+            _ => None,
+        },
+        _ => None,
+    };
+    (
+        currency,
+        exponent
+            .filter(|&exponent| exponent <= 9)
+            .unwrap_or(DEFAULT_EXPONENT),
+    )
+}
+
+/// Largest exponent whose divisor fits in a `u128`
+const MAX_EXPONENT: u8 = 38;
+
+/// Format an amount in minor units with `exponent` digits after the decimal point, e.g. `12.34`.
+/// Exponents over 38 are treated as 38, which no real currency comes close to.
+pub fn format_minor_units(minor_units: u128, exponent: u8) -> String {
+    let exponent = exponent.min(MAX_EXPONENT);
+    let divisor = 10u128.pow(exponent as u32);
+    if exponent == 0 {
+        minor_units.to_string()
+    } else {
+        format!(
+            "{}.{:0width$}",
+            minor_units / divisor,
+            minor_units % divisor,
+            width = exponent as usize
+        )
+    }
+}
+
+/// Format an amount in the minor unit of `currency` (an ISO 4217 numeric code) with `exponent`
+/// digits after the decimal point, e.g. `$12.34 USD`
+pub fn format_amount(minor_units: u128, currency: u16, exponent: u8) -> String {
+    let number = format_minor_units(minor_units, exponent);
+    match CURRENCIES.iter().find(|(code, _, _, _)| *code == currency) {
+        Some((_, alpha, _, Some(symbol))) => format!("{}{} {}", symbol, number, alpha),
+        Some((_, alpha, _, None)) => format!("{} {}", number, alpha),
+        None => format!("{} (currency {:03})", number, currency),
+    }
+}
+
+/// ISO 3166 numeric code and short name
+const COUNTRIES: &[(u16, &str)] = &[
+    (36, "Australia"),
+    (40, "Austria"),
+    (56, "Belgium"),
+    (76, "Brazil"),
+    (124, "Canada"),
+    (156, "China"),
+    (191, "Croatia"),
+    (203, "Czechia"),
+    (208, "Denmark"),
+    (233, "Estonia"),
+    (246, "Finland"),
+    (250, "France"),
+    (276, "Germany"),
+    (300, "Greece"),
+    (344, "Hong Kong"),
+    (348, "Hungary"),
+    (352, "Iceland"),
+    (356, "India"),
+    (372, "Ireland"),
+    (376, "Israel"),
+    (380, "Italy"),
+    (392, "Japan"),
+    (410, "South Korea"),
+    (428, "Latvia"),
+    (440, "Lithuania"),
+    (442, "Luxembourg"),
+    (458, "Malaysia"),
+    (484, "Mexico"),
+    (528, "Netherlands"),
+    (554, "New Zealand"),
+    (578, "Norway"),
+    (616, "Poland"),
+    (620, "Portugal"),
+    (642, "Romania"),
+    (643, "Russia"),
+    (682, "Saudi Arabia"),
+    (702, "Singapore"),
+    (703, "Slovakia"),
+    (705, "Slovenia"),
+    (710, "South Africa"),
+    (724, "Spain"),
+    (752, "Sweden"),
+    (756, "Switzerland"),
+    (764, "Thailand"),
+    (784, "United Arab Emirates"),
+    (792, "Turkey"),
+    (804, "Ukraine"),
+    (826, "United Kingdom"),
+    (840, "United States"),
+];
+
+/// Short name for an ISO 3166 numeric country code
+pub fn country_name(code: u16) -> Option<&'static str> {
+    COUNTRIES
+        .iter()
+        .find(|(numeric, _)| *numeric == code)
+        .map(|&(_, name)| name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_amount() {
+        assert_eq!(format_amount(1234, 840, 2), "$12.34 USD");
+        assert_eq!(format_amount(5, 978, 2), "€0.05 EUR");
+        assert_eq!(format_amount(500, 392, 0), "¥500 JPY");
+        assert_eq!(format_amount(1000, 756, 2), "10.00 CHF");
+        assert_eq!(format_amount(1234, 1, 3), "1.234 (currency 001)");
+        // A card can claim any exponent, which mustn't overflow
+        assert_eq!(
+            format_minor_units(5, 0xff),
+            format!("0.{}5", "0".repeat(37))
+        );
+    }
+
+    #[test]
+    fn test_amount_currency() {
+        let mut fields = crate::tlv::FieldMap::new();
+        assert_eq!(amount_currency(&fields, false), (None, 2));
+        fields.insert(0x5f2a, Value::Numeric(414));
+        fields.insert(0x5f36, Value::Binary(vec![3]));
+        fields.insert(0x9f3d, Value::Binary(vec![0x0a]));
+        assert_eq!(amount_currency(&fields, false), (Some(414), 3));
+        // Not a digit, so the default is used
+        assert_eq!(amount_currency(&fields, true), (None, 2));
+        assert_eq!(format_minor_units(1234, 2), "12.34");
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(currency_name(840), Some(("USD", 2)));
+        assert_eq!(currency_name(414), Some(("KWD", 3)));
+        assert_eq!(currency_name(1), None);
+        assert_eq!(country_name(826), Some("United Kingdom"));
+        assert_eq!(country_name(1), None);
+    }
+}
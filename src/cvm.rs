@@ -0,0 +1,184 @@
+use std::fmt::Display;
+
+use crate::tlv::DecodeError;
+
+/// Cardholder verification method from the first byte of a CV Rule in the CVM List (0x8e) or of
+/// the CVM Results (0x9f34), see EMV 4.4 Book 3 Annex C3
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CvmMethod {
+    /// Fail CVM processing
+    Fail,
+    PlaintextPin,
+    EncipheredPinOnline,
+    PlaintextPinAndSignature,
+    EncipheredPin,
+    EncipheredPinAndSignature,
+    Signature,
+    NoCvmRequired,
+    /// Only used in the CVM Results, when no CVM was performed
+    NotAvailable,
+    /// Reserved or payment system specific method
+    Other(u8),
+}
+
+impl CvmMethod {
+    /// The method in the low 6 bits of `code`, ignoring the "apply succeeding CV rule" bit
+    pub fn from_code(code: u8) -> CvmMethod {
+        match code & 0x3f {
+            0x00 => CvmMethod::Fail,
+            0x01 => CvmMethod::PlaintextPin,
+            0x02 => CvmMethod::EncipheredPinOnline,
+            0x03 => CvmMethod::PlaintextPinAndSignature,
+            0x04 => CvmMethod::EncipheredPin,
+            0x05 => CvmMethod::EncipheredPinAndSignature,
+            0x1e => CvmMethod::Signature,
+            0x1f => CvmMethod::NoCvmRequired,
+            0x3f => CvmMethod::NotAvailable,
+            other => CvmMethod::Other(other),
+        }
+    }
+}
+
+impl Display for CvmMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CvmMethod::Fail => write!(f, "fail CVM processing"),
+            CvmMethod::PlaintextPin => write!(f, "plaintext PIN verified by ICC"),
+            CvmMethod::EncipheredPinOnline => write!(f, "enciphered PIN verified online"),
+            CvmMethod::PlaintextPinAndSignature => {
+                write!(f, "plaintext PIN verified by ICC and signature")
+            }
+            CvmMethod::EncipheredPin => write!(f, "enciphered PIN verified by ICC"),
+            CvmMethod::EncipheredPinAndSignature => {
+                write!(f, "enciphered PIN verified by ICC and signature")
+            }
+            CvmMethod::Signature => write!(f, "signature"),
+            CvmMethod::NoCvmRequired => write!(f, "no CVM required"),
+            CvmMethod::NotAvailable => write!(f, "no CVM performed"),
+            CvmMethod::Other(code) => write!(f, "CVM {:02x}", code),
+        }
+    }
+}
+
+/// When a CV Rule applies, from its second byte
+pub fn condition_description(condition: u8) -> &'static str {
+    match condition {
+        0x00 => "always",
+        0x01 => "if unattended cash",
+        0x02 => "if not unattended cash, manual cash, or purchase with cashback",
+        0x03 => "if terminal supports the CVM",
+        0x04 => "if manual cash",
+        0x05 => "if purchase with cashback",
+        0x06 => "if under X in application currency",
+        0x07 => "if over X in application currency",
+        0x08 => "if under Y in application currency",
+        0x09 => "if over Y in application currency",
+        _ => "reserved condition",
+    }
+}
+
+/// Outcome of the CVM in the last byte of the CVM Results
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CvmOutcome {
+    /// Used for signature, where the terminal can't know whether it matched
+    Unknown,
+    Failed,
+    Successful,
+    Reserved(u8),
+}
+
+impl From<u8> for CvmOutcome {
+    fn from(value: u8) -> Self {
+        match value {
+            0x00 => CvmOutcome::Unknown,
+            0x01 => CvmOutcome::Failed,
+            0x02 => CvmOutcome::Successful,
+            other => CvmOutcome::Reserved(other),
+        }
+    }
+}
+
+impl Display for CvmOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CvmOutcome::Unknown => write!(f, "unknown"),
+            CvmOutcome::Failed => write!(f, "failed"),
+            CvmOutcome::Successful => write!(f, "successful"),
+            CvmOutcome::Reserved(value) => write!(f, "reserved result {:02x}", value),
+        }
+    }
+}
+
+/// Cardholder Verification Method Results (0x9f34): the last CVM the terminal performed, see EMV
+/// 4.4 Book 4 Annex A4
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CvmResults {
+    pub method: CvmMethod,
+    /// Raw condition code, see [`condition_description`]
+    pub condition: u8,
+    pub outcome: CvmOutcome,
+}
+
+impl TryFrom<&[u8]> for CvmResults {
+    type Error = DecodeError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let &[method, condition, outcome] = value else {
+            return Err(DecodeError::WrongType(0x9f34, "3 byte CVM Results"));
+        };
+        Ok(CvmResults {
+            method: CvmMethod::from_code(method),
+            condition,
+            outcome: outcome.into(),
+        })
+    }
+}
+
+impl Display for CvmResults {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}, {}",
+            self.method,
+            condition_description(self.condition),
+            self.outcome
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cvm_results() {
+        let results = CvmResults::try_from(&[0x1e, 0x03, 0x00][..]).unwrap();
+        assert_eq!(
+            results,
+            CvmResults {
+                method: CvmMethod::Signature,
+                condition: 0x03,
+                outcome: CvmOutcome::Unknown,
+            }
+        );
+        assert_eq!(
+            results.to_string(),
+            "signature if terminal supports the CVM, unknown"
+        );
+
+        // The "apply succeeding CV rule" bit doesn't change the method
+        assert_eq!(
+            CvmResults::try_from(&[0x42, 0x00, 0x02][..])
+                .unwrap()
+                .to_string(),
+            "enciphered PIN verified online always, successful"
+        );
+        assert_eq!(
+            CvmResults::try_from(&[0x3f, 0x00, 0x01][..])
+                .unwrap()
+                .method,
+            CvmMethod::NotAvailable
+        );
+        assert!(CvmResults::try_from(&[0x1f, 0x00][..]).is_err());
+    }
+}
@@ -0,0 +1,157 @@
+use std::path::Path;
+
+use anyhow::Context;
+use crypto_bigint::Encoding;
+use log::warn;
+use serde::Serialize;
+
+use crate::{
+    afl::Afl,
+    crypto::{
+        chain::{ICCPublicKey, IssuerPublicKey},
+        Modulus,
+    },
+    exchange::CardTransport,
+    pan::mask_pan_in,
+    processing_options::{self, ProcessingResult, Record},
+    pse::PSEData,
+    tlv::{serialize::serialize_field_map, FieldMap, FieldMapExt, OptionsMap, Value},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PublicKeyDump {
+    pub expiry: String,
+    #[serde(with = "hex::serde")]
+    pub serial_number: [u8; 3],
+    pub exponent: u32,
+    #[serde(serialize_with = "serialize_modulus")]
+    pub modulus: Modulus,
+}
+
+impl From<&IssuerPublicKey> for PublicKeyDump {
+    fn from(key: &IssuerPublicKey) -> Self {
+        Self {
+            expiry: key.expiry.format("%Y-%m").to_string(),
+            serial_number: key.serial_number,
+            exponent: key.exponent,
+            modulus: key.modulus,
+        }
+    }
+}
+
+impl From<&ICCPublicKey> for PublicKeyDump {
+    fn from(key: &ICCPublicKey) -> Self {
+        Self {
+            expiry: key.expiry.format("%Y-%m").to_string(),
+            serial_number: key.serial_number,
+            exponent: key.exponent,
+            modulus: key.modulus,
+        }
+    }
+}
+
+fn serialize_modulus<S: serde::Serializer>(
+    modulus: &Modulus,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&hex::encode(modulus.to_be_bytes()))
+}
+
+/// Everything we can read from a card without running a transaction
+#[derive(Debug, Clone, Serialize)]
+pub struct CardDump {
+    pub pse: Option<PSEData>,
+    #[serde(with = "hex::serde")]
+    pub aid: Vec<u8>,
+    pub afl: Option<Afl>,
+    #[serde(serialize_with = "serialize_field_map")]
+    pub records: FieldMap,
+    /// Every record exactly as the card returned it
+    pub raw_records: Vec<Record>,
+    /// Signed static data from the records marked in the AFL
+    #[serde(with = "hex::serde")]
+    pub sda_data: Vec<u8>,
+    pub issuer_key: Option<PublicKeyDump>,
+    pub icc_key: Option<PublicKeyDump>,
+}
+
+/// Mask `pan` in every string in `json`, which catches it in the hex of raw records too
+fn mask_json_strings(json: &mut serde_json::Value, pan: &[u8]) {
+    match json {
+        serde_json::Value::String(s) => *s = mask_pan_in(s, pan),
+        serde_json::Value::Array(values) => values
+            .iter_mut()
+            .for_each(|value| mask_json_strings(value, pan)),
+        serde_json::Value::Object(map) => map
+            .values_mut()
+            .for_each(|value| mask_json_strings(value, pan)),
+        _ => {}
+    }
+}
+
+impl CardDump {
+    /// Save the dump as JSON. The PAN is masked everywhere it appears unless `show_pan` is set,
+    /// in which case the file should be handled as carefully as the card.
+    pub fn save(&self, path: &Path, show_pan: bool) -> anyhow::Result<()> {
+        let mut json = serde_json::to_value(self).context("Failed to serialize card dump")?;
+        if show_pan {
+            json["records"] =
+                serde_json::to_value(FieldMapExt::serialize(&self.records).show_pan(true))
+                    .context("Failed to serialize card dump")?;
+        } else if let Some(pan) = self.records.get(&0x5a).and_then(Value::as_digit_string) {
+            mask_json_strings(&mut json, pan);
+        }
+
+        let file = std::fs::File::create(path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        serde_json::to_writer_pretty(file, &json).context("Failed to write card dump")?;
+        Ok(())
+    }
+}
+
+/// Select the application `aid` and read every record in its AFL. `pse_data` is only included in
+/// the dump. Failing to recover the keys is not an error since not every card supports offline
+/// data authentication.
+pub fn dump_card<C: CardTransport + ?Sized>(
+    card: &mut C,
+    pse_data: Option<PSEData>,
+    aid: Vec<u8>,
+    state: &OptionsMap,
+) -> anyhow::Result<CardDump> {
+    let ProcessingResult {
+        fields: records,
+        sda_data,
+        records: raw_records,
+    } = processing_options::read_processing_options(card, &aid, state)?;
+
+    let issuer_key = match aid.get(..5) {
+        Some(rid) => IssuerPublicKey::from_options(rid.try_into().unwrap(), &records)
+            .map_err(|err| warn!("Failed to recover issuer key: {}", err))
+            .ok(),
+        None => {
+            warn!("AID too short to contain a RID");
+            None
+        }
+    };
+    let icc_key = issuer_key.as_ref().and_then(|issuer_key| {
+        ICCPublicKey::from_options(issuer_key, &sda_data, &records)
+            .map_err(|err| warn!("Failed to recover ICC key: {}", err))
+            .ok()
+    });
+
+    let afl = records
+        .get(&0x94)
+        .and_then(Value::as_binary)
+        .and_then(|afl| Afl::parse(afl).ok());
+
+    Ok(CardDump {
+        pse: pse_data,
+        aid,
+        afl,
+        records,
+        raw_records,
+        sda_data,
+        issuer_key: issuer_key.as_ref().map(PublicKeyDump::from),
+        icc_key: icc_key.as_ref().map(PublicKeyDump::from),
+    })
+}
@@ -1,7 +1,78 @@
-use anyhow::Context;
-use log::trace;
+use std::{
+    error::Error,
+    fmt::Display,
+    sync::mpsc::{self, RecvTimeoutError},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
 
+use log::{debug, log_enabled, trace, warn, Level};
+
+/// Cryptogram type requested by GENERATE AC, see EMV 4.3 Book 3 section 6.5.5
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum AcType {
+    /// Application Authentication Cryptogram, decline
+    Aac,
+    /// Transaction Certificate, approve offline
+    Tc,
+    /// Authorisation Request Cryptogram, go online
+    Arqc,
+}
+
+impl AcType {
+    fn reference_control(self) -> u8 {
+        match self {
+            AcType::Aac => 0x00,
+            AcType::Tc => 0x40,
+            AcType::Arqc => 0x80,
+        }
+    }
+}
+
+/// Which of the applications matching a partial AID SELECT returns, in the low bits of P2. See
+/// EMV 4.4 Book 1 section 11.3.2.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum SelectOccurrence {
+    First,
+    /// The next application after the one that was last selected with the same name
+    Next,
+}
+
+impl SelectOccurrence {
+    fn p2(self) -> u8 {
+        match self {
+            SelectOccurrence::First => 0x00,
+            SelectOccurrence::Next => 0x02,
+        }
+    }
+}
+
+/// What SELECT returns about the selected file, in bits 3-4 of P2 (ISO 7816-4 section 11.2.2).
+/// EMV cards are only required to support the FCI.
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum SelectResponse {
+    /// File Control Information template (0x6f)
+    Fci,
+    /// File Control Parameters template (0x62)
+    Fcp,
+    /// File Management Data template (0x64)
+    Fmd,
+    /// No response data
+    None,
+}
+
+impl SelectResponse {
+    fn p2(self) -> u8 {
+        match self {
+            SelectResponse::Fci => 0x00,
+            SelectResponse::Fcp => 0x04,
+            SelectResponse::Fmd => 0x08,
+            SelectResponse::None => 0x0c,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
 pub struct ADPUCommand<'a> {
     /// Command class
     pub cla: u8,
@@ -13,53 +84,208 @@ pub struct ADPUCommand<'a> {
     pub p2: u8,
     /// Command data
     pub data: &'a [u8],
-    /// Number of bytes expected for response, between 0 and 65536 inclusive
-    pub ne: u32,
+    /// Number of bytes expected for response, between 1 and 65536 inclusive, or `None` to leave out
+    /// Le for commands with no response data
+    pub ne: Option<u32>,
+}
+
+/// Builds an [`ADPUCommand`] field by field, for commands without a constructor. Fields that
+/// aren't set are 0, with no data and no Le.
+///
+/// ```
+/// use emvsign::exchange::ADPUCommand;
+///
+/// // GET DATA for the PIN Try Counter with a one byte Le
+/// let command = ADPUCommand::builder()
+///     .cla(0x80)
+///     .ins(0xca)
+///     .p1(0x9f)
+///     .p2(0x17)
+///     .ne(0x100)
+///     .build();
+/// assert_eq!(command, ADPUCommand::get_data(0x9f17));
+/// assert_eq!(&*command.encode().unwrap(), &[0x80, 0xca, 0x9f, 0x17, 0x00]);
+/// ```
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct ADPUCommandBuilder<'a> {
+    command: ADPUCommand<'a>,
+}
+
+impl<'a> ADPUCommandBuilder<'a> {
+    pub fn new() -> ADPUCommandBuilder<'a> {
+        Self::default()
+    }
+
+    pub fn cla(mut self, cla: u8) -> Self {
+        self.command.cla = cla;
+        self
+    }
+
+    pub fn ins(mut self, ins: u8) -> Self {
+        self.command.ins = ins;
+        self
+    }
+
+    pub fn p1(mut self, p1: u8) -> Self {
+        self.command.p1 = p1;
+        self
+    }
+
+    pub fn p2(mut self, p2: u8) -> Self {
+        self.command.p2 = p2;
+        self
+    }
+
+    pub fn data(mut self, data: &'a [u8]) -> Self {
+        self.command.data = data;
+        self
+    }
+
+    /// Number of bytes expected in the response, where 0 leaves out Le
+    pub fn ne(mut self, ne: u32) -> Self {
+        self.command.ne = (ne != 0).then_some(ne);
+        self
+    }
+
+    pub fn build(self) -> ADPUCommand<'a> {
+        self.command
+    }
+}
+
+/// Ne for a one byte Le, where 0 means 256
+fn short_ne(le: u8) -> u32 {
+    if le == 0 {
+        0x100
+    } else {
+        le as u32
+    }
 }
 
 impl ADPUCommand<'_> {
+    /// Start building a custom command, see [`ADPUCommandBuilder`]
+    pub fn builder<'a>() -> ADPUCommandBuilder<'a> {
+        ADPUCommandBuilder::new()
+    }
+
     pub fn encode(&self) -> Option<Box<[u8]>> {
         let mut raw = Vec::with_capacity(10 + self.data.len());
         raw.extend_from_slice(&[self.cla, self.ins, self.p1, self.p2]);
 
         let nc = self.data.len();
+        let ne = self.ne.unwrap_or(0);
+        if nc > 65535 || ne > 65536 || self.ne == Some(0) {
+            // Impossible to encode over 65535 bytes of data or 65536 bytes of response, and Le
+            // can't ask for nothing since 0 means the maximum
+            return None;
+        }
+        // If either field needs to be extended both must be (ISO 7816-4 section 5.1)
+        let extended = nc > 255 || ne > 256;
+
         if nc == 0 {
             // Do nothing, Lc is empty
-        } else if nc <= 255 {
+        } else if !extended {
             raw.push(nc as u8);
-        } else if nc <= 65535 {
+        } else {
             raw.push(0u8);
             raw.extend_from_slice(&(nc as u16).to_be_bytes());
-        } else {
-            // Impossible to encode over 65536 bytes
-            return None;
         }
         raw.extend_from_slice(self.data);
 
-        if self.ne == 0 {
+        if self.ne.is_none() {
             // Do nothing, Le is empty
-        } else if self.ne <= 256 {
+        } else if !extended {
             // 256 will be 0x100 which we truncate to 0x00. This is correct.
-            raw.push(self.ne as u8);
-        } else if self.ne <= 65536 {
-            // 65536 will be 0x10000 which we truncate to 0x0000. This is correct.
-            if nc <= 255 {
+            raw.push(ne as u8);
+        } else {
+            // Extended Le is only prefixed with 0x00 if there was no Lc to do so
+            if nc == 0 {
                 raw.push(0u8);
             }
-            raw.extend_from_slice(&(self.ne as u16).to_be_bytes());
+            // 65536 will be 0x10000 which we truncate to 0x0000. This is correct.
+            raw.extend_from_slice(&(ne as u16).to_be_bytes());
         }
 
         Some(raw.into_boxed_slice())
     }
 
-    pub fn select(aid: &[u8]) -> ADPUCommand {
+    /// Parse an encoded command, the inverse of [`ADPUCommand::encode`]. Returns `None` if the
+    /// lengths don't match any of the cases in ISO 7816-4 section 5.1.
+    pub fn decode(raw: &[u8]) -> Option<ADPUCommand<'_>> {
+        let (&[cla, ins, p1, p2], body) = raw.split_first_chunk()?;
+        let extended_ne = |le: &[u8]| match u16::from_be_bytes([le[0], le[1]]) {
+            0 => Some(0x10000),
+            le => Some(le as u32),
+        };
+
+        let (data, ne) = match *body {
+            // Case 1
+            [] => (&[][..], None),
+            // Case 2S
+            [le] => (&[][..], Some(short_ne(le))),
+            // Case 2E
+            [0, le1, le2] => (&[][..], extended_ne(&[le1, le2])),
+            // Cases 3E and 4E
+            [0, lc1, lc2, ref rest @ ..] => {
+                let nc = u16::from_be_bytes([lc1, lc2]) as usize;
+                if nc == 0 {
+                    return None;
+                }
+                match rest.len().checked_sub(nc)? {
+                    0 => (rest, None),
+                    2 => (&rest[..nc], extended_ne(&rest[nc..])),
+                    _ => return None,
+                }
+            }
+            // Cases 3S and 4S
+            [lc, ref rest @ ..] => {
+                let nc = lc as usize;
+                if nc == 0 {
+                    return None;
+                }
+                match rest.len().checked_sub(nc)? {
+                    0 => (rest, None),
+                    1 => (&rest[..nc], Some(short_ne(rest[nc]))),
+                    _ => return None,
+                }
+            }
+        };
+
+        Some(ADPUCommand {
+            cla,
+            ins,
+            p1,
+            p2,
+            data,
+            ne,
+        })
+    }
+
+    /// SELECT the first application matching `aid` and return its FCI
+    pub fn select(aid: &[u8]) -> ADPUCommand<'_> {
+        Self::select_with(aid, SelectOccurrence::First)
+    }
+
+    /// SELECT an application whose AID starts with `aid` and return its FCI. Repeating this with
+    /// [`SelectOccurrence::Next`] goes through every application matching a partial AID.
+    pub fn select_with(aid: &[u8], occurrence: SelectOccurrence) -> ADPUCommand<'_> {
+        Self::select_with_response(aid, occurrence, SelectResponse::Fci)
+    }
+
+    pub fn select_with_response(
+        aid: &[u8],
+        occurrence: SelectOccurrence,
+        response: SelectResponse,
+    ) -> ADPUCommand<'_> {
         ADPUCommand {
-            cla: 0x00, // Interindustry command
-            ins: 0xa4, // SELECT
-            p1: 0x04,  // Select by name
-            p2: 0x00,  // 1st element
-            data: aid, // AID
-            ne: 0x100, // 256 bytes, the card will correct us
+            cla: 0x00,                           // Interindustry command
+            ins: 0xa4,                           // SELECT
+            p1: 0x04,                            // Select by name
+            p2: occurrence.p2() | response.p2(), // Which application and what to return
+            data: aid,                           // AID
+            ne: match response {
+                SelectResponse::None => None, // Nothing to return
+                _ => Some(0x100),             // 256 bytes, the card will correct us
+            },
         }
     }
 
@@ -70,72 +296,434 @@ impl ADPUCommand<'_> {
             p1: record,            // Record number
             p2: (sfi << 3) | 0x04, // SFI, P1 is a record number
             data: &[],             // No data
-            ne: 0x100,             // 256 bytes, the card will correct us
+            ne: Some(0x100),       // 256 bytes, the card will correct us
+        }
+    }
+
+    pub fn get_processing_options(pdol: &[u8]) -> ADPUCommand<'_> {
+        ADPUCommand {
+            cla: 0x80,       // Propriatery command
+            ins: 0xa8,       // GET PROCESSING OPTIONS
+            p1: 0x00,        // The only non-RFU value
+            p2: 0x00,        // The only non-RFU value
+            data: pdol,      // Processing Data Object List, may be empty
+            ne: Some(0x100), // 256 bytes, the card will correct us
+        }
+    }
+
+    pub fn internal_authenticate(ddol: &[u8]) -> ADPUCommand<'_> {
+        ADPUCommand {
+            cla: 0x00,       // Interindustry command
+            ins: 0x88,       // INTERNAL AUTHENTICATE
+            p1: 0x00,        // The only non-RFU value
+            p2: 0x00,        // The only non-RFU value
+            data: ddol,      // Dynamic Data Authentication Data Object List, may be empty
+            ne: Some(0x100), // 256 bytes, the card will correct us
+        }
+    }
+
+    pub fn get_data(tag: u16) -> ADPUCommand<'static> {
+        let [p1, p2] = tag.to_be_bytes();
+        ADPUCommand {
+            cla: 0x80,       // Propriatery command
+            ins: 0xca,       // GET DATA
+            p1,              // Tag, upper byte
+            p2,              // Tag, lower byte
+            data: &[],       // No data
+            ne: Some(0x100), // 256 bytes, the card will correct us
+        }
+    }
+
+    /// PUT DATA for the issuer to update a data object on the card, usually from an issuer
+    /// script. Cards require secure messaging, so `value` must end with the MAC.
+    pub fn put_data(tag: u16, value: &[u8]) -> ADPUCommand<'_> {
+        let [p1, p2] = tag.to_be_bytes();
+        ADPUCommand {
+            cla: 0x04,   // Interindustry command with secure messaging
+            ins: 0xda,   // PUT DATA
+            p1,          // Tag, upper byte
+            p2,          // Tag, lower byte
+            data: value, // New value followed by the MAC
+            ne: None,    // No response data
+        }
+    }
+
+    pub fn verify_plaintext_pin(pin_block: &[u8; 8]) -> ADPUCommand<'_> {
+        ADPUCommand {
+            cla: 0x00,       // Interindustry command
+            ins: 0x20,       // VERIFY
+            p1: 0x00,        // The only non-RFU value
+            p2: 0x80,        // Plaintext PIN
+            data: pin_block, // Plaintext PIN block
+            ne: None,        // No response data
+        }
+    }
+
+    /// Enciphered PIN from [`crate::crypto::encipher::encipher_pin`], as long as the key modulus
+    pub fn verify_enciphered_pin(enciphered_pin: &[u8]) -> ADPUCommand<'_> {
+        ADPUCommand {
+            cla: 0x00,            // Interindustry command
+            ins: 0x20,            // VERIFY
+            p1: 0x00,             // The only non-RFU value
+            p2: 0x88,             // Enciphered PIN
+            data: enciphered_pin, // PIN block enciphered with the ICC key
+            ne: None,             // No response data
         }
     }
 
-    pub fn get_processing_options(pdol: &[u8]) -> ADPUCommand {
+    pub fn get_challenge() -> ADPUCommand<'static> {
         ADPUCommand {
-            cla: 0x80,  // Propriatery command
-            ins: 0xa8,  // GET PROCESSING OPTIONS
-            p1: 0x00,   // The only non-RFU value
-            p2: 0x00,   // The only non-RFU value
-            data: pdol, // Processing Data Object List, may be empty
-            ne: 0x100,  // 256 bytes, the card will correct us
+            cla: 0x00,       // Interindustry command
+            ins: 0x84,       // GET CHALLENGE
+            p1: 0x00,        // The only non-RFU value
+            p2: 0x00,        // The only non-RFU value
+            data: &[],       // No data
+            ne: Some(0x100), // 256 bytes, the card will send an 8 byte unpredictable number
         }
     }
 
-    pub fn internal_authenticate(ddol: &[u8]) -> ADPUCommand {
+    pub fn generate_ac(ac_type: AcType, cda: bool, cdol: &[u8]) -> ADPUCommand<'_> {
         ADPUCommand {
-            cla: 0x00,  // Propriatery command
-            ins: 0x88,  // INTERNAL AUTHENTICATE
-            p1: 0x00,   // The only non-RFU value
-            p2: 0x00,   // The only non-RFU value
-            data: ddol, // Dynamic Data Authentication Data Object List, may be empty
-            ne: 0x100,  // 256 bytes, the card will correct us
+            cla: 0x80, // Propriatery command
+            ins: 0xae, // GENERATE APPLICATION CRYPTOGRAM
+            // Reference control parameter, bit 5 requests a CDA signature
+            p1: ac_type.reference_control() | if cda { 0x10 } else { 0x00 },
+            p2: 0x00,        // The only non-RFU value
+            data: cdol,      // Card Risk Management Data Object List data
+            ne: Some(0x100), // 256 bytes, the card will correct us
         }
     }
 }
 
-pub fn exchange(card: &mut pcsc::Card, command: &ADPUCommand) -> anyhow::Result<(Vec<u8>, u16)> {
-    let mut recieve_buffer = [0u8; 256];
-    let mut response = Vec::new();
-    let mut sw1;
-    let mut sw2;
-    let tx = card.transaction().context("Failed to create transaction")?;
-    {
-        let encoded = &command
-            .encode()
-            .ok_or_else(|| anyhow::anyhow!("Could not encode command"))?;
-        trace!("→ {}", hex::encode(encoded));
-        let data = tx
-            .transmit(encoded, &mut recieve_buffer)
-            .context("Failed to recieve from card")?;
-        if data.len() < 2 {
-            anyhow::bail!("Received message too short");
+/// Whether READ RECORD failed because the record doesn't exist (0x6a83). This is how the card
+/// says there are no more records in the file, so loops over records should stop instead of
+/// failing.
+pub fn is_record_not_found(sw: u16) -> bool {
+    sw == 0x6a83
+}
+
+/// Describe an ISO 7816-4 / EMV status word, see EMV 4.3 Book 3 section 6.3.5
+pub fn describe_sw(sw: u16) -> String {
+    let [sw1, sw2] = sw.to_be_bytes();
+    match (sw1, sw2) {
+        (0x90, 0x00) => "Success".to_string(),
+        (0x61, n) => format!("{} more bytes available", n),
+        (0x62, 0x81) => "Part of returned data may be corrupted".to_string(),
+        (0x62, 0x82) => "End of file reached before reading Le bytes".to_string(),
+        (0x62, 0x83) => "Selected file invalidated".to_string(),
+        (0x62, 0x84) => "FCI not formatted correctly".to_string(),
+        (0x62, _) => "Warning, non-volatile memory unchanged".to_string(),
+        (0x63, 0x00) => "Authentication failed".to_string(),
+        (0x63, n) if n & 0xf0 == 0xc0 => format!("Verification failed, {} tries left", n & 0x0f),
+        (0x63, _) => "Warning, non-volatile memory changed".to_string(),
+        (0x64, _) => "Execution error, non-volatile memory unchanged".to_string(),
+        (0x65, 0x81) => "Memory failure".to_string(),
+        (0x65, _) => "Execution error, non-volatile memory changed".to_string(),
+        (0x67, 0x00) => "Wrong length".to_string(),
+        (0x68, 0x81) => "Logical channel not supported".to_string(),
+        (0x68, 0x82) => "Secure messaging not supported".to_string(),
+        (0x68, _) => "Function in CLA not supported".to_string(),
+        (0x69, 0x81) => "Command incompatible with file structure".to_string(),
+        (0x69, 0x82) => "Security status not satisfied".to_string(),
+        (0x69, 0x83) => "Authentication method blocked".to_string(),
+        (0x69, 0x84) => "Referenced data invalidated".to_string(),
+        (0x69, 0x85) => "Conditions of use not satisfied".to_string(),
+        (0x69, 0x86) => "Command not allowed, no current EF".to_string(),
+        (0x69, _) => "Command not allowed".to_string(),
+        (0x6a, 0x80) => "Incorrect parameters in the data field".to_string(),
+        (0x6a, 0x81) => "Function not supported".to_string(),
+        (0x6a, 0x82) => "File or application not found".to_string(),
+        (0x6a, 0x83) => "Record not found".to_string(),
+        (0x6a, 0x84) => "Not enough memory space in the file".to_string(),
+        (0x6a, 0x86) => "Incorrect parameters P1-P2".to_string(),
+        (0x6a, 0x88) => "Referenced data not found".to_string(),
+        (0x6a, _) => "Wrong parameters P1-P2".to_string(),
+        (0x6b, 0x00) => "Wrong parameters P1-P2".to_string(),
+        (0x6c, n) => format!("Wrong Le, {} bytes available", n),
+        (0x6d, 0x00) => "Instruction code not supported or invalid".to_string(),
+        (0x6e, 0x00) => "Class not supported".to_string(),
+        (0x6f, 0x00) => "No precise diagnosis".to_string(),
+        _ => "Unknown status".to_string(),
+    }
+}
+
+/// Errors from talking to the card. These convert into [`anyhow::Error`], so callers that don't
+/// care about the details can still use `?`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExchangeError {
+    /// The reader failed to send the command or receive the response
+    Transmit(pcsc::Error),
+    /// A replayed transcript doesn't match the commands being sent
+    Transcript(String),
+    /// The response didn't contain a status word
+    ResponseTooShort(usize),
+    /// The card returned a status word other than 0x9000
+    StatusWord(u16),
+    /// The command couldn't be encoded, e.g. because it has too much data
+    Encode,
+    /// The card kept sending continuation data past [`MAX_RESPONSE_LEN`]
+    ResponseTooLong(usize),
+    /// The card or reader didn't answer within the [`TimeoutTransport`] timeout
+    Timeout(Duration),
+    /// The [`TimeoutTransport`] worker thread is gone because the card panicked
+    WorkerStopped,
+}
+
+impl ExchangeError {
+    /// Turn any status word other than 0x9000 into [`ExchangeError::StatusWord`]
+    pub fn check_sw(sw: u16) -> Result<(), ExchangeError> {
+        match sw {
+            0x9000 => Ok(()),
+            sw => Err(ExchangeError::StatusWord(sw)),
         }
+    }
+
+    /// Like [`ExchangeError::check_sw`] for commands that return data, except warnings (0x62xx
+    /// and 0x63xx) are logged and accepted as long as the card still returned `data`, unless
+    /// `fail_on_warning` is set. Pass [`CardTransport::fail_on_warning`] of the card.
+    pub fn check_data_sw(sw: u16, data: &[u8], fail_on_warning: bool) -> Result<(), ExchangeError> {
+        match sw >> 8 {
+            0x62 | 0x63 if !data.is_empty() && !fail_on_warning => {
+                warn!("Card returned warning 0x{:04x} ({})", sw, describe_sw(sw));
+                Ok(())
+            }
+            _ => Self::check_sw(sw),
+        }
+    }
+}
+
+impl Display for ExchangeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExchangeError::Transmit(err) => write!(f, "Failed to recieve from card: {}", err),
+            ExchangeError::Transcript(reason) => write!(f, "{}", reason),
+            ExchangeError::ResponseTooShort(len) => {
+                write!(f, "Received message too short, {} bytes", len)
+            }
+            ExchangeError::StatusWord(sw) => {
+                write!(f, "Card returned 0x{:04x} ({})", sw, describe_sw(*sw))
+            }
+            ExchangeError::Encode => write!(f, "Could not encode command"),
+            ExchangeError::ResponseTooLong(len) => write!(
+                f,
+                "Card sent {} bytes of response, more than the maximum of {}",
+                len, MAX_RESPONSE_LEN
+            ),
+            ExchangeError::Timeout(timeout) => write!(
+                f,
+                "Card did not respond within {:.1} seconds",
+                timeout.as_secs_f64()
+            ),
+            ExchangeError::WorkerStopped => write!(f, "Card thread stopped unexpectedly"),
+        }
+    }
+}
+
+impl Error for ExchangeError {}
+
+/// Something that can carry raw APDUs to a card, normally a [`pcsc::Card`]. Implement this to
+/// drive the card flows without hardware.
+pub trait CardTransport {
+    /// Send an encoded command, returning the raw response including the status word
+    fn transmit<'buf>(
+        &mut self,
+        command: &[u8],
+        recieve_buffer: &'buf mut [u8],
+    ) -> Result<&'buf [u8], ExchangeError>;
+
+    /// Whether warning status words (0x62xx and 0x63xx) should fail commands even if the card
+    /// returned data, see [`ExchangeError::check_data_sw`]
+    fn fail_on_warning(&self) -> bool {
+        false
+    }
+}
+
+impl CardTransport for pcsc::Card {
+    fn transmit<'buf>(
+        &mut self,
+        command: &[u8],
+        recieve_buffer: &'buf mut [u8],
+    ) -> Result<&'buf [u8], ExchangeError> {
+        // We connect in exclusive mode, so there is no need for a PCSC transaction
+        pcsc::Card::transmit(self, command, recieve_buffer).map_err(ExchangeError::Transmit)
+    }
+}
+
+/// Default for how long [`TimeoutTransport`] waits for each response
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+type TransmitResult = Result<Vec<u8>, ExchangeError>;
+
+/// Runs a [`CardTransport`] on its own thread so that a card or reader that stops responding
+/// returns [`ExchangeError::Timeout`] instead of hanging forever. PC/SC has no way to cancel a
+/// transmit, so after a timeout the worker thread is abandoned and every later command fails.
+pub struct TimeoutTransport<C> {
+    /// Closing this stops the worker, which then returns the card
+    requests: Option<mpsc::Sender<(Vec<u8>, usize)>>,
+    responses: mpsc::Receiver<TransmitResult>,
+    worker: Option<JoinHandle<C>>,
+    timeout: Duration,
+    timed_out: bool,
+    fail_on_warning: bool,
+}
+
+impl<C: CardTransport + Send + 'static> TimeoutTransport<C> {
+    pub fn new(mut card: C, timeout: Duration) -> TimeoutTransport<C> {
+        let (requests, worker_requests) = mpsc::channel::<(Vec<u8>, usize)>();
+        let (worker_responses, responses) = mpsc::channel();
+        let worker = thread::spawn(move || {
+            for (command, buffer_len) in worker_requests {
+                let mut recieve_buffer = vec![0; buffer_len];
+                let response = card
+                    .transmit(&command, &mut recieve_buffer)
+                    .map(<[u8]>::to_vec);
+                if worker_responses.send(response).is_err() {
+                    break;
+                }
+            }
+            card
+        });
+        TimeoutTransport {
+            requests: Some(requests),
+            responses,
+            worker: Some(worker),
+            timeout,
+            timed_out: false,
+            fail_on_warning: false,
+        }
+    }
+}
+
+impl<C> TimeoutTransport<C> {
+    /// See [`CardTransport::fail_on_warning`]
+    pub fn set_fail_on_warning(&mut self, fail: bool) {
+        self.fail_on_warning = fail;
+    }
+
+    /// Stop the worker and return the card, or `None` if it timed out and may still be stuck
+    pub fn into_inner(mut self) -> Option<C> {
+        self.stop()
+    }
+
+    fn stop(&mut self) -> Option<C> {
+        if self.timed_out {
+            return None;
+        }
+        self.requests.take();
+        self.worker.take()?.join().ok()
+    }
+}
+
+impl<C> Drop for TimeoutTransport<C> {
+    /// Wait for the worker so the card is dropped before returning, unless it's stuck
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl<C> CardTransport for TimeoutTransport<C> {
+    fn transmit<'buf>(
+        &mut self,
+        command: &[u8],
+        recieve_buffer: &'buf mut [u8],
+    ) -> Result<&'buf [u8], ExchangeError> {
+        if self.timed_out {
+            return Err(ExchangeError::Timeout(self.timeout));
+        }
+        // The worker only stops when the card panics
+        self.requests
+            .as_ref()
+            .ok_or(ExchangeError::WorkerStopped)?
+            .send((command.to_vec(), recieve_buffer.len()))
+            .map_err(|_| ExchangeError::WorkerStopped)?;
+        let response = match self.responses.recv_timeout(self.timeout) {
+            Ok(response) => response?,
+            Err(RecvTimeoutError::Timeout) => {
+                self.timed_out = true;
+                return Err(ExchangeError::Timeout(self.timeout));
+            }
+            Err(RecvTimeoutError::Disconnected) => return Err(ExchangeError::WorkerStopped),
+        };
+        let received = &mut recieve_buffer[..response.len()];
+        received.copy_from_slice(&response);
+        Ok(received)
+    }
+
+    fn fail_on_warning(&self) -> bool {
+        self.fail_on_warning
+    }
+}
+
+/// Most response data accepted from one command, including GET RESPONSE continuations. This is
+/// the largest Ne an APDU can ask for, so a card that keeps sending 0x61xx is misbehaving.
+pub const MAX_RESPONSE_LEN: usize = 65536;
+
+/// Callback receiving every raw command and response (including the status word) exchanged with
+/// the card, e.g. to record a transcript
+pub type TraceFn<'a> = dyn FnMut(&[u8], &[u8]) + 'a;
+
+fn transmit<'buf, C: CardTransport + ?Sized>(
+    card: &mut C,
+    command: &[u8],
+    recieve_buffer: &'buf mut [u8],
+    trace: &mut TraceFn,
+) -> Result<(&'buf [u8], u8, u8), ExchangeError> {
+    if log_enabled!(Level::Trace) {
+        trace!("→ {}", hex::encode(command));
+    }
+    let data = card.transmit(command, recieve_buffer)?;
+    if log_enabled!(Level::Trace) {
         trace!("← {}", hex::encode(data));
-        sw1 = data[data.len() - 2];
-        sw2 = data[data.len() - 1];
-        response.extend_from_slice(&data[..(data.len() - 2)]);
     }
+    trace(command, data);
+
+    if data.len() < 2 {
+        return Err(ExchangeError::ResponseTooShort(data.len()));
+    }
+    let (body, sw) = data.split_at(data.len() - 2);
+    Ok((body, sw[0], sw[1]))
+}
+
+pub fn exchange<C: CardTransport + ?Sized>(
+    card: &mut C,
+    command: &ADPUCommand,
+) -> Result<(Vec<u8>, u16), ExchangeError> {
+    exchange_traced(card, command, &mut |_, _| {})
+}
+
+/// Like [`exchange`], but calls `trace` with every raw command and response sent over the wire
+pub fn exchange_traced<C: CardTransport + ?Sized>(
+    card: &mut C,
+    command: &ADPUCommand,
+    trace: &mut TraceFn,
+) -> Result<(Vec<u8>, u16), ExchangeError> {
+    // A full short response plus the status word. Each transmit reuses the buffer, so data is
+    // copied out into the response before the next one.
+    let mut recieve_buffer = [0u8; 256 + 2];
+    let mut response = Vec::new();
+
+    let encoded = command.encode().ok_or(ExchangeError::Encode)?;
+    let (data, mut sw1, mut sw2) = transmit(card, &encoded, &mut recieve_buffer, trace)?;
+    response.extend_from_slice(data);
 
     if sw1 == 0x6c {
+        // The wrong Le means nothing sent with it is usable, the retry returns all the data
+        if !response.is_empty() {
+            debug!(
+                "Discarding {} bytes sent with wrong Le status",
+                response.len()
+            );
+            response.clear();
+        }
+
         // Reduce data size requested
         let mut modified_command = *command;
-        modified_command.ne = sw2 as u32;
+        modified_command.ne = Some(short_ne(sw2));
 
-        let encoded = &modified_command
-            .encode()
-            .ok_or_else(|| anyhow::anyhow!("Could not encode command"))?;
-        trace!("→ {}", hex::encode(encoded));
-        let data = tx
-            .transmit(encoded, &mut recieve_buffer)
-            .context("Failed to recieve from card after reducing size")?;
-        trace!("← {}", hex::encode(data));
-        sw1 = data[data.len() - 2];
-        sw2 = data[data.len() - 1];
-        response.extend_from_slice(&data[..(data.len() - 2)]);
+        let encoded = modified_command.encode().ok_or(ExchangeError::Encode)?;
+        let data;
+        (data, sw1, sw2) = transmit(card, &encoded, &mut recieve_buffer, trace)?;
+        response.extend_from_slice(data);
     }
 
     while sw1 == 0x61 {
@@ -148,15 +736,460 @@ pub fn exchange(card: &mut pcsc::Card, command: &ADPUCommand) -> anyhow::Result<
             sw2,  // P3: Expected length
         ];
 
-        trace!("→ {}", hex::encode(continuation_command));
-        let data = tx
-            .transmit(&continuation_command, &mut recieve_buffer)
-            .context("Failed to recieve from card while requesting continuation data")?;
-        trace!("← {}", hex::encode(data));
-        sw1 = data[data.len() - 2];
-        sw2 = data[data.len() - 1];
-        response.extend_from_slice(&data[..(data.len() - 2)]);
+        let data;
+        (data, sw1, sw2) = transmit(card, &continuation_command, &mut recieve_buffer, trace)?;
+        response.extend_from_slice(data);
+        if response.len() > MAX_RESPONSE_LEN {
+            return Err(ExchangeError::ResponseTooLong(response.len()));
+        }
     }
 
     Ok((response, (sw1 as u16) << 8 | (sw2 as u16)))
 }
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::replay::{ReplayTransport, TranscriptEntry};
+
+    #[test]
+    fn test_exchange_continuation() {
+        let mut card = ReplayTransport::new(vec![
+            TranscriptEntry {
+                command: vec![0x00, 0xb2, 0x01, 0x0c, 0x00],
+                response: vec![0x6c, 0x04],
+            },
+            TranscriptEntry {
+                command: vec![0x00, 0xb2, 0x01, 0x0c, 0x04],
+                response: vec![0x01, 0x02, 0x61, 0x02],
+            },
+            TranscriptEntry {
+                command: vec![0x00, 0xc0, 0x00, 0x00, 0x02],
+                response: vec![0x03, 0x04, 0x90, 0x00],
+            },
+        ]);
+        assert_eq!(
+            exchange(&mut card, &ADPUCommand::read_record(1, 1)).unwrap(),
+            (vec![0x01, 0x02, 0x03, 0x04], 0x9000)
+        );
+        assert_eq!(card.remaining(), 0);
+    }
+
+    #[test]
+    fn test_exchange_wrong_le_with_data() {
+        let mut card = ReplayTransport::new(vec![
+            TranscriptEntry {
+                command: vec![0x00, 0xb2, 0x01, 0x0c, 0x00],
+                response: vec![0xff, 0xff, 0x6c, 0x02],
+            },
+            TranscriptEntry {
+                command: vec![0x00, 0xb2, 0x01, 0x0c, 0x02],
+                response: vec![0x01, 0x02, 0x90, 0x00],
+            },
+        ]);
+        assert_eq!(
+            exchange(&mut card, &ADPUCommand::read_record(1, 1)).unwrap(),
+            (vec![0x01, 0x02], 0x9000)
+        );
+        assert_eq!(card.remaining(), 0);
+    }
+
+    #[test]
+    fn test_exchange_many_continuations() {
+        let mut entries = vec![TranscriptEntry {
+            command: vec![0x00, 0xb2, 0x01, 0x0c, 0x00],
+            response: vec![0x00, 0x61, 0x00],
+        }];
+        for i in 1..5u8 {
+            let mut response = vec![i; 256];
+            response.extend_from_slice(&[0x61, 0x00]);
+            entries.push(TranscriptEntry {
+                command: vec![0x00, 0xc0, 0x00, 0x00, 0x00],
+                response,
+            });
+        }
+        entries.push(TranscriptEntry {
+            command: vec![0x00, 0xc0, 0x00, 0x00, 0x00],
+            response: vec![0x05, 0x90, 0x00],
+        });
+        let mut card = ReplayTransport::new(entries);
+
+        let (response, sw) = exchange(&mut card, &ADPUCommand::read_record(1, 1)).unwrap();
+        assert_eq!(sw, 0x9000);
+        assert_eq!(response.len(), 1 + 4 * 256 + 1);
+        assert_eq!(response[0], 0x00);
+        for i in 1..5u8 {
+            let start = 1 + (i as usize - 1) * 256;
+            assert!(response[start..start + 256].iter().all(|&b| b == i));
+        }
+        assert_eq!(response[response.len() - 1], 0x05);
+        assert_eq!(card.remaining(), 0);
+    }
+
+    /// Card that answers everything with a full buffer and 0x6100
+    struct EndlessCard;
+
+    impl CardTransport for EndlessCard {
+        fn transmit<'buf>(
+            &mut self,
+            _command: &[u8],
+            recieve_buffer: &'buf mut [u8],
+        ) -> Result<&'buf [u8], ExchangeError> {
+            let len = recieve_buffer.len();
+            recieve_buffer[len - 2..].copy_from_slice(&[0x61, 0x00]);
+            Ok(recieve_buffer)
+        }
+    }
+
+    #[test]
+    fn test_exchange_endless_continuation() {
+        assert!(matches!(
+            exchange(&mut EndlessCard, &ADPUCommand::read_record(1, 1)),
+            Err(ExchangeError::ResponseTooLong(len)) if len > MAX_RESPONSE_LEN
+        ));
+    }
+
+    /// Answers after a delay, standing in for a card that has stopped responding
+    struct SlowCard(Duration);
+
+    impl CardTransport for SlowCard {
+        fn transmit<'buf>(
+            &mut self,
+            _command: &[u8],
+            recieve_buffer: &'buf mut [u8],
+        ) -> Result<&'buf [u8], ExchangeError> {
+            thread::sleep(self.0);
+            recieve_buffer[..2].copy_from_slice(&[0x90, 0x00]);
+            Ok(&recieve_buffer[..2])
+        }
+    }
+
+    struct PanickingCard;
+
+    impl CardTransport for PanickingCard {
+        fn transmit<'buf>(
+            &mut self,
+            _command: &[u8],
+            _recieve_buffer: &'buf mut [u8],
+        ) -> Result<&'buf [u8], ExchangeError> {
+            panic!("card exploded");
+        }
+    }
+
+    #[test]
+    fn test_timeout_transport() {
+        let replay = ReplayTransport::new(vec![TranscriptEntry {
+            command: vec![0x00, 0xb2, 0x01, 0x0c, 0x00],
+            response: vec![0x70, 0x00, 0x90, 0x00],
+        }]);
+        let mut card = TimeoutTransport::new(replay, DEFAULT_TIMEOUT);
+        assert_eq!(
+            exchange(&mut card, &ADPUCommand::read_record(1, 1)),
+            Ok((vec![0x70, 0x00], 0x9000))
+        );
+        assert_eq!(card.into_inner().unwrap().remaining(), 0);
+
+        let timeout = Duration::from_millis(20);
+        let mut card = TimeoutTransport::new(SlowCard(Duration::from_secs(1)), timeout);
+        assert_eq!(
+            exchange(&mut card, &ADPUCommand::read_record(1, 1)),
+            Err(ExchangeError::Timeout(timeout))
+        );
+        // Later commands fail straight away instead of queueing behind the stuck one
+        assert_eq!(
+            exchange(&mut card, &ADPUCommand::read_record(1, 2)),
+            Err(ExchangeError::Timeout(timeout))
+        );
+        assert!(card.into_inner().is_none());
+
+        // A card that panics takes the worker with it, which isn't a timeout
+        let mut card = TimeoutTransport::new(PanickingCard, DEFAULT_TIMEOUT);
+        assert_eq!(
+            exchange(&mut card, &ADPUCommand::read_record(1, 1)),
+            Err(ExchangeError::WorkerStopped)
+        );
+        assert_eq!(
+            exchange(&mut card, &ADPUCommand::read_record(1, 2)),
+            Err(ExchangeError::WorkerStopped)
+        );
+    }
+
+    #[test]
+    fn test_exchange_errors() {
+        let mut card = ReplayTransport::new(vec![
+            TranscriptEntry {
+                command: vec![0x00, 0xb2, 0x01, 0x0c, 0x00],
+                response: vec![0x6a],
+            },
+            TranscriptEntry {
+                command: vec![0x00, 0xb2, 0x01, 0x0c, 0x00],
+                response: vec![0x6a, 0x83],
+            },
+        ]);
+        let command = ADPUCommand::read_record(1, 1);
+        assert_eq!(
+            exchange(&mut card, &command),
+            Err(ExchangeError::ResponseTooShort(1))
+        );
+
+        // Status words are returned, but can be turned into errors which survive anyhow
+        let (_, sw) = exchange(&mut card, &command).unwrap();
+        let err = anyhow::Error::from(ExchangeError::check_sw(sw).unwrap_err())
+            .context("Failure returned by card while reading record");
+        assert_eq!(
+            err.downcast_ref::<ExchangeError>(),
+            Some(&ExchangeError::StatusWord(0x6a83))
+        );
+
+        assert!(matches!(
+            exchange(&mut card, &command),
+            Err(ExchangeError::Transcript(_))
+        ));
+    }
+
+    #[test]
+    fn test_encode_lengths() {
+        let long_data = [0xaa; 300];
+        let header = [0x00, 0xb2, 0x01, 0x0c];
+        let cases: [(&[u8], Option<u32>, &[u8]); 10] = [
+            // Case 1: no data, no response
+            (&[], None, &[]),
+            // Case 2S: short Le
+            (&[], Some(0x10), &[0x10]),
+            (&[], Some(0x100), &[0x00]),
+            // Case 2E: extended Le
+            (&[], Some(0x101), &[0x00, 0x01, 0x01]),
+            (&[], Some(0x10000), &[0x00, 0x00, 0x00]),
+            // Case 3S: short Lc
+            (&[0x12, 0x34], None, &[0x02, 0x12, 0x34]),
+            // Case 4S: short Lc and Le
+            (&[0x12, 0x34], Some(0x100), &[0x02, 0x12, 0x34, 0x00]),
+            // Case 4E: extended Le forces extended Lc
+            (&[0x12], Some(0x200), &[0x00, 0x00, 0x01, 0x12, 0x02, 0x00]),
+            // Case 3E: extended Lc
+            (&long_data, None, &[0x00, 0x01, 0x2c]),
+            // Case 4E: extended Lc forces extended Le
+            (&long_data, Some(0x10), &[0x00, 0x01, 0x2c]),
+        ];
+
+        for (data, ne, expected_fields) in cases {
+            let command = ADPUCommand {
+                cla: header[0],
+                ins: header[1],
+                p1: header[2],
+                p2: header[3],
+                data,
+                ne,
+            };
+            let encoded = command.encode().unwrap();
+            assert_eq!(&encoded[..4], &header);
+            if data.len() > 255 {
+                // Extended Lc, then data, then the 2 byte Le if any
+                assert_eq!(&encoded[4..7], expected_fields);
+                assert_eq!(&encoded[7..7 + data.len()], data);
+                let le = &encoded[7 + data.len()..];
+                match ne {
+                    None => assert!(le.is_empty()),
+                    Some(ne) => assert_eq!(le, (ne as u16).to_be_bytes()),
+                }
+            } else {
+                assert_eq!(&encoded[4..], expected_fields, "{:?} {:?}", data, ne);
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_roundtrip() {
+        let data = [0x5a; 300];
+        for nc in [0, 1, 255, 256, 300] {
+            for ne in [
+                None,
+                Some(1),
+                Some(255),
+                Some(256),
+                Some(257),
+                Some(65535),
+                Some(65536),
+            ] {
+                let command = ADPUCommand {
+                    cla: 0x80,
+                    ins: 0xae,
+                    p1: 0x90,
+                    p2: 0x00,
+                    data: &data[..nc],
+                    ne,
+                };
+                let encoded = command.encode().unwrap();
+                assert_eq!(
+                    ADPUCommand::decode(&encoded),
+                    Some(command),
+                    "nc {} ne {:?}",
+                    nc,
+                    ne
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        // Too short for a header
+        assert_eq!(ADPUCommand::decode(&[0x00, 0xb2, 0x01]), None);
+        // Lc says 3 bytes but there are 2
+        assert_eq!(
+            ADPUCommand::decode(&[0x00, 0x88, 0x00, 0x00, 0x03, 0x01, 0x02]),
+            None
+        );
+        // 2 bytes left over after the data
+        assert_eq!(
+            ADPUCommand::decode(&[0x00, 0x88, 0x00, 0x00, 0x01, 0x01, 0x00, 0x00]),
+            None
+        );
+        // Extended Lc of 0
+        assert_eq!(
+            ADPUCommand::decode(&[0x00, 0x88, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+            None
+        );
+        // Short Lc of 0
+        assert_eq!(
+            ADPUCommand::decode(&[0x00, 0xa4, 0x04, 0x00, 0x00, 0x05]),
+            None
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_encode_decode(
+            header in any::<[u8; 4]>(),
+            data in proptest::collection::vec(any::<u8>(), 0..300),
+            ne in proptest::option::of(1u32..=0x10000),
+        ) {
+            let command = ADPUCommand {
+                cla: header[0],
+                ins: header[1],
+                p1: header[2],
+                p2: header[3],
+                data: &data,
+                ne,
+            };
+            let encoded = command.encode().unwrap();
+            prop_assert_eq!(ADPUCommand::decode(&encoded), Some(command));
+        }
+    }
+
+    #[test]
+    fn test_encode_too_long() {
+        let long_data = vec![0; 65536];
+        let mut command = ADPUCommand::select(&long_data);
+        assert_eq!(command.encode(), None);
+        command.data = &[];
+        command.ne = Some(65537);
+        assert_eq!(command.encode(), None);
+        command.ne = Some(0);
+        assert_eq!(command.encode(), None);
+    }
+
+    #[test]
+    fn test_encode_internal_authenticate() {
+        assert_eq!(
+            ADPUCommand::internal_authenticate(&[0x12, 0x34, 0x56, 0x78])
+                .encode()
+                .unwrap()
+                .as_ref(),
+            &[0x00, 0x88, 0x00, 0x00, 0x04, 0x12, 0x34, 0x56, 0x78, 0x00]
+        )
+    }
+
+    #[test]
+    fn test_encode_select() {
+        assert_eq!(
+            ADPUCommand::select(&[0xa0, 0x00, 0x00, 0x00, 0x03])
+                .encode()
+                .unwrap()
+                .as_ref(),
+            &[0x00, 0xa4, 0x04, 0x00, 0x05, 0xa0, 0x00, 0x00, 0x00, 0x03, 0x00]
+        );
+        assert_eq!(
+            ADPUCommand::select_with(&[0xa0, 0x00, 0x00, 0x00, 0x03], SelectOccurrence::Next).p2,
+            0x02
+        );
+        assert_eq!(
+            ADPUCommand::select_with_response(
+                &[0xa0, 0x00, 0x00, 0x00, 0x03],
+                SelectOccurrence::First,
+                SelectResponse::None
+            )
+            .encode()
+            .unwrap()
+            .as_ref(),
+            &[0x00, 0xa4, 0x04, 0x0c, 0x05, 0xa0, 0x00, 0x00, 0x00, 0x03]
+        );
+    }
+
+    #[test]
+    fn test_encode_get_data() {
+        assert_eq!(
+            ADPUCommand::get_data(0x9f36).encode().unwrap().as_ref(),
+            &[0x80, 0xca, 0x9f, 0x36, 0x00]
+        )
+    }
+
+    #[test]
+    fn test_encode_put_data() {
+        assert_eq!(
+            &*ADPUCommand::put_data(0x9f14, &[0x03, 0xaa, 0xbb])
+                .encode()
+                .unwrap(),
+            &[0x04, 0xda, 0x9f, 0x14, 0x03, 0x03, 0xaa, 0xbb]
+        );
+    }
+
+    #[test]
+    fn test_describe_sw() {
+        assert_eq!(describe_sw(0x6a83), "Record not found");
+        assert_eq!(describe_sw(0x63c2), "Verification failed, 2 tries left");
+        assert_eq!(describe_sw(0x6c10), "Wrong Le, 16 bytes available");
+    }
+
+    #[test]
+    fn test_check_data_sw() {
+        assert_eq!(ExchangeError::check_data_sw(0x9000, &[], false), Ok(()));
+        assert_eq!(
+            ExchangeError::check_data_sw(0x6283, &[0x6f, 0x00], false),
+            Ok(())
+        );
+        assert_eq!(
+            ExchangeError::check_data_sw(0x6283, &[0x6f, 0x00], true),
+            Err(ExchangeError::StatusWord(0x6283))
+        );
+        assert_eq!(
+            ExchangeError::check_data_sw(0x6283, &[], false),
+            Err(ExchangeError::StatusWord(0x6283))
+        );
+        assert_eq!(
+            ExchangeError::check_data_sw(0x6a82, &[0x6f, 0x00], false),
+            Err(ExchangeError::StatusWord(0x6a82))
+        );
+    }
+
+    #[test]
+    fn test_encode_generate_ac() {
+        assert_eq!(
+            ADPUCommand::generate_ac(AcType::Arqc, true, &[0xab])
+                .encode()
+                .unwrap()
+                .as_ref(),
+            &[0x80, 0xae, 0x90, 0x00, 0x01, 0xab, 0x00]
+        )
+    }
+
+    #[test]
+    fn test_encode_get_challenge() {
+        assert_eq!(
+            ADPUCommand::get_challenge().encode().unwrap().as_ref(),
+            &[0x00, 0x84, 0x00, 0x00, 0x00]
+        )
+    }
+}
@@ -0,0 +1,141 @@
+use std::fmt::Display;
+
+/// Issuer Application Data (0x9f10) split into its parts. The layout is defined by each payment
+/// system rather than EMV, so this is best-effort: it recognises the common Visa and Mastercard
+/// layouts by their shape and otherwise keeps the raw bytes. A card from another scheme could
+/// happen to match one of the shapes, so don't rely on the result for anything but display.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IssuerApplicationData {
+    /// Visa formats 0, 1, and 3: a length byte of 6, then DKI, CVN, and the CVR with its own
+    /// length byte
+    Visa {
+        /// Derivation Key Index
+        dki: u8,
+        /// Cryptogram Version Number
+        cvn: u8,
+        /// Card Verification Results, without the length byte
+        cvr: Vec<u8>,
+        /// Issuer Discretionary Data, if any
+        discretionary: Vec<u8>,
+    },
+    /// M/Chip: DKI, CVN, a 6 byte CVR, and the DAC or ICC Dynamic Number, optionally followed by
+    /// counters
+    Mastercard {
+        /// Derivation Key Index
+        dki: u8,
+        /// Cryptogram Version Number
+        cvn: u8,
+        /// Card Verification Results
+        cvr: [u8; 6],
+        /// Data Authentication Code or the first bytes of the ICC Dynamic Number
+        dac: [u8; 2],
+        /// Counters, if any
+        counters: Vec<u8>,
+    },
+    Unknown(Vec<u8>),
+}
+
+/// Decode the Issuer Application Data if it has a recognisable layout, see
+/// [`IssuerApplicationData`]
+pub fn decode_iad(iad: &[u8]) -> IssuerApplicationData {
+    match *iad {
+        [0x06, dki, cvn, 0x03, ref rest @ ..] if rest.len() >= 3 => IssuerApplicationData::Visa {
+            dki,
+            cvn,
+            cvr: rest[..3].to_vec(),
+            discretionary: rest[3..].to_vec(),
+        },
+        [dki, cvn, ref rest @ ..] if cvn & 0xf0 == 0x10 && matches!(rest.len(), 8 | 16 | 24) => {
+            IssuerApplicationData::Mastercard {
+                dki,
+                cvn,
+                cvr: rest[..6].try_into().unwrap(),
+                dac: rest[6..8].try_into().unwrap(),
+                counters: rest[8..].to_vec(),
+            }
+        }
+        _ => IssuerApplicationData::Unknown(iad.to_vec()),
+    }
+}
+
+impl Display for IssuerApplicationData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IssuerApplicationData::Visa {
+                dki,
+                cvn,
+                cvr,
+                discretionary,
+            } => {
+                write!(
+                    f,
+                    "Visa CVN {:02x}, DKI {:02x}, CVR {}",
+                    cvn,
+                    dki,
+                    hex::encode(cvr)
+                )?;
+                if !discretionary.is_empty() {
+                    write!(f, ", discretionary data {}", hex::encode(discretionary))?;
+                }
+                Ok(())
+            }
+            IssuerApplicationData::Mastercard {
+                dki,
+                cvn,
+                cvr,
+                dac,
+                counters,
+            } => {
+                write!(
+                    f,
+                    "Mastercard CVN {:02x}, DKI {:02x}, CVR {}, DAC {}",
+                    cvn,
+                    dki,
+                    hex::encode(cvr),
+                    hex::encode(dac)
+                )?;
+                if !counters.is_empty() {
+                    write!(f, ", counters {}", hex::encode(counters))?;
+                }
+                Ok(())
+            }
+            IssuerApplicationData::Unknown(raw) => write!(f, "unknown format {}", hex::encode(raw)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_iad() {
+        assert_eq!(
+            decode_iad(&hex::decode("06010a03a00000").unwrap()),
+            IssuerApplicationData::Visa {
+                dki: 0x01,
+                cvn: 0x0a,
+                cvr: vec![0xa0, 0x00, 0x00],
+                discretionary: vec![],
+            }
+        );
+        assert_eq!(
+            decode_iad(&hex::decode("06011203a000000f0300001000000000000000").unwrap()).to_string(),
+            "Visa CVN 12, DKI 01, CVR a00000, discretionary data 0f0300001000000000000000"
+        );
+        assert_eq!(
+            decode_iad(&hex::decode("0110a00003220000a1b20000000000000000").unwrap()),
+            IssuerApplicationData::Mastercard {
+                dki: 0x01,
+                cvn: 0x10,
+                cvr: [0xa0, 0x00, 0x03, 0x22, 0x00, 0x00],
+                dac: [0xa1, 0xb2],
+                counters: vec![0; 8],
+            }
+        );
+        assert_eq!(
+            decode_iad(&[0x01, 0x02, 0x03]),
+            IssuerApplicationData::Unknown(vec![0x01, 0x02, 0x03])
+        );
+    }
+}
@@ -0,0 +1,105 @@
+use std::fmt::Display;
+
+use crate::tlv::{decoders::alphanumeric_string, DecodeError};
+
+/// International Bank Account Number (0x5f53) from ISO 13616, in the electronic format without
+/// spaces
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Iban {
+    pub value: String,
+    /// Whether the check digits match the rest of the number
+    pub checksum_valid: bool,
+}
+
+impl Iban {
+    /// ISO 3166 alpha-2 country code
+    pub fn country(&self) -> &str {
+        &self.value[..2]
+    }
+
+    /// Basic Bank Account Number, the country specific part after the check digits
+    pub fn bban(&self) -> &str {
+        &self.value[4..]
+    }
+}
+
+impl Display for Iban {
+    /// The print format, in groups of four characters
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, group) in self.value.as_bytes().chunks(4).enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            // Only ASCII alphanumerics get through parse_iban
+            write!(f, "{}", std::str::from_utf8(group).unwrap())?;
+        }
+        Ok(())
+    }
+}
+
+/// ISO 7064 MOD 97-10: move the country code and check digits to the end, replace letters with 10
+/// to 35, and the number must be 1 mod 97
+fn checksum_valid(iban: &str) -> bool {
+    let (start, rest) = iban.split_at(4);
+    rest.chars()
+        .chain(start.chars())
+        .filter_map(|c| c.to_digit(36))
+        .fold(0, |remainder, digit| {
+            let scale = if digit < 10 { 10 } else { 100 };
+            (remainder * scale + digit) % 97
+        })
+        == 1
+}
+
+/// Decode an IBAN as stored in 0x5f53. The characters and the country code and check digit layout
+/// must be right, but a wrong checksum is only reported in [`Iban::checksum_valid`] so the number
+/// can still be shown.
+pub fn parse_iban(raw: &[u8]) -> Result<Iban, DecodeError> {
+    let value = alphanumeric_string(raw)?.to_ascii_uppercase();
+    if value.len() < 5 {
+        return Err(DecodeError::MessageTooShort(5, value.len()));
+    }
+    if value.len() > 34 {
+        return Err(DecodeError::LengthTooLong(34, value.len()));
+    }
+    let bytes = value.as_bytes();
+    if !bytes[..2].iter().all(u8::is_ascii_alphabetic)
+        || !bytes[2..4].iter().all(u8::is_ascii_digit)
+    {
+        return Err(DecodeError::WrongType(0x5f53, "IBAN"));
+    }
+
+    Ok(Iban {
+        checksum_valid: checksum_valid(&value),
+        value,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_iban() {
+        let iban = parse_iban(b"GB82WEST12345698765432").unwrap();
+        assert!(iban.checksum_valid);
+        assert_eq!(iban.country(), "GB");
+        assert_eq!(iban.bban(), "WEST12345698765432");
+        assert_eq!(iban.to_string(), "GB82 WEST 1234 5698 7654 32");
+
+        assert!(
+            parse_iban(b"de89370400440532013000")
+                .unwrap()
+                .checksum_valid
+        );
+        assert!(
+            !parse_iban(b"GB83WEST12345698765432")
+                .unwrap()
+                .checksum_valid
+        );
+
+        assert!(parse_iban(b"GB82 WEST").is_err());
+        assert!(parse_iban(b"1282WEST12345698765432").is_err());
+        assert!(parse_iban(b"GB8").is_err());
+    }
+}
@@ -0,0 +1,26 @@
+pub mod afl;
+pub mod aip;
+pub mod auc;
+pub mod color;
+pub mod contactless;
+pub mod crypto;
+pub mod currency;
+pub mod cvm;
+pub mod dump;
+pub mod exchange;
+pub mod iad;
+pub mod iban;
+pub mod notes;
+pub mod pan;
+pub mod pin;
+pub mod processing_options;
+pub mod pse;
+pub mod replay;
+pub mod script;
+pub mod service_code;
+pub mod terminal;
+pub mod tlv;
+pub mod transaction;
+pub mod tvr;
+mod util;
+pub mod verify;
@@ -1,20 +1,43 @@
-use std::collections::HashMap;
+use std::{
+    io::{IsTerminal, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    str::FromStr,
+    time::Duration,
+};
 
 use anyhow::Context;
-use crypto::chain::IssuerPublicKey;
-use log::error;
+use chrono::{Datelike, NaiveDate};
+use emvsign::{
+    aip::{Aip, OfflineAuthentication},
+    color::{Painter, Style},
+    contactless::{self, Kernel},
+    crypto::{
+        chain::{self, verify_sda, ICCPublicKey, IssuerPublicKey},
+        dynamic::{verify_cda, verify_dda},
+        export,
+    },
+    currency, dump,
+    exchange::{self, CardTransport, TimeoutTransport},
+    iad,
+    notes::NOTES,
+    pan,
+    pin::{self, PinResult},
+    processing_options::{self, ProcessingResult},
+    pse,
+    replay::{RecordingTransport, ReplayTransport},
+    script,
+    terminal::{
+        self, AdditionalTerminalCapabilities, TerminalActionCode, TerminalCapabilities,
+        TerminalConfig, TerminalType,
+    },
+    tlv::{self, FieldMapExt, OptionsMap, TagMap, Value},
+    transaction::{self, ApplicationCryptogram, TransactionSummary},
+    tvr::{self, ActionCodes, Tvr},
+    verify,
+};
+use log::{debug, error, info, warn};
 use structopt::StructOpt;
-use tlv::{OptionsMap, Value};
-
-use crate::crypto::chain::ICCPublicKey;
-
-mod crypto;
-mod exchange;
-mod processing_options;
-mod pse;
-mod tlv;
-mod transaction;
-mod util;
 
 #[derive(Debug, StructOpt)]
 struct Options {
@@ -25,11 +48,146 @@ struct Options {
         help = "Reader index, see list-readers"
     )]
     reader: usize,
+    #[structopt(
+        long,
+        help = "Use the reader whose name contains this, ignoring case, instead of --reader"
+    )]
+    reader_name: Option<String>,
     #[structopt(
         long,
         help = "Use the PPSE (2PAY.SYS.DDF01) instead of the PSE (1PAY.SYS.DDF01)"
     )]
     ppse: bool,
+    #[structopt(
+        long,
+        help = "Select this AID (as hex) directly instead of the first application in the PSE"
+    )]
+    aid: Option<Aid>,
+    #[structopt(
+        long,
+        conflicts_with = "aid",
+        help = "Use the application at this index in the PSE instead of asking"
+    )]
+    select: Option<usize>,
+    #[structopt(
+        long,
+        help = "Show full PANs instead of masking all but the first 6 and last 4 digits"
+    )]
+    show_pan: bool,
+    #[structopt(
+        long,
+        help = "Never color output, it is otherwise colored when writing to a terminal unless \
+                NO_COLOR is set"
+    )]
+    no_color: bool,
+    #[structopt(
+        long,
+        number_of_values = 1,
+        help = "Log this module at trace level, or at another level as module=level (e.g. \
+                exchange=debug). Can be repeated and overrides RUST_LOG for that module"
+    )]
+    verbose: Vec<String>,
+    #[structopt(
+        long,
+        help = "Stop on warning status words (0x62xx and 0x63xx) instead of using the data the card \
+                returned anyway"
+    )]
+    fail_on_warning: bool,
+    #[structopt(
+        long,
+        default_value = "10",
+        help = "Seconds to wait for each response from the card before giving up"
+    )]
+    timeout: f64,
+    #[structopt(
+        long,
+        conflicts_with_all = &["aid", "select"],
+        help = "Use the application whose label contains this, ignoring case"
+    )]
+    app: Option<String>,
+    #[structopt(
+        long,
+        default_value = "0",
+        help = "Amount authorised in the minor unit of the currency (e.g. cents)"
+    )]
+    amount: u64,
+    #[structopt(
+        long,
+        default_value = "840",
+        help = "Transaction currency as an ISO 4217 numeric code"
+    )]
+    currency: u16,
+    #[structopt(long, help = "Transaction date as yyyy-mm-dd, defaults to today")]
+    date: Option<NaiveDate>,
+    #[structopt(
+        long,
+        default_value = "0",
+        help = "Transaction type as the first two digits of an ISO 8583 processing code"
+    )]
+    tx_type: u8,
+    #[structopt(
+        long,
+        help = "Unpredictable number as 4 bytes of hex instead of a random one, needed to --replay \
+                transcripts that include it"
+    )]
+    unpredictable: Option<UnpredictableNumber>,
+    #[structopt(
+        long,
+        default_value = "22",
+        help = "Terminal type as two digits, the default is an attended merchant terminal with \
+                online capability"
+    )]
+    terminal_type: TerminalType,
+    #[structopt(
+        long,
+        default_value = "e0f8c8",
+        help = "Terminal capabilities as 3 bytes of hex"
+    )]
+    terminal_capabilities: TerminalCapabilities,
+    #[structopt(
+        long,
+        default_value = "6000b0f001",
+        help = "Additional terminal capabilities as 5 bytes of hex"
+    )]
+    additional_terminal_capabilities: AdditionalTerminalCapabilities,
+    #[structopt(
+        long,
+        default_value = "0000000000",
+        help = "Terminal Action Code - Default as 5 bytes of hex in the TVR layout, for when the \
+                terminal can't go online"
+    )]
+    tac_default: TerminalActionCode,
+    #[structopt(
+        long,
+        default_value = "0000000000",
+        help = "Terminal Action Code - Denial as 5 bytes of hex in the TVR layout"
+    )]
+    tac_denial: TerminalActionCode,
+    #[structopt(
+        long,
+        default_value = "0000000000",
+        help = "Terminal Action Code - Online as 5 bytes of hex in the TVR layout"
+    )]
+    tac_online: TerminalActionCode,
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "JSON file mapping hex tags to terminal values for DOLs, in the format of decode \
+                --json, overriding the values from other options"
+    )]
+    terminal_config: Option<PathBuf>,
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Replay a transcript recorded with --record instead of using a reader"
+    )]
+    replay: Option<PathBuf>,
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Record all commands sent to the card to a transcript file"
+    )]
+    record: Option<PathBuf>,
     #[structopt(subcommand)]
     cmd: Command,
 }
@@ -37,90 +195,1059 @@ struct Options {
 #[derive(Debug, StructOpt)]
 enum Command {
     #[structopt(about = "List connected readers")]
-    ListReaders,
+    ListReaders {
+        #[structopt(
+            long,
+            help = "Keep running and print readers and cards as they are added and removed"
+        )]
+        watch: bool,
+    },
+    #[structopt(about = "List applications in the PSE by priority")]
+    ListAids {
+        #[structopt(
+            long,
+            number_of_values = 1,
+            help = "Instead of reading the PSE, select each application starting with this AID \
+                    (as hex) in turn. Can be repeated"
+        )]
+        by_aid: Vec<Aid>,
+    },
     #[structopt(about = "Show data contained in the PSE")]
     ShowPSE,
+    #[structopt(
+        about = "Show the FCI, first record, and data object lists of the application. Starts a \
+                 transaction to read the records"
+    )]
+    ShowDol,
+    #[structopt(about = "Show which authentication methods the card supports")]
+    Capabilities,
     #[structopt(about = "Get the public key")]
-    GetKey,
+    GetKey {
+        #[structopt(
+            long,
+            parse(from_os_str),
+            help = "Directory to write the recovered keys to as PEM and DER SubjectPublicKeyInfo"
+        )]
+        export_keys: Option<PathBuf>,
+    },
+    #[structopt(about = "Recover the card's keys and check every supported authentication method")]
+    Verify {
+        #[structopt(long, help = "Print the results as a JSON report")]
+        json: bool,
+    },
     #[structopt(about = "Run a test transaction")]
     TestTransaction,
+    #[structopt(about = "Show the transaction and PIN try counters")]
+    Counters,
+    #[structopt(about = "Send the commands in an issuer script to the card")]
+    IssuerScript {
+        #[structopt(help = "Issuer Script Template (0x71 or 0x72) as hex")]
+        script: ScriptTemplate,
+    },
+    #[structopt(about = "Save everything readable from the card to a JSON file")]
+    Dump {
+        #[structopt(long, parse(from_os_str), help = "File to write the dump to")]
+        out: PathBuf,
+        #[structopt(long, help = "List tags read from the card that have no known meaning")]
+        unknown_tags: bool,
+    },
+    #[structopt(about = "Verify an offline PIN")]
+    VerifyPin {
+        pin: String,
+        #[structopt(long, help = "Encipher the PIN with the card's public key")]
+        enciphered: bool,
+    },
+    #[structopt(about = "Decode a hex TLV blob without a card")]
+    Decode {
+        #[structopt(help = "TLV data as hex, whitespace is ignored")]
+        hex: Option<String>,
+        #[structopt(
+            long,
+            parse(from_os_str),
+            conflicts_with = "hex",
+            help = "Read hex TLV data from a file"
+        )]
+        file: Option<PathBuf>,
+        #[structopt(long, help = "Print the decoded fields as JSON")]
+        json: bool,
+        #[structopt(
+            long,
+            conflicts_with = "json",
+            help = "Print the decoded fields as a JSON tree with tag names, types, and raw values"
+        )]
+        tree: bool,
+    },
+    #[structopt(about = "Encode a JSON description of TLV fields as hex")]
+    Encode {
+        #[structopt(
+            help = "JSON object mapping hex tags to values, in the format of decode --json"
+        )]
+        json: Option<String>,
+        #[structopt(
+            long,
+            parse(from_os_str),
+            conflicts_with = "json",
+            help = "Read the JSON description from a file"
+        )]
+        file: Option<PathBuf>,
+    },
+    #[structopt(
+        about = "Run a command against a transcript recorded with --record instead of a card",
+        setting = structopt::clap::AppSettings::TrailingVarArg
+    )]
+    Replay {
+        #[structopt(parse(from_os_str), help = "Transcript file")]
+        transcript: PathBuf,
+        #[structopt(
+            required = true,
+            allow_hyphen_values = true,
+            help = "Command to run and its options, as they were recorded"
+        )]
+        command: Vec<String>,
+    },
+}
+/// Turn a `--verbose` module, optionally with `=level`, into an env_logger directive for that
+/// module of this crate
+fn log_directive(module: &str) -> String {
+    let (module, level) = module.split_once('=').unwrap_or((module, "trace"));
+    let module = module.trim_start_matches("emvsign::");
+    format!("emvsign::{}={}", module, level)
 }
-fn main() -> anyhow::Result<()> {
-    pretty_env_logger::init();
 
+/// Set up logging from `RUST_LOG` like `pretty_env_logger::init`, then apply the per-module levels
+/// from `--verbose` on top
+fn init_logging(verbose: &[String]) {
+    let mut builder = pretty_env_logger::formatted_builder();
+    // Without RUST_LOG env_logger only shows errors, but once --verbose adds a directive anything
+    // it doesn't cover would be hidden entirely
+    let filters = std::env::var("RUST_LOG").unwrap_or_else(|_| "error".to_string());
+    builder.parse_filters(&filters);
+    for module in verbose {
+        builder.parse_filters(&log_directive(module));
+    }
+    builder.init();
+}
+
+fn main() -> ExitCode {
     let options = Options::from_args();
+    init_logging(&options.verbose);
+    let error_painter = Painter::for_stream(&std::io::stderr(), options.no_color);
+    match run(options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}: {:?}", error_painter.paint(Style::Error, "Error"), err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(mut options: Options) -> anyhow::Result<()> {
+    if let Command::Replay {
+        ref transcript,
+        ref command,
+    } = options.cmd
+    {
+        let replayed =
+            Command::from_iter(std::iter::once("replay").chain(command.iter().map(String::as_str)));
+        options.replay = Some(transcript.clone());
+        options.cmd = replayed;
+    }
+    if let Command::Decode {
+        ref hex,
+        ref file,
+        json,
+        tree,
+    } = options.cmd
+    {
+        return decode(
+            hex.as_deref(),
+            file.as_deref(),
+            json,
+            tree,
+            stdout_painter(&options),
+            options.show_pan,
+        );
+    }
+    if let Command::Encode { ref json, ref file } = options.cmd {
+        return encode(json.as_deref(), file.as_deref());
+    }
+
+    let mut state = terminal_state(&options)?;
+
+    if let Some(transcript) = &options.replay {
+        let mut card = ReplayTransport::from_file(transcript)?;
+        card.set_fail_on_warning(options.fail_on_warning);
+        run_command(&mut card, &options, &mut state)?;
+        if card.remaining() != 0 {
+            warn!("{} exchanges left over in transcript", card.remaining());
+        }
+        return Ok(());
+    }
+
     let context =
         pcsc::Context::establish(pcsc::Scope::User).context("Failed to create PCSC session")?;
+    if let Command::ListReaders { watch } = options.cmd {
+        return if watch {
+            watch_readers(&context)
+        } else {
+            list_readers(&context)
+        };
+    }
+
+    let timeout = Duration::try_from_secs_f64(options.timeout)
+        .ok()
+        .filter(|timeout| !timeout.is_zero())
+        .context("Timeout must be a positive number of seconds")?;
+    let card = get_card(&options, &context).context("Failed to connect to card")?;
+    let mut card = TimeoutTransport::new(card, timeout);
+    card.set_fail_on_warning(options.fail_on_warning);
+    let res = if let Some(transcript) = &options.record {
+        let mut recorder = RecordingTransport::new(&mut card);
+        let res = run_command(&mut recorder, &options, &mut state);
+        recorder.save(transcript)?;
+        res
+    } else {
+        run_command(&mut card, &options, &mut state)
+    };
+
+    // Dropping the guard resets the card, this only needs to report a card that is stuck
+    if card.into_inner().is_none() {
+        error!("Card stopped responding, you may need to manually unplug the card");
+    }
+    res
+}
+
+#[derive(Debug, Clone)]
+struct UnpredictableNumber([u8; 4]);
+
+impl FromStr for UnpredictableNumber {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut unpredictable = [0; 4];
+        hex::decode_to_slice(s, &mut unpredictable)
+            .context("Unpredictable number must be 4 bytes of hex")?;
+        Ok(UnpredictableNumber(unpredictable))
+    }
+}
+
+fn currency_exponent(code: u16) -> u8 {
+    currency::currency_name(code).map_or(2, |(_, exponent)| exponent)
+}
+
+fn terminal_state(options: &Options) -> anyhow::Result<OptionsMap> {
+    if options.currency > 999 {
+        anyhow::bail!("Currency code must be at most 3 digits");
+    }
+    if options.amount > 999_999_999_999 {
+        anyhow::bail!("Amount must be at most 12 digits");
+    }
+    if options.tx_type > 99 {
+        anyhow::bail!("Transaction type must be at most 2 digits");
+    }
+    let date = options
+        .date
+        .unwrap_or_else(|| chrono::Local::now().date_naive());
 
     let mut state = OptionsMap::new();
 
-    // Chosen by fair die roll
-    state.insert(0x9f37, Value::Binary(vec![0x00, 0x00, 0x00, 0x04]));
-    // Currency code: USD
-    state.insert(0x5f2a, Value::Numeric(840));
+    let unpredictable = match &options.unpredictable {
+        Some(UnpredictableNumber(unpredictable)) => *unpredictable,
+        None => rand::random(),
+    };
+    info!("Unpredictable number: {}", hex::encode(unpredictable));
+    state.insert(0x9f37, Value::Binary(unpredictable.to_vec()));
+    // Amounts are sent as BCD, and as 4 byte binary for cards that ask for it if they fit
+    state.insert(0x9f02, Value::Numeric(options.amount as u128));
+    state.insert(0x9f03, Value::Numeric(0));
+    if let Ok(amount) = u32::try_from(options.amount) {
+        state.insert(0x81, Value::Binary(amount.to_be_bytes().to_vec()));
+        state.insert(0x9f04, Value::Binary(vec![0; 4]));
+    }
+    state.insert(0x5f2a, Value::Numeric(options.currency as u128));
+    state.insert(
+        0x5f36,
+        Value::Numeric(currency_exponent(options.currency) as u128),
+    );
+    // YYMMDD
+    state.insert(
+        0x9a,
+        Value::Numeric(
+            (date.year() % 100) as u128 * 10000 + date.month() as u128 * 100 + date.day() as u128,
+        ),
+    );
+    state.insert(0x9c, Value::Numeric(options.tx_type as u128));
+    TerminalConfig {
+        terminal_type: options.terminal_type,
+        capabilities: options.terminal_capabilities,
+        additional_capabilities: options.additional_terminal_capabilities,
+    }
+    .insert_into(&mut state);
+    if let Some(path) = &options.terminal_config {
+        let values = terminal::load_values(path)?;
+        info!(
+            "Loaded {} terminal values from {}",
+            values.len(),
+            path.display()
+        );
+        state.extend(values);
+    }
+    Ok(state)
+}
+
+/// Colors output when stdout is a terminal, unless disabled
+fn stdout_painter(options: &Options) -> Painter {
+    Painter::for_stream(&std::io::stdout(), options.no_color)
+}
 
+fn run_command<C: CardTransport + ?Sized>(
+    card: &mut C,
+    options: &Options,
+    state: &mut OptionsMap,
+) -> anyhow::Result<()> {
+    let painter = stdout_painter(options);
     match options.cmd {
-        Command::ListReaders => list_readers(&context),
+        Command::ListReaders { .. }
+        | Command::Decode { .. }
+        | Command::Encode { .. }
+        | Command::Replay { .. } => {
+            anyhow::bail!("Command does not use a card")
+        }
+        Command::ListAids { ref by_aid } => {
+            let applications = if by_aid.is_empty() {
+                let pse_data = pse::list_applications(card, options.ppse)?;
+                println!("Applications found using {}", pse_data.source);
+                pse_data.applications
+            } else {
+                let mut applications = Vec::new();
+                for Aid(aid) in by_aid {
+                    applications.extend(pse::list_by_aid(card, aid)?);
+                }
+                applications
+            };
+            println!("{:<16} {:<32} {:<8} COUNTRY", "LABEL", "AID", "PRIORITY");
+            for app in applications {
+                println!(
+                    "{:<16} {:<32} {:<8} {}",
+                    app.label,
+                    hex::encode(&app.aid),
+                    app.priority_order()
+                        .map_or("-".to_string(), |order| order.to_string()),
+                    app.country.as_deref().unwrap_or("-")
+                );
+            }
+            Ok(())
+        }
         Command::ShowPSE => {
-            let mut card = get_card(&options, &context).context("Failed to connect to card")?;
-            let res = pse::list_applications(&mut card, options.ppse);
+            let res = pse::list_applications(card, options.ppse);
             println!("{:#?}", res);
-            // Reset the card because we could be in a PIN authenticated state
-            if card.disconnect(pcsc::Disposition::ResetCard).is_err() {
-                error!("Failed to reset card, you may need to manually unplug the card");
-            }
             res?;
             Ok(())
         }
-        Command::GetKey => {
-            let mut card = get_card(&options, &context).context("Failed to connect to card")?;
-            let pse_data = pse::list_applications(&mut card, options.ppse)?;
-            let aid = &pse_data
-                .applications
-                .get(0)
-                .ok_or_else(|| anyhow::anyhow!("No applications in PSE"))?
-                .aid;
+        Command::ShowDol => {
+            let aid = choose_application(card, options)?;
+            let fci = processing_options::select_application(card, &aid)?;
+            println!(
+                "{}",
+                fci.display()
+                    .with_painter(painter)
+                    .show_pan(options.show_pan)
+                    .with_notes(&NOTES)
+            );
+
+            // Only GET PROCESSING OPTIONS gives the AFL saying which records hold the other DOLs,
+            // and cards refuse READ RECORD before it. It starts a transaction, so the ATC goes up,
+            // but nothing else is sent after the records are read.
+            let data = processing_options::read_application_data(card, &fci, state)?;
+            if let Some(record) = data.records.first() {
+                let fields = tlv::parse_response(&record.data)?;
+                println!(
+                    "SFI {:02x} record {:02x}: {}",
+                    record.sfi,
+                    record.number,
+                    fields
+                        .display()
+                        .with_painter(painter)
+                        .show_pan(options.show_pan)
+                        .with_notes(&NOTES)
+                );
+            }
+            let records = data.fields;
+            let pdol = fci.get_path(&[0xa5, 0x9f38]).ok().and_then(Value::as_dol);
+            let dols = [
+                ("PDOL", pdol),
+                ("CDOL1", records.get_dol(0x8c).ok()),
+                ("CDOL2", records.get_dol(0x8d).ok()),
+                ("DDOL", records.get_dol(0x9f49).ok()),
+                ("TDOL", records.get_dol(0x97).ok()),
+            ];
+            for (name, dol) in dols {
+                let Some(dol) = dol else {
+                    println!("{}: not present", name);
+                    continue;
+                };
+                println!("{}: 0x{:x} bytes", name, dol.get_size());
+                for entry in dol.get_entries() {
+                    println!("    {}", entry);
+                }
+            }
+            Ok(())
+        }
+        Command::Capabilities => {
+            let aid = choose_application(card, options)?;
+            let options = processing_options::read_processing_options(card, &aid, state)?.fields;
+            let aip = Aip::try_from(
+                options
+                    .get(&0x82)
+                    .and_then(Value::as_binary)
+                    .context("Card did not send an AIP")?,
+            )?;
+
+            let yes_no = |supported: bool| if supported { "yes" } else { "no" };
+            println!("AIP: {}", hex::encode(aip.0));
+            println!("SDA: {}", yes_no(aip.sda_supported()));
+            println!("DDA: {}", yes_no(aip.dda_supported()));
+            println!("CDA: {}", yes_no(aip.cda_supported()));
+            println!(
+                "Cardholder verification: {}",
+                yes_no(aip.cardholder_verification_supported())
+            );
+            println!(
+                "Issuer authentication: {}",
+                yes_no(aip.issuer_authentication_supported())
+            );
+            for (tag, name) in [
+                (0x90, "Issuer public key certificate"),
+                (0x9f46, "ICC public key certificate"),
+                (0x9f2d, "ICC PIN encipherment public key certificate"),
+                (0x93, "Signed static application data"),
+            ] {
+                println!(
+                    "{}: {}",
+                    name,
+                    if options.contains_key(&tag) {
+                        "present"
+                    } else {
+                        "absent"
+                    }
+                );
+            }
+            println!(
+                "Preferred offline data authentication: {}",
+                aip.offline_authentication()
+            );
+            Ok(())
+        }
+        Command::GetKey { ref export_keys } => {
+            let show_pan = options.show_pan;
+            let aid = &choose_application(card, options)?;
 
             if aid.len() < 5 {
                 anyhow::bail!("AID too short");
             }
 
-            let (options, sda_data) =
-                processing_options::read_processing_options(&mut card, aid, &state)?;
+            let ProcessingResult {
+                fields: options,
+                sda_data,
+                ..
+            } = processing_options::read_processing_options(card, aid, state)?;
 
+            let today = chrono::Local::now().date_naive();
+            let expiry = |expiry| {
+                let note = chain::expiry_note(expiry, today);
+                if expiry < today {
+                    painter.paint(Style::Warning, note)
+                } else {
+                    painter.paint(Style::Note, note)
+                }
+            };
             let issuer_key = IssuerPublicKey::from_options(aid[..5].try_into().unwrap(), &options)?;
-            println!("{:#?}", issuer_key);
+            println!("{} ({})", issuer_key, expiry(issuer_key.expiry));
             let icc_key = ICCPublicKey::from_options(&issuer_key, &sda_data, &options)?;
-            println!("{:#?}", icc_key);
+            println!(
+                "{} ({})",
+                pan::show_pan(&icc_key, show_pan),
+                expiry(icc_key.expiry)
+            );
 
-            // Reset the card because we could be in a PIN authenticated state
-            if card.disconnect(pcsc::Disposition::ResetCard).is_err() {
-                error!("Failed to reset card, you may need to manually unplug the card");
+            if let Some(dir) = export_keys {
+                std::fs::create_dir_all(dir)
+                    .with_context(|| format!("Failed to create {}", dir.display()))?;
+                for (name, der) in [
+                    ("issuer", issuer_key.to_spki_der()),
+                    ("icc", icc_key.to_spki_der()),
+                ] {
+                    write_key(&dir.join(format!("{}.der", name)), &der)?;
+                    write_key(
+                        &dir.join(format!("{}.pem", name)),
+                        export::spki_pem(&der).as_bytes(),
+                    )?;
+                }
+                println!("Exported keys to {}", dir.display());
             }
             Ok(())
         }
         Command::TestTransaction => {
-            let mut card = get_card(&options, &context).context("Failed to connect to card")?;
-            let pse_data = pse::list_applications(&mut card, options.ppse)?;
-            let aid = &pse_data
-                .applications
-                .get(0)
-                .ok_or_else(|| anyhow::anyhow!("No applications in PSE"))?
-                .aid;
-
-            let (options, _sda_data) =
-                processing_options::read_processing_options(&mut card, aid, &state)?;
-            transaction::do_transaction(&mut card, &options, &mut state)?;
-
-            // Reset the card because we could be in a PIN authenticated state
-            if card.disconnect(pcsc::Disposition::ResetCard).is_err() {
-                error!("Failed to reset card, you may need to manually unplug the card");
+            let show_pan = options.show_pan;
+            let terminal_action_codes = ActionCodes {
+                default: options.tac_default.0,
+                denial: options.tac_denial.0,
+                online: options.tac_online.0,
+            };
+            let (aid, kernel_id) = &choose_application_with_kernel(card, options)?;
+            let (_, exponent) = currency::amount_currency(state, false);
+            println!(
+                "Amount: {}",
+                currency::format_amount(options.amount as u128, options.currency, exponent)
+            );
+            if options.ppse {
+                return contactless_transaction(card, aid, kernel_id.as_deref(), state, show_pan);
+            }
+
+            let fci = processing_options::select_application(card, aid)?;
+            let ProcessingResult {
+                fields: options,
+                sda_data,
+                ..
+            } = processing_options::read_application_data(card, &fci, state)?;
+            let aip = Aip::try_from(
+                options
+                    .get(&0x82)
+                    .and_then(Value::as_binary)
+                    .context("Card did not send an AIP")?,
+            )?;
+            let method = aip.offline_authentication();
+            println!("{}", aip);
+            println!("Using {}", method);
+
+            let mut tvr = Tvr::default();
+            let authenticated = match method {
+                OfflineAuthentication::Dda => {
+                    let sdad = transaction::do_transaction(card, &options, state)?;
+                    match authenticate_dynamic(aid, &options, &sda_data, state, &sdad) {
+                        Ok(number) => {
+                            println!("DDA verified, ICC Dynamic Number {}", hex::encode(number));
+                            Some(true)
+                        }
+                        Err(err) => {
+                            warn!("DDA failed: {:#}", err);
+                            tvr.set(Tvr::DDA_FAILED);
+                            Some(false)
+                        }
+                    }
+                }
+                OfflineAuthentication::Sda => {
+                    tvr.set(Tvr::SDA_SELECTED);
+                    match authenticate_static(aid, &options, &sda_data) {
+                        Ok(data_authentication_code) => {
+                            println!(
+                                "SDA verified, Data Authentication Code {}",
+                                hex::encode(data_authentication_code)
+                            );
+                            state.insert(0x9f45, Value::Binary(data_authentication_code.to_vec()));
+                            Some(true)
+                        }
+                        Err(err) => {
+                            warn!("SDA failed: {:#}", err);
+                            tvr.set(Tvr::SDA_FAILED);
+                            Some(false)
+                        }
+                    }
+                }
+                // Checked below on the GENERATE AC response
+                OfflineAuthentication::Cda => None,
+                OfflineAuthentication::None => {
+                    tvr.set(Tvr::OFFLINE_DATA_AUTHENTICATION_NOT_PERFORMED);
+                    None
+                }
+            };
+
+            let decision = tvr::terminal_decision(
+                &tvr,
+                &ActionCodes::from_options(&options),
+                &terminal_action_codes,
+            );
+            println!("{}", tvr);
+            println!("Terminal decision: {}", decision);
+            state.insert(0x95, Value::Binary(tvr.0.to_vec()));
+
+            let cryptogram = transaction::generate_ac(
+                card,
+                &options,
+                state,
+                decision.ac_type(),
+                method == OfflineAuthentication::Cda,
+            )?;
+            debug!("{:#?}", cryptogram);
+            let authenticated = match method {
+                // Cards don't sign an AAC, so there is nothing to check when they decline
+                OfflineAuthentication::Cda
+                    if cryptogram.cryptogram_information().ac_type
+                        == Some(exchange::AcType::Aac) =>
+                {
+                    None
+                }
+                OfflineAuthentication::Cda => {
+                    match authenticate_combined(aid, &options, &sda_data, &fci, state, &cryptogram)
+                    {
+                        Ok(ac) => {
+                            println!("CDA verified, Application Cryptogram {}", hex::encode(ac));
+                            Some(true)
+                        }
+                        Err(err) => {
+                            warn!("CDA failed: {:#}", err);
+                            tvr.set(Tvr::CDA_FAILED);
+                            state.insert(0x95, Value::Binary(tvr.0.to_vec()));
+                            println!("{}", tvr);
+                            Some(false)
+                        }
+                    }
+                }
+                _ => authenticated,
+            };
+            if let Some(iad) = &cryptogram.issuer_application_data {
+                println!("Issuer application data: {}", iad::decode_iad(iad));
+            }
+            let summary = TransactionSummary::new(
+                &options,
+                method,
+                authenticated,
+                Some(decision),
+                &cryptogram,
+            );
+            println!("{}", pan::show_pan(&summary, show_pan));
+            Ok(())
+        }
+        Command::Counters => {
+            let aid = choose_application(card, options)?;
+            processing_options::select_application(card, &aid)?;
+            for (tag, name) in [
+                (0x9f36, "Application Transaction Counter"),
+                (0x9f13, "Last Online ATC Register"),
+                (0x9f17, "PIN Try Counter"),
+            ] {
+                match transaction::get_counter(card, tag) {
+                    Ok(counter) => println!("{}: {}", name, counter),
+                    Err(err) => println!("{}: not available ({:#})", name, err),
+                }
+            }
+            Ok(())
+        }
+        Command::IssuerScript {
+            script: ScriptTemplate(ref script),
+        } => {
+            let aid = choose_application(card, options)?;
+            processing_options::select_application(card, &aid)?;
+            let result = script::run_issuer_script(card, script)?;
+            println!("{}", result);
+            println!(
+                "Issuer Script Results: {}",
+                hex::encode(result.script_results())
+            );
+            Ok(())
+        }
+        Command::Verify { json } => {
+            let aid = choose_application(card, options)?;
+            let report = verify::verify_card(card, &aid, state)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                for step in &report.steps {
+                    println!("{}", step.result.display(painter));
+                }
+            }
+            if !report.passed {
+                anyhow::bail!("Verification failed");
+            }
+            Ok(())
+        }
+        Command::Dump {
+            ref out,
+            unknown_tags,
+        } => {
+            if unknown_tags {
+                tlv::decoders::track_unknown_tags();
+            }
+            // The PSE is saved in the dump too, so pick from it instead of reading it again
+            let (pse_data, aid) = match (pse::list_applications(card, options.ppse), &options.aid) {
+                (Ok(pse_data), Some(Aid(aid))) => (Some(pse_data), aid.clone()),
+                (Ok(pse_data), None) => {
+                    let aid = pick_application(&pse_data.applications, options)?
+                        .aid
+                        .clone();
+                    (Some(pse_data), aid)
+                }
+                (Err(err), Some(Aid(aid))) => {
+                    warn!("Failed to read PSE: {:#}", err);
+                    (None, aid.clone())
+                }
+                (Err(err), None) => return Err(err),
+            };
+            let dump = dump::dump_card(card, pse_data, aid, state)?;
+            dump.save(out, options.show_pan)?;
+            println!("Saved card dump to {}", out.display());
+            // On a line of its own so terminals can pick out the link
+            if let Some(url) = dump
+                .records
+                .get(&0x5f50)
+                .and_then(Value::as_alphanumeric_special)
+            {
+                println!("Issuer URL: {}", url);
+            }
+            if unknown_tags {
+                let tags = tlv::decoders::take_unknown_tags();
+                let tags: Vec<String> = tags.iter().map(|tag| format!("0x{:04x}", tag)).collect();
+                println!(
+                    "Encountered {} unknown tags: {}",
+                    tags.len(),
+                    tags.join(", ")
+                );
             }
             Ok(())
         }
+        Command::VerifyPin {
+            ref pin,
+            enciphered,
+        } => {
+            let aid = &choose_application(card, options)?;
+
+            let ProcessingResult {
+                fields: options,
+                sda_data,
+                ..
+            } = processing_options::read_processing_options(card, aid, state)?;
+            let result = if enciphered {
+                let rid = aid.get(..5).context("AID too short")?.try_into().unwrap();
+                let issuer_key = IssuerPublicKey::from_options(rid, &options)?;
+                let key = ICCPublicKey::pin_from_options(&issuer_key, &sda_data, &options)?;
+                pin::verify_enciphered_pin(card, pin, &key)?
+            } else {
+                pin::verify_plaintext_pin(card, pin)?
+            };
+            match result {
+                PinResult::Ok => {
+                    println!("{}", result);
+                    Ok(())
+                }
+                PinResult::Incorrect { tries_left } => {
+                    if tries_left <= 1 {
+                        warn!(
+                            "Only {} PIN tries left before the PIN is blocked!",
+                            tries_left
+                        );
+                    }
+                    anyhow::bail!("{}", result)
+                }
+                PinResult::Blocked => anyhow::bail!("{}", result),
+            }
+        }
+    }
+}
+
+fn write_key(path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    std::fs::write(path, data).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Run the contactless flow for an application picked from the PPSE, with the kernel from its
+/// Kernel Identifier if it had one
+fn contactless_transaction<C: CardTransport + ?Sized>(
+    card: &mut C,
+    aid: &[u8],
+    kernel_id: Option<&[u8]>,
+    state: &mut OptionsMap,
+    show_pan: bool,
+) -> anyhow::Result<()> {
+    let kernel = Kernel::from_id(kernel_id, aid);
+    println!("Using contactless {}", kernel);
+    let result = contactless::do_contactless_transaction(card, aid, kernel, state)?;
+    let aip = Aip::try_from(
+        result
+            .processing
+            .fields
+            .get(&0x82)
+            .and_then(Value::as_binary)
+            .context("Card did not send an AIP")?,
+    )?;
+    println!("{}", aip);
+    let cryptogram = &result.cryptogram;
+    debug!("{:#?}", cryptogram);
+    if let Some(iad) = &cryptogram.issuer_application_data {
+        println!("Issuer application data: {}", iad::decode_iad(iad));
+    }
+    // Offline data authentication and terminal action analysis aren't done for contactless yet
+    let summary = TransactionSummary::new(
+        &result.processing.fields,
+        aip.offline_authentication(),
+        None,
+        None,
+        cryptogram,
+    );
+    println!("{}", pan::show_pan(&summary, show_pan));
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+struct Aid(Vec<u8>);
+
+impl FromStr for Aid {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let aid = hex::decode(s).context("AID is not valid hex")?;
+        // A RID is 5 bytes and the PIX at most 11 (ISO 7816-5)
+        if !(5..=16).contains(&aid.len()) {
+            anyhow::bail!("AID must be 5 to 16 bytes, got {}", aid.len());
+        }
+        Ok(Aid(aid))
     }
 }
 
+/// Contents of an Issuer Script Template given on the command line
+#[derive(Debug, Clone)]
+struct ScriptTemplate(tlv::FieldMap);
+
+impl FromStr for ScriptTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let raw = hex::decode(s).context("Issuer script is not valid hex")?;
+        match tlv::read_field(&raw).context("Failed to decode issuer script")? {
+            (0x71 | 0x72, Value::Template(script)) => Ok(ScriptTemplate(script)),
+            (tag, _) => anyhow::bail!("Tag 0x{:04x} is not an Issuer Script Template", tag),
+        }
+    }
+}
+
+/// Recover the issuer key and check the signed static data, returning the Data Authentication Code
+fn authenticate_static(
+    aid: &[u8],
+    options: &tlv::FieldMap,
+    sda_data: &[u8],
+) -> anyhow::Result<[u8; 2]> {
+    let rid = aid.get(..5).context("AID too short")?.try_into().unwrap();
+    let issuer_key = IssuerPublicKey::from_options(rid, options)?;
+    Ok(verify_sda(&issuer_key, options, sda_data)?)
+}
+
+/// Recover both keys and check the response to INTERNAL AUTHENTICATE, returning the ICC Dynamic
+/// Number
+fn authenticate_dynamic(
+    aid: &[u8],
+    options: &tlv::FieldMap,
+    sda_data: &[u8],
+    state: &OptionsMap,
+    sdad: &[u8],
+) -> anyhow::Result<Vec<u8>> {
+    let rid = aid.get(..5).context("AID too short")?.try_into().unwrap();
+    let issuer_key = IssuerPublicKey::from_options(rid, options)?;
+    let icc_key = ICCPublicKey::from_options(&issuer_key, sda_data, options)?;
+    let ddol_data = options.get_dol(0x9f49)?.encode_bare(state);
+    Ok(verify_dda(&icc_key, sdad, &ddol_data)?)
+}
+
+/// Recover both keys and check the Signed Dynamic Application Data in a GENERATE AC response
+/// requested with CDA, returning the Application Cryptogram. A response without it fails.
+fn authenticate_combined(
+    aid: &[u8],
+    options: &tlv::FieldMap,
+    sda_data: &[u8],
+    fci: &tlv::FieldMap,
+    state: &OptionsMap,
+    cryptogram: &ApplicationCryptogram,
+) -> anyhow::Result<[u8; 8]> {
+    let sdad = cryptogram
+        .signed_dynamic_data
+        .as_deref()
+        .context("Card did not return signed dynamic data")?;
+    let rid = aid.get(..5).context("AID too short")?.try_into().unwrap();
+    let issuer_key = IssuerPublicKey::from_options(rid, options)?;
+    let icc_key = ICCPublicKey::from_options(&issuer_key, sda_data, options)?;
+
+    let pdol_data = fci
+        .get_path(&[0xa5, 0x9f38])
+        .ok()
+        .and_then(Value::as_dol)
+        .map(|pdol| pdol.encode_bare(state))
+        .unwrap_or_default();
+    let cdol_data = options.get_dol(0x8c)?.encode_bare(state);
+    let transaction_data =
+        transaction::cda_transaction_data(&pdol_data, &cdol_data, &cryptogram.response_data)?;
+    let unpredictable_number = state
+        .get(&0x9f37)
+        .and_then(Value::as_binary)
+        .unwrap_or_default();
+    Ok(verify_cda(
+        &icc_key,
+        sdad,
+        unpredictable_number,
+        cryptogram.cid,
+        &transaction_data,
+    )?)
+}
+
+/// Use the AID given with --aid if present, otherwise the first application in the PSE
+fn choose_application<C: CardTransport + ?Sized>(
+    card: &mut C,
+    options: &Options,
+) -> anyhow::Result<Vec<u8>> {
+    choose_application_with_kernel(card, options).map(|(aid, _)| aid)
+}
+
+/// [`choose_application`], along with the Kernel Identifier (0x9f2a) of the application if it was
+/// picked from the PPSE
+fn choose_application_with_kernel<C: CardTransport + ?Sized>(
+    card: &mut C,
+    options: &Options,
+) -> anyhow::Result<(Vec<u8>, Option<Vec<u8>>)> {
+    if let Some(Aid(aid)) = &options.aid {
+        return Ok((aid.clone(), None));
+    }
+
+    let applications = pse::list_applications(card, options.ppse)?.applications;
+    let application = pick_application(&applications, options)?;
+    Ok((application.aid.clone(), application.kernel_id.clone()))
+}
+
+/// Pick one of the applications read from the PSE by `--app` or `--select`, asking if neither was
+/// given and there is more than one
+fn pick_application<'a>(
+    applications: &'a [pse::ApplicationTemplate],
+    options: &Options,
+) -> anyhow::Result<&'a pse::ApplicationTemplate> {
+    if applications.is_empty() {
+        anyhow::bail!("No applications in PSE");
+    }
+
+    if let Some(app) = &options.app {
+        let needle = app.to_lowercase();
+        let mut matching: Vec<_> = applications
+            .iter()
+            .filter(|application| application.label.to_lowercase().contains(&needle))
+            .collect();
+        return match matching.len() {
+            0 => anyhow::bail!(
+                "No application matches {:?}, available: {}",
+                app,
+                list_labels(applications)
+            ),
+            1 => Ok(matching.remove(0)),
+            _ => anyhow::bail!(
+                "Multiple applications match {:?}: {}",
+                app,
+                list_labels(matching)
+            ),
+        };
+    }
+
+    let index = match options.select {
+        Some(index) if index >= applications.len() => anyhow::bail!(
+            "No application at index {}, only {} applications found",
+            index,
+            applications.len()
+        ),
+        Some(index) => index,
+        None if applications.len() > 1 && std::io::stdout().is_terminal() => {
+            prompt_application(applications)?
+        }
+        None => 0,
+    };
+    Ok(&applications[index])
+}
+
+fn list_labels<'a>(applications: impl IntoIterator<Item = &'a pse::ApplicationTemplate>) -> String {
+    applications
+        .into_iter()
+        .map(|application| format!("{:?}", application.label))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn prompt_application(applications: &[pse::ApplicationTemplate]) -> anyhow::Result<usize> {
+    println!("Card has multiple applications:");
+    for (idx, application) in applications.iter().enumerate() {
+        println!(
+            "{}: {} ({}) priority {}",
+            idx,
+            application.label,
+            hex::encode(&application.aid),
+            application
+                .priority
+                .map_or("none".to_string(), |priority| priority.to_string())
+        );
+    }
+
+    loop {
+        print!("Select an application [0]: ");
+        std::io::stdout().flush()?;
+        let mut line = String::new();
+        if std::io::stdin().read_line(&mut line)? == 0 {
+            anyhow::bail!("No application selected");
+        }
+        match line.trim() {
+            "" => return Ok(0),
+            choice => match choice.parse::<usize>() {
+                Ok(index) if index < applications.len() => return Ok(index),
+                _ => println!("Enter a number from 0 to {}", applications.len() - 1),
+            },
+        }
+    }
+}
+
+fn decode(
+    hex: Option<&str>,
+    file: Option<&Path>,
+    json: bool,
+    tree: bool,
+    painter: Painter,
+    show_pan: bool,
+) -> anyhow::Result<()> {
+    let input = match (hex, file) {
+        (Some(hex), _) => hex.to_string(),
+        (None, Some(file)) => std::fs::read_to_string(file)
+            .with_context(|| format!("Failed to read {}", file.display()))?,
+        (None, None) => anyhow::bail!("Either hex data or --file is required"),
+    };
+    let input: String = input.split_whitespace().collect();
+    let raw = hex::decode(input).context("Input is not valid hex")?;
+
+    let fields = tlv::decoders::template(&raw).context("Failed to decode TLV data")?;
+    if tree {
+        let tree = tlv::serialize::fields_to_json_tree(&raw, show_pan)
+            .context("Failed to decode TLV data")?;
+        println!("{}", serde_json::to_string_pretty(&tree)?);
+    } else if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&fields.serialize().show_pan(show_pan))?
+        );
+    } else {
+        println!(
+            "{}",
+            fields
+                .display()
+                .with_painter(painter)
+                .show_pan(show_pan)
+                .with_notes(&NOTES)
+        );
+    }
+    Ok(())
+}
+
+fn encode(json: Option<&str>, file: Option<&Path>) -> anyhow::Result<()> {
+    let input = match (json, file) {
+        (Some(json), _) => json.to_string(),
+        (None, Some(file)) => std::fs::read_to_string(file)
+            .with_context(|| format!("Failed to read {}", file.display()))?,
+        (None, None) => anyhow::bail!("Either JSON data or --file is required"),
+    };
+    let input: serde_json::Value =
+        serde_json::from_str(&input).context("Input is not valid JSON")?;
+
+    let fields = tlv::serialize::fields_from_json(&input).context("Failed to encode TLV data")?;
+    println!("{}", hex::encode(fields.encode()));
+    Ok(())
+}
+
 fn list_readers(context: &pcsc::Context) -> anyhow::Result<()> {
     let readers = context
         .list_readers_owned()
@@ -131,16 +1258,137 @@ fn list_readers(context: &pcsc::Context) -> anyhow::Result<()> {
     Ok(())
 }
 
-fn get_card(options: &Options, context: &pcsc::Context) -> anyhow::Result<pcsc::Card> {
+/// Describe the card in a reader from its state as reported by PC/SC
+fn reader_state_name(state: pcsc::State) -> &'static str {
+    if state.intersects(pcsc::State::UNKNOWN | pcsc::State::IGNORE) {
+        "removed"
+    } else if state.contains(pcsc::State::MUTE) {
+        "mute card"
+    } else if state.contains(pcsc::State::PRESENT) {
+        if state.intersects(pcsc::State::INUSE | pcsc::State::EXCLUSIVE) {
+            "card present, in use"
+        } else {
+            "card present"
+        }
+    } else if state.contains(pcsc::State::EMPTY) {
+        "empty"
+    } else if state.contains(pcsc::State::UNAVAILABLE) {
+        "unavailable"
+    } else {
+        "unknown"
+    }
+}
+
+/// Print reader insertions and removals and card state changes until interrupted
+fn watch_readers(context: &pcsc::Context) -> anyhow::Result<()> {
+    // The PnP pseudo-reader changes whenever a reader is added or removed
+    let mut reader_states = vec![pcsc::ReaderState::new(
+        pcsc::PNP_NOTIFICATION(),
+        pcsc::State::UNAWARE,
+    )];
+    println!("Watching readers, press Ctrl-C to stop");
+    loop {
+        reader_states.retain(|reader| {
+            !reader
+                .event_state()
+                .intersects(pcsc::State::UNKNOWN | pcsc::State::IGNORE)
+        });
+        let readers = match context.list_readers_owned() {
+            Ok(readers) => readers,
+            Err(pcsc::Error::NoReadersAvailable) => Vec::new(),
+            Err(err) => return Err(err).context("Failed to find readers"),
+        };
+        for reader in readers {
+            if !reader_states.iter().any(|state| state.name() == &*reader) {
+                reader_states.push(pcsc::ReaderState::new(reader, pcsc::State::UNAWARE));
+            }
+        }
+        for reader in &mut reader_states {
+            reader.sync_current_state();
+        }
+
+        context
+            .get_status_change(None, &mut reader_states)
+            .context("Failed to wait for reader changes")?;
+        for reader in &reader_states {
+            if reader.name() == pcsc::PNP_NOTIFICATION() {
+                continue;
+            }
+            let old = reader.current_state();
+            let new = reader.event_state();
+            if old == pcsc::State::UNAWARE {
+                println!(
+                    "{}: added, {}",
+                    reader.name().to_string_lossy(),
+                    reader_state_name(new)
+                );
+            } else if reader_state_name(old) != reader_state_name(new) {
+                println!(
+                    "{}: {}",
+                    reader.name().to_string_lossy(),
+                    reader_state_name(new)
+                );
+            }
+        }
+    }
+}
+
+/// A connected card that is reset when dropped, since we could have left it in a PIN
+/// authenticated state. This also covers errors that return early.
+struct CardGuard(Option<pcsc::Card>);
+
+impl CardTransport for CardGuard {
+    fn transmit<'buf>(
+        &mut self,
+        command: &[u8],
+        recieve_buffer: &'buf mut [u8],
+    ) -> Result<&'buf [u8], exchange::ExchangeError> {
+        // Only taken in drop
+        CardTransport::transmit(self.0.as_mut().unwrap(), command, recieve_buffer)
+    }
+}
+
+impl Drop for CardGuard {
+    fn drop(&mut self) {
+        let Some(card) = self.0.take() else {
+            return;
+        };
+        if card.disconnect(pcsc::Disposition::ResetCard).is_err() {
+            error!("Failed to reset card, you may need to manually unplug the card");
+        }
+    }
+}
+
+fn get_card(options: &Options, context: &pcsc::Context) -> anyhow::Result<CardGuard> {
     let readers = context
         .list_readers_owned()
         .expect("Failed to list readers");
-    let Some(reader) = readers.get(options.reader) else {
-        anyhow::bail!(
-            "No reader at index {}, only {} readers found",
-            options.reader,
-            readers.len()
-        );
+    let reader = if let Some(name) = &options.reader_name {
+        let lowercase_name = name.to_lowercase();
+        let matching: Vec<_> = readers
+            .iter()
+            .filter(|reader| {
+                reader
+                    .to_string_lossy()
+                    .to_lowercase()
+                    .contains(&lowercase_name)
+            })
+            .collect();
+        match matching[..] {
+            [reader] => reader,
+            [] => anyhow::bail!("No reader name contains \"{}\", see list-readers", name),
+            _ => anyhow::bail!("Multiple readers match \"{}\": {:?}", name, matching),
+        }
+    } else {
+        let Some(reader) = readers.get(options.reader) else {
+            anyhow::bail!(
+                "No reader at index {}, only {} readers found",
+                options.reader,
+                readers.len()
+            );
+        };
+        reader
     };
-    Ok(context.connect(reader, pcsc::ShareMode::Exclusive, pcsc::Protocols::ANY)?)
+    let card = context.connect(reader, pcsc::ShareMode::Exclusive, pcsc::Protocols::ANY)?;
+    Ok(CardGuard(Some(card)))
 }
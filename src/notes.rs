@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+
+use crate::{
+    auc, currency, cvm, iban, service_code, terminal,
+    tlv::{FieldMap, NoteFn, NoteRegistry, Value},
+    transaction, tvr,
+};
+
+/// Names of set flags, or nothing if there aren't any
+fn join_flags(flags: Vec<&str>) -> Option<String> {
+    (!flags.is_empty()).then(|| flags.join(", "))
+}
+
+/// Whether `url` starts with a scheme such as `https:`, see RFC 3986 section 3.1
+fn has_url_scheme(url: &str) -> bool {
+    let Some((scheme, _)) = url.split_once(':') else {
+        return false;
+    };
+    scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+}
+
+/// An amount with the decimal point placed by the currency exponent in `fields`, and the
+/// currency if there is one
+fn amount(tag: u16, value: &Value, fields: &FieldMap) -> Option<String> {
+    let minor_units = match value {
+        Value::Numeric(amount) => *amount,
+        Value::Binary(amount) if amount.len() <= 16 => amount
+            .iter()
+            .fold(0, |total, &byte| total << 8 | byte as u128),
+        _ => return None,
+    };
+    let (currency, exponent) = currency::amount_currency(fields, tag == 0x9f3a);
+    Some(match currency {
+        Some(currency) => currency::format_amount(minor_units, currency, exponent),
+        None => currency::format_minor_units(minor_units, exponent),
+    })
+}
+
+fn tvr_flags(_: u16, value: &Value, _: &FieldMap) -> Option<String> {
+    join_flags(tvr::flag_names(value.as_binary()?))
+}
+
+fn cid(_: u16, value: &Value, _: &FieldMap) -> Option<String> {
+    let &[cid] = value.as_binary()? else {
+        return None;
+    };
+    Some(transaction::decode_cid(cid).to_string())
+}
+
+fn auc(_: u16, value: &Value, _: &FieldMap) -> Option<String> {
+    join_flags(auc::Auc::try_from(value.as_binary()?).ok()?.flags())
+}
+
+fn cvm_results(_: u16, value: &Value, _: &FieldMap) -> Option<String> {
+    let results = cvm::CvmResults::try_from(value.as_binary()?).ok()?;
+    Some(results.to_string())
+}
+
+fn additional_capabilities(_: u16, value: &Value, _: &FieldMap) -> Option<String> {
+    join_flags(terminal::additional_capabilities_names(value.as_binary()?))
+}
+
+fn url(_: u16, value: &Value, _: &FieldMap) -> Option<String> {
+    let url = value.as_alphanumeric_special()?;
+    (!has_url_scheme(url)).then(|| "no URL scheme".to_string())
+}
+
+// Kept as binary so a malformed IBAN still shows the raw bytes
+fn iban(_: u16, value: &Value, _: &FieldMap) -> Option<String> {
+    let iban = iban::parse_iban(value.as_binary()?).ok()?;
+    Some(if iban.checksum_valid {
+        iban.to_string()
+    } else {
+        format!("{}, invalid checksum", iban)
+    })
+}
+
+fn code(value: &Value) -> Option<u16> {
+    (*value.as_numeric()?).try_into().ok()
+}
+
+fn currency(_: u16, value: &Value, _: &FieldMap) -> Option<String> {
+    currency::currency_name(code(value)?).map(|(name, _)| name.to_string())
+}
+
+fn country(_: u16, value: &Value, _: &FieldMap) -> Option<String> {
+    currency::country_name(code(value)?).map(str::to_string)
+}
+
+fn service_code(_: u16, value: &Value, _: &FieldMap) -> Option<String> {
+    Some(service_code::decode_service_code(code(value)?).to_string())
+}
+
+lazy_static! {
+    /// Meanings of the values this crate knows how to decode, for
+    /// [`crate::tlv::FieldMapDisplay::with_notes`]
+    pub static ref NOTES: NoteRegistry = HashMap::from([
+        (0x0081, amount as NoteFn),
+        (0x9f02, amount),
+        (0x9f03, amount),
+        (0x9f3a, amount),
+        (0x0095, tvr_flags),
+        (0x9f0d, tvr_flags),
+        (0x9f0e, tvr_flags),
+        (0x9f0f, tvr_flags),
+        (0x9f27, cid),
+        (0x9f07, auc),
+        (0x9f34, cvm_results),
+        (0x9f40, additional_capabilities),
+        (0x5f50, url),
+        (0x5f53, iban),
+        (0x5f2a, currency),
+        (0x9f3c, currency),
+        (0x9f42, currency),
+        (0x5f28, country),
+        (0x9f1a, country),
+        (0x5f30, service_code),
+    ]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tlv::{decoders, FieldMapExt};
+
+    fn display(raw: &[u8]) -> String {
+        decoders::template(raw)
+            .unwrap()
+            .display()
+            .with_notes(&NOTES)
+            .to_string()
+    }
+
+    #[test]
+    fn test_code_names() {
+        let displayed = display(b"\x5f\x2a\x02\x08\x40\x5f\x28\x02\x08\x26");
+        assert!(displayed.contains("0x5f2a (\"Transaction Currency Code\") => n840 (USD),"));
+        assert!(displayed.contains("0x5f28 (\"Issuer Country Code\") => n826 (United Kingdom),"));
+
+        assert!(display(b"\x5f\x30\x02\x02\x01").contains(
+            "n201 (international interchange, use chip where feasible, normal authorization, \
+             no restrictions),"
+        ));
+        assert!(display(b"\x9f\x0e\x05\x00\x10\x00\x00\x00")
+            .contains("(Requested service not allowed for card product)"));
+        assert!(display(b"\x9f\x40\x05\x60\x00\x00\x00\x00").contains("(Goods, Services)"));
+
+        assert!(display(
+            b"\x9f\x02\x06\x00\x00\x00\x00\x12\x34\x5f\x2a\x02\x09\x78\x5f\x36\x01\x02"
+        )
+        .contains("n1234 (€12.34 EUR),"));
+        // Without an exponent the amount still gets 2 decimal places
+        assert!(display(b"\x9f\x02\x06\x00\x00\x00\x00\x12\x34").contains("n1234 (12.34),"));
+
+        // Notes are only shown when asked for
+        let fields = decoders::template(&b"\x5f\x2a\x02\x08\x40"[..]).unwrap();
+        assert!(fields.display().to_string().contains("=> n840,"));
+    }
+
+    #[test]
+    fn test_url_scheme() {
+        assert!(display(b"\x5f\x50\x13https://example.com").contains("=> https://example.com,"));
+        assert!(display(b"\x5f\x50\x0bexample.com").contains("=> example.com (no URL scheme),"));
+    }
+
+    #[test]
+    fn test_iban() {
+        assert!(display(b"\x5f\x53\x16GB83WEST12345698765432")
+            .contains("(GB83 WEST 1234 5698 7654 32, invalid checksum),"));
+    }
+}
@@ -0,0 +1,202 @@
+use std::fmt::{self, Display};
+
+use crate::tlv::Value;
+
+/// Tags holding a PAN: Track 1 Data (0x56), Track 2 Equivalent Data (0x57), the Application PAN
+/// (0x5a), and Track 2 Data (0x9f6b)
+pub const PAN_TAGS: &[u16] = &[0x56, 0x57, 0x5a, 0x9f6b];
+
+/// Range of the digits to hide in a PAN with `len` digits, all but the first 6 and last 4
+fn masked_range(len: usize) -> Option<std::ops::Range<usize>> {
+    (len > 10).then(|| 6..len - 4)
+}
+
+/// Mask all but the first 6 and last 4 digits of `pan`. Any characters after the digits (e.g. the
+/// rest of Track 2) are left alone. PANs are masked by default so they don't end up in logs or
+/// scrollback.
+pub fn mask_pan(pan: &str) -> String {
+    let digits = pan
+        .find(|ch: char| !ch.is_ascii_digit())
+        .unwrap_or(pan.len());
+    let Some(range) = masked_range(digits) else {
+        return pan.to_string();
+    };
+
+    let mut masked = String::with_capacity(pan.len());
+    masked.push_str(&pan[..range.start]);
+    masked.extend(std::iter::repeat_n('*', range.len()));
+    masked.push_str(&pan[range.end..]);
+    masked
+}
+
+/// Format a PAN given as one digit per byte, masked as in [`mask_pan`] unless `show`
+pub fn display_pan(pan: &[u8], show: bool) -> String {
+    let pan: String = pan
+        .iter()
+        .map(|&digit| char::from_digit(digit as u32, 10).unwrap_or('?'))
+        .collect();
+    if show {
+        pan
+    } else {
+        mask_pan(&pan)
+    }
+}
+
+/// Track 1 Data as hex, with the hidden PAN digits as `**`. Track 1 is ASCII: a format code, the
+/// PAN, then `^` and the cardholder name.
+fn mask_track1_hex(track1: &[u8]) -> String {
+    let start = track1
+        .iter()
+        .position(u8::is_ascii_digit)
+        .unwrap_or(track1.len());
+    let digits = track1[start..]
+        .iter()
+        .take_while(|byte| byte.is_ascii_digit())
+        .count();
+    let hidden = masked_range(digits).map_or(0..0, |range| start + range.start..start + range.end);
+    track1
+        .iter()
+        .enumerate()
+        .map(|(i, byte)| {
+            if hidden.contains(&i) {
+                "**".to_string()
+            } else {
+                format!("{:02x}", byte)
+            }
+        })
+        .collect()
+}
+
+/// The value of a PAN-bearing `tag` as digits (0x5a) or hex (the tracks) with the PAN masked, or
+/// `None` for tags without a PAN
+pub fn masked_value(tag: u16, value: &Value) -> Option<String> {
+    match (tag, value) {
+        (0x5a, Value::DigitString(pan)) => Some(display_pan(pan, false)),
+        (0x57 | 0x9f6b, Value::Binary(track2)) => Some(mask_pan(&hex::encode(track2))),
+        (0x56, Value::Binary(track1)) => Some(mask_track1_hex(track1)),
+        _ => None,
+    }
+}
+
+/// Replace every occurrence of `pan`, as one digit per byte, in `text` with the masked PAN. Since
+/// BCD is the digits as hex, this also masks the PAN in hex dumps of raw records.
+pub fn mask_pan_in(text: &str, pan: &[u8]) -> String {
+    let digits = display_pan(pan, true);
+    if masked_range(digits.len()).is_none() {
+        return text.to_string();
+    }
+    text.replace(&digits, &mask_pan(&digits))
+}
+
+/// Types whose output contains a PAN, displayed masked unless [`show_pan`] says otherwise
+pub trait DisplayPan {
+    fn fmt_pan(&self, f: &mut fmt::Formatter<'_>, show_pan: bool) -> fmt::Result;
+}
+
+/// Displays a [`DisplayPan`] with the full PAN if `show` is set
+pub struct ShowPan<'a, T: ?Sized> {
+    value: &'a T,
+    show: bool,
+}
+
+impl<T: DisplayPan + ?Sized> Display for ShowPan<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.value.fmt_pan(f, self.show)
+    }
+}
+
+/// Display `value` with its PAN masked unless `show`
+pub fn show_pan<T: DisplayPan + ?Sized>(value: &T, show: bool) -> ShowPan<'_, T> {
+    ShowPan { value, show }
+}
+
+/// Check the Luhn (mod 10) check digit of a PAN given as one digit per byte
+pub fn luhn_valid(pan: &[u8]) -> bool {
+    if pan.len() < 2 || pan.iter().any(|&digit| digit > 9) {
+        return false;
+    }
+
+    let sum: u32 = pan
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(idx, &digit)| {
+            let digit = digit as u32;
+            // Double every second digit from the right, starting with the one left of the check
+            // digit
+            if idx % 2 == 1 {
+                let doubled = digit * 2;
+                doubled / 10 + doubled % 10
+            } else {
+                digit
+            }
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digits(s: &str) -> Vec<u8> {
+        s.bytes().map(|b| b - b'0').collect()
+    }
+
+    #[test]
+    fn test_luhn_valid() {
+        assert!(luhn_valid(&digits("4111111111111111")));
+        assert!(luhn_valid(&digits("5555555555554444")));
+        assert!(luhn_valid(&digits("378282246310005")));
+        assert!(luhn_valid(&digits("4916550000000009")));
+        assert!(!luhn_valid(&digits("4111111111111112")));
+        assert!(!luhn_valid(&digits("4916550000000002")));
+        assert!(!luhn_valid(&[4]));
+        assert!(!luhn_valid(&[4, 1, 10]));
+    }
+
+    #[test]
+    fn test_mask_pan() {
+        assert_eq!(mask_pan("4916550000000009"), "491655******0009");
+        assert_eq!(
+            mask_pan("4916550000000009d25122010000"),
+            "491655******0009d25122010000"
+        );
+        assert_eq!(mask_pan("4916550000"), "4916550000");
+        assert_eq!(
+            display_pan(&digits("378282246310005"), false),
+            "378282*****0005"
+        );
+        assert_eq!(
+            display_pan(&digits("378282246310005"), true),
+            "378282246310005"
+        );
+    }
+
+    #[test]
+    fn test_masked_value() {
+        let pan = digits("4916550000000009");
+        assert_eq!(
+            masked_value(0x5a, &Value::DigitString(pan.clone())).unwrap(),
+            "491655******0009"
+        );
+        assert_eq!(
+            masked_value(
+                0x57,
+                &Value::Binary(hex::decode("4916550000000009d2512201").unwrap())
+            )
+            .unwrap(),
+            "491655******0009d2512201"
+        );
+        assert_eq!(
+            masked_value(0x56, &Value::Binary(b"B4916550000000009^DOE".to_vec())).unwrap(),
+            "42343931363535************303030395e444f45"
+        );
+        assert_eq!(masked_value(0x50, &Value::Binary(pan.clone())), None);
+
+        assert_eq!(
+            mask_pan_in("5a0849165500000000095f2403", &pan),
+            "5a08491655******00095f2403"
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use std::fmt::Display;
+
+use anyhow::Context;
+
+use crate::{
+    crypto::{chain::ICCPublicKey, encipher::encipher_pin},
+    exchange::{exchange, ADPUCommand, CardTransport, ExchangeError},
+};
+
+/// Build a plaintext offline PIN block, see EMV 4.3 Book 3 section 6.5.12
+pub fn plaintext_pin_block(pin: &str) -> anyhow::Result<[u8; 8]> {
+    if !(4..=12).contains(&pin.len()) {
+        anyhow::bail!("PIN must be 4 to 12 digits, got {}", pin.len());
+    }
+
+    let digits = pin
+        .chars()
+        .map(|ch| ch.to_digit(10).map(|digit| digit as u8))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(|| anyhow::anyhow!("PIN must only contain digits"))?;
+
+    // Control field 0x2 followed by the PIN length, then the digits padded with 0xf
+    let mut block = [0xff; 8];
+    block[0] = 0x20 | digits.len() as u8;
+    for (dest, pair) in block[1..].iter_mut().zip(digits.chunks(2)) {
+        *dest = match pair {
+            [single] => single << 4 | 0x0f,
+            [higher, lower] => higher << 4 | lower,
+            [..] => unreachable!(), // slice::chunks(2) cannot return any other sizes
+        }
+    }
+
+    Ok(block)
+}
+
+pub fn verify_plaintext_pin<C: CardTransport + ?Sized>(
+    card: &mut C,
+    pin: &str,
+) -> anyhow::Result<PinResult> {
+    let pin_block = plaintext_pin_block(pin)?;
+    let (_, sw) = exchange(card, &ADPUCommand::verify_plaintext_pin(&pin_block))?;
+    check_verify_sw(sw)
+}
+
+/// Verify the PIN offline, enciphered with `key`. This should be the key from
+/// [`ICCPublicKey::pin_from_options`]. See EMV 4.3 Book 2 section 7
+pub fn verify_enciphered_pin<C: CardTransport + ?Sized>(
+    card: &mut C,
+    pin: &str,
+    key: &ICCPublicKey,
+) -> anyhow::Result<PinResult> {
+    let pin_block = plaintext_pin_block(pin)?;
+
+    let (challenge, sw) = exchange(card, &ADPUCommand::get_challenge())?;
+    ExchangeError::check_sw(sw).context("Failure returned by card while running GET CHALLENGE")?;
+    let challenge: [u8; 8] = challenge.as_slice().try_into().map_err(|_| {
+        anyhow::anyhow!(
+            "GET CHALLENGE returned {} bytes instead of 8",
+            challenge.len()
+        )
+    })?;
+
+    let enciphered = encipher_pin(key, &pin_block, &challenge, &mut rand::thread_rng())?;
+    let (_, sw) = exchange(card, &ADPUCommand::verify_enciphered_pin(&enciphered))?;
+    check_verify_sw(sw)
+}
+
+/// Outcome of VERIFY, see EMV 4.3 Book 3 section 6.5.12.4
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PinResult {
+    Ok,
+    Incorrect { tries_left: u8 },
+    Blocked,
+}
+
+impl Display for PinResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PinResult::Ok => write!(f, "PIN verified"),
+            PinResult::Incorrect { tries_left } => {
+                write!(f, "PIN incorrect, {} tries left", tries_left)
+            }
+            PinResult::Blocked => write!(f, "PIN blocked"),
+        }
+    }
+}
+
+/// Interpret the status word returned by VERIFY. Status words that don't say anything about the
+/// PIN are errors.
+pub fn decode_verify_sw(sw: u16) -> Result<PinResult, ExchangeError> {
+    match sw {
+        0x9000 => Ok(PinResult::Ok),
+        0x63c0..=0x63cf => Ok(PinResult::Incorrect {
+            tries_left: (sw & 0x000f) as u8,
+        }),
+        // Authentication method blocked, or the PIN Try Counter is already 0
+        0x6983 | 0x6984 => Ok(PinResult::Blocked),
+        sw => Err(ExchangeError::StatusWord(sw)),
+    }
+}
+
+fn check_verify_sw(sw: u16) -> anyhow::Result<PinResult> {
+    decode_verify_sw(sw).context("Failure returned by card while verifying PIN")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plaintext_pin_block() {
+        assert_eq!(
+            plaintext_pin_block("1234").unwrap(),
+            [0x24, 0x12, 0x34, 0xff, 0xff, 0xff, 0xff, 0xff]
+        );
+        assert_eq!(
+            plaintext_pin_block("123456789012").unwrap(),
+            [0x2c, 0x12, 0x34, 0x56, 0x78, 0x90, 0x12, 0xff]
+        );
+        assert_eq!(
+            plaintext_pin_block("12345").unwrap(),
+            [0x25, 0x12, 0x34, 0x5f, 0xff, 0xff, 0xff, 0xff]
+        );
+    }
+
+    #[test]
+    fn test_decode_verify_sw() {
+        assert_eq!(decode_verify_sw(0x9000), Ok(PinResult::Ok));
+        assert_eq!(
+            decode_verify_sw(0x63c2),
+            Ok(PinResult::Incorrect { tries_left: 2 })
+        );
+        assert_eq!(decode_verify_sw(0x6983), Ok(PinResult::Blocked));
+        assert_eq!(
+            decode_verify_sw(0x6a80),
+            Err(ExchangeError::StatusWord(0x6a80))
+        );
+    }
+
+    #[test]
+    fn test_plaintext_pin_block_invalid() {
+        assert!(plaintext_pin_block("123").is_err());
+        assert!(plaintext_pin_block("1234567890123").is_err());
+        assert!(plaintext_pin_block("12a4").is_err());
+    }
+}
@@ -1,23 +1,42 @@
 use anyhow::Context;
-use log::{debug, info};
+use log::{debug, info, warn};
+use serde::Serialize;
 
 use crate::{
-    exchange::{exchange, ADPUCommand},
-    tlv::{self, DecodeError, FieldMap, FieldMapExt, OptionsMap, Value},
+    afl::Afl,
+    exchange::{exchange, is_record_not_found, ADPUCommand, CardTransport, ExchangeError},
+    tlv::{self, DecodeError, FieldMap, FieldMapExt, OptionsMap, TagMap, Value},
 };
 
-pub fn read_processing_options(
-    card: &mut pcsc::Card,
+/// A record read with READ RECORD, exactly as the card returned it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Record {
+    pub sfi: u8,
+    pub number: u8,
+    /// The whole response, including the record template tag and length
+    #[serde(with = "hex::serde")]
+    pub data: Vec<u8>,
+}
+
+/// Everything read from an application by [`read_application_data`]
+#[derive(Debug, Clone)]
+pub struct ProcessingResult {
+    /// The AIP, AFL, and the fields from every record
+    pub fields: FieldMap,
+    /// Static data to be authenticated, see Book 3 section 10.3
+    pub sda_data: Vec<u8>,
+    /// Every record in the order the AFL lists them
+    pub records: Vec<Record>,
+}
+
+/// Select the application `aid` and return its File Control Information template
+pub fn select_application<C: CardTransport + ?Sized>(
+    card: &mut C,
     aid: &[u8],
-    state: &OptionsMap,
-) -> anyhow::Result<(FieldMap, Vec<u8>)> {
+) -> anyhow::Result<FieldMap> {
     let (ats, sw) = exchange(card, &ADPUCommand::select(aid))?;
-    if sw != 0x9000 {
-        anyhow::bail!(
-            "Failure returned by card while selecting payment app: 0x{:04x}",
-            sw
-        );
-    }
+    ExchangeError::check_data_sw(sw, &ats, card.fail_on_warning())
+        .context("Failure returned by card while selecting payment app")?;
 
     let (ats_tag, ats_value) = tlv::read_field(&ats)?;
     info!(
@@ -25,10 +44,60 @@ pub fn read_processing_options(
         aid, ats_tag, ats_value
     );
 
-    let ats_map = ats_value
-        .as_template()
-        .ok_or_else(|| anyhow::anyhow!("ATS response was not a map!"))?;
+    ats_value
+        .into_template()
+        .ok_or_else(|| anyhow::anyhow!("ATS response was not a map!"))
+}
+
+pub fn read_processing_options<C: CardTransport + ?Sized>(
+    card: &mut C,
+    aid: &[u8],
+    state: &OptionsMap,
+) -> anyhow::Result<ProcessingResult> {
+    let ats_map = select_application(card, aid)?;
+    read_application_data(card, &ats_map, state)
+}
+
+/// Run GET PROCESSING OPTIONS on an already selected application and read every record in the
+/// AFL. Returns the fields from all records along with the data to be authenticated and the raw
+/// records.
+pub fn read_application_data<C: CardTransport + ?Sized>(
+    card: &mut C,
+    ats_map: &FieldMap,
+    state: &OptionsMap,
+) -> anyhow::Result<ProcessingResult> {
+    let card_info = get_processing_options(card, ats_map, state)?;
+    if !card_info.contains_key(&0x82) {
+        anyhow::bail!("Failed to read AIP");
+    }
+    let afl = card_info
+        .get(&0x94)
+        .and_then(Value::as_binary)
+        .context("Failed to read AFL")?
+        .to_vec();
+    read_records(card, card_info, &afl)
+}
+
+/// Split the value of a Response Message Template Format 1 (0x80) from GET PROCESSING OPTIONS
+/// into the AIP and AFL, which are concatenated without tags. See EMV 4.4 Book 3 section 6.5.8.4.
+pub fn parse_gpo_format1(raw: &[u8]) -> Result<(Vec<u8>, Vec<u8>), DecodeError> {
+    let Some((aip, afl)) = raw.split_at_checked(2) else {
+        return Err(DecodeError::MessageTooShort(2, raw.len()));
+    };
+    if afl.len() % 4 != 0 {
+        return Err(DecodeError::InvalidAfl(afl.len() / 4, "truncated entry"));
+    }
+    Ok((aip.to_vec(), afl.to_vec()))
+}
 
+/// Run GET PROCESSING OPTIONS with the PDOL from the FCI `ats_map` and return the fields of the
+/// response. A format 1 response (0x80) is split into the AIP (0x82) and AFL (0x94). A format 2
+/// template (0x77) is returned whole, since contactless cards may put other fields in it.
+pub fn get_processing_options<C: CardTransport + ?Sized>(
+    card: &mut C,
+    ats_map: &FieldMap,
+    state: &OptionsMap,
+) -> anyhow::Result<FieldMap> {
     let pdol_encoded = ats_map
         .get_path(&[0xa5, 0x9f38])
         .ok()
@@ -38,61 +107,69 @@ pub fn read_processing_options(
 
     // Request command template, no length, as recommended by EMV 4.3 book 3 section 10.1
     let (response, sw) = exchange(card, &ADPUCommand::get_processing_options(&pdol_encoded))?;
-    if sw != 0x9000 {
-        anyhow::bail!(
-            "Failure returned by card while running GET PROCESSING OPTIONS with {}: 0x{:04x}",
-            hex::encode(pdol_encoded),
-            sw
-        );
-    }
+    ExchangeError::check_data_sw(sw, &response, card.fail_on_warning()).with_context(|| {
+        format!(
+            "Failure returned by card while running GET PROCESSING OPTIONS with {}",
+            hex::encode(pdol_encoded)
+        )
+    })?;
 
     let (gpo_tag, gpo_value) =
         tlv::read_field(&response).context("Failed to parse processing options")?;
     debug!("{} => {}", gpo_tag, gpo_value);
 
-    let (aip, afl) = match gpo_tag {
-        0x77 => (
-            gpo_value
-                .get_path_binary(&[0x82])
-                .context("Failed to read AIP")?,
-            gpo_value
-                .get_path_binary(&[0x94])
-                .context("Failed to read AFL")?,
-        ),
+    match gpo_tag {
+        0x77 => Ok(gpo_value
+            .into_template()
+            .ok_or(DecodeError::WrongType(0x77, "Template"))?),
         0x80 => {
             let resp = gpo_value
                 .as_binary()
                 .ok_or(DecodeError::WrongType(0x80, "Binary"))?;
-            if resp.len() < 6 {
-                anyhow::bail!("Failed to read AIP and AFL!");
-            }
-            resp.split_at(2)
+            let (aip, afl) = parse_gpo_format1(resp)
+                .context("Malformed GET PROCESSING OPTIONS format 1 response")?;
+            let mut card_info = FieldMap::new();
+            card_info.insert(0x82, Value::Binary(aip));
+            card_info.insert(0x94, Value::Binary(afl));
+            Ok(card_info)
         }
         tag => {
             anyhow::bail!("Got tag {:04x} when trying to read AIP and AFL", tag);
         }
-    };
-    let mut card_info = FieldMap::new();
-    card_info.insert(0x82, Value::Binary(aip.to_vec()));
-    card_info.insert(0x94, Value::Binary(afl.to_vec()));
+    }
+}
 
-    let mut sda_data = Vec::new();
-    for afl_fields in afl.chunks_exact(4) {
-        let sfi = afl_fields[0] >> 3;
-        let first_record = afl_fields[1];
-        let last_record = afl_fields[2];
-        let num_sda = afl_fields[3];
+/// Read every record listed in `afl` and add their fields to `card_info`, the result of
+/// [`get_processing_options`]. An empty AFL reads nothing.
+pub fn read_records<C: CardTransport + ?Sized>(
+    card: &mut C,
+    mut card_info: FieldMap,
+    afl: &[u8],
+) -> anyhow::Result<ProcessingResult> {
+    let afl = Afl::parse(afl).context("Failed to parse AFL")?;
+    debug!("AFL:\n{}", afl);
 
-        for record in first_record..=last_record {
+    let mut sda_data = Vec::new();
+    let mut records = Vec::new();
+    for entry in &afl.entries {
+        let sfi = entry.sfi;
+        for record in entry.records() {
             let (response, sw) = exchange(card, &ADPUCommand::read_record(sfi, record))?;
-            if sw != 0x9000 {
-                anyhow::bail!(
-                    "Failure returned by card while reading sfi {:02x} record {:02x}: 0x{:04x}",
-                    sfi,
-                    record,
-                    sw
+            if is_record_not_found(sw) {
+                warn!(
+                    "AFL lists SFI {:02x} record {:02x} but the card doesn't have it",
+                    sfi, record
                 );
+                break;
             }
+            ExchangeError::check_data_sw(sw, &response, card.fail_on_warning()).with_context(
+                || {
+                    format!(
+                        "Failure returned by card while reading sfi {:02x} record {:02x}",
+                        sfi, record
+                    )
+                },
+            )?;
             let (file_tag, file_value) = tlv::read_field(&response)?;
             debug!(
                 "SFI {:02x} rec {:02x} ({:04x})\n{} => {}",
@@ -102,20 +179,180 @@ pub fn read_processing_options(
                 anyhow::anyhow!("SFI {:02x} record {:02x} is not a template", sfi, record)
             })?);
 
-            if record - first_record < num_sda {
+            if entry.is_sda_record(record) {
                 debug!("Adding record {:02x}", record);
                 // Exclude the tag and length if SFI is 1-10. (Book 3 section 10.3)
                 // What the fuck.
                 if sfi <= 10 {
-                    let (_, _, tl_len) = tlv::decoders::read_tl(&response)?;
-                    sda_data.extend(&response[tl_len..])
+                    let (_, record_data, _) = tlv::decoders::read_value(&response)?;
+                    sda_data.extend(record_data)
                 } else if sfi <= 30 {
                     sda_data.extend(&response)
                 }
             }
+            records.push(Record {
+                sfi,
+                number: record,
+                data: response,
+            });
+        }
+    }
+
+    // The values (without tag or length) of any tags in the Static Data Authentication Tag List
+    // are also signed. (Book 3 section 10.3)
+    if let Some(tag_list) = card_info.get(&0x9f4a).and_then(Value::as_binary) {
+        for tag in tlv::decoders::tag_list(tag_list).context("Failed to parse SDA tag list")? {
+            // Values from GET PROCESSING OPTIONS, normally just the AIP, are only kept decoded,
+            // but binary ones are still exactly what the card sent
+            let value = raw_record_value(&records, tag)?
+                .or_else(|| card_info.get(&tag).and_then(Value::as_binary))
+                .ok_or_else(|| {
+                    anyhow::anyhow!("Tag 0x{:04x} in SDA tag list is not on the card", tag)
+                })?;
+            sda_data.extend(value);
         }
     }
 
     debug!("{}", card_info.display());
-    Ok((card_info, sda_data))
+    Ok(ProcessingResult {
+        fields: card_info,
+        sda_data,
+        records,
+    })
+}
+
+/// The value of `tag` in `records` exactly as the card sent it, since signed data can't go
+/// through decoding and encoding again
+fn raw_record_value(records: &[Record], tag: u16) -> Result<Option<&[u8]>, DecodeError> {
+    for record in records {
+        let (_, contents, _) = tlv::decoders::read_value(&record.data)?;
+        for field in tlv::decoders::raw_fields(contents) {
+            let (field_tag, value) = field?;
+            if field_tag == tag {
+                return Ok(Some(value));
+            }
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::{ReplayTransport, TranscriptEntry};
+
+    #[test]
+    fn test_read_application_data() {
+        let mut card = ReplayTransport::new(vec![
+            TranscriptEntry::new(
+                &ADPUCommand::get_processing_options(&[0x83, 0x00]),
+                b"\x80\x06\x7c\x00\x08\x01\x02\x01\x90\x00",
+            ),
+            TranscriptEntry::new(
+                &ADPUCommand::read_record(1, 1),
+                b"\x70\x05\x5f\x28\x02\x08\x40\x90\x00",
+            ),
+            TranscriptEntry::new(
+                &ADPUCommand::read_record(1, 2),
+                b"\x70\x04\x9f\x07\x01\xff\x90\x00",
+            ),
+        ]);
+
+        let result =
+            read_application_data(&mut card, &FieldMap::new(), &OptionsMap::new()).unwrap();
+        assert_eq!(result.fields.get(&0x5f28), Some(&Value::Numeric(840)));
+        // Only the first record is signed, and without its template since the SFI is under 11
+        assert_eq!(result.sda_data, b"\x5f\x28\x02\x08\x40");
+        assert_eq!(
+            result.records,
+            vec![
+                Record {
+                    sfi: 1,
+                    number: 1,
+                    data: b"\x70\x05\x5f\x28\x02\x08\x40".to_vec()
+                },
+                Record {
+                    sfi: 1,
+                    number: 2,
+                    data: b"\x70\x04\x9f\x07\x01\xff".to_vec()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_gpo_format1() {
+        assert_eq!(
+            parse_gpo_format1(&[0x7c, 0x00, 0x08, 0x01, 0x02, 0x01]),
+            Ok((vec![0x7c, 0x00], vec![0x08, 0x01, 0x02, 0x01]))
+        );
+        assert_eq!(
+            parse_gpo_format1(&[0x20, 0x00]),
+            Ok((vec![0x20, 0x00], vec![]))
+        );
+        assert_eq!(
+            parse_gpo_format1(&[0x7c]),
+            Err(DecodeError::MessageTooShort(2, 1))
+        );
+        assert_eq!(
+            parse_gpo_format1(&[0x7c, 0x00, 0x08, 0x01, 0x02, 0x01, 0x10]),
+            Err(DecodeError::InvalidAfl(1, "truncated entry"))
+        );
+    }
+
+    #[test]
+    fn test_truncated_afl() {
+        // A format 2 response with a 5 byte AFL, no records should be read
+        let mut card = ReplayTransport::new(vec![TranscriptEntry::new(
+            &ADPUCommand::get_processing_options(&[0x83, 0x00]),
+            b"\x77\x0b\x82\x02\x7c\x00\x94\x05\x08\x01\x02\x01\x10\x90\x00",
+        )]);
+
+        let err =
+            read_application_data(&mut card, &FieldMap::new(), &OptionsMap::new()).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<DecodeError>(),
+            Some(&DecodeError::InvalidAfl(1, "truncated entry"))
+        );
+        assert_eq!(card.remaining(), 0);
+    }
+
+    #[test]
+    fn test_read_records_not_found() {
+        // The AFL claims records 1-3 of SFI 1 and record 1 of SFI 2
+        let afl = [0x08, 0x01, 0x03, 0x00, 0x10, 0x01, 0x01, 0x00];
+        let mut card = ReplayTransport::new(vec![
+            TranscriptEntry::new(
+                &ADPUCommand::read_record(1, 1),
+                b"\x70\x05\x5f\x28\x02\x08\x40\x90\x00",
+            ),
+            TranscriptEntry::new(&ADPUCommand::read_record(1, 2), b"\x6a\x83"),
+            TranscriptEntry::new(
+                &ADPUCommand::read_record(2, 1),
+                b"\x70\x04\x9f\x07\x01\xff\x90\x00",
+            ),
+        ]);
+
+        let result = read_records(&mut card, FieldMap::new(), &afl).unwrap();
+        assert_eq!(result.records.len(), 2);
+        assert_eq!(result.fields.get(&0x9f07), Some(&Value::Binary(vec![0xff])));
+        assert_eq!(card.remaining(), 0);
+    }
+
+    #[test]
+    fn test_sda_tag_list() {
+        // The record lists the AIP and the Application Currency Code, which is padded to 3 bytes
+        let afl = [0x08, 0x01, 0x01, 0x00];
+        let mut card = ReplayTransport::new(vec![TranscriptEntry::new(
+            &ADPUCommand::read_record(1, 1),
+            b"\x70\x0c\x9f\x4a\x03\x82\x9f\x42\x9f\x42\x03\x00\x09\x78\x90\x00",
+        )]);
+        let mut card_info = FieldMap::new();
+        card_info.insert(0x82, Value::Binary(vec![0x7c, 0x00]));
+
+        let result = read_records(&mut card, card_info, &afl).unwrap();
+        assert_eq!(result.fields.get(&0x9f42), Some(&Value::Numeric(978)));
+        // Signed as sent, not as the value would be encoded again
+        assert_eq!(result.sda_data, [0x7c, 0x00, 0x00, 0x09, 0x78]);
+    }
 }
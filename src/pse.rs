@@ -1,18 +1,45 @@
+use std::{collections::HashSet, fmt::Display};
+
 use anyhow::Context;
-use log::debug;
+use log::{debug, info, warn};
+use serde::Serialize;
 
 use crate::{
-    exchange::{exchange, ADPUCommand},
-    tlv::{self, errors::DecodeError, FieldMap, FieldMapExt, Value},
+    exchange::{
+        exchange, is_record_not_found, ADPUCommand, CardTransport, ExchangeError, SelectOccurrence,
+    },
+    tlv::{
+        self, errors::DecodeError, serialize::serialize_field_map, FieldMap, FieldMapExt, TagMap,
+        Value,
+    },
 };
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub struct ApplicationTemplate {
+    #[serde(with = "hex::serde")]
     pub aid: Vec<u8>,
     pub label: String,
+    /// Raw Application Priority Indicator (0x87)
     pub priority: Option<u8>,
+    /// The application may not be selected without the cardholder confirming
+    pub confirmation_required: bool,
     pub country: Option<String>,
     pub iin: Option<u32>,
+    /// Kernel Identifier (0x9f2a) from contactless PPSE entries
+    pub kernel_id: Option<Vec<u8>>,
+    /// Any other fields in the template
+    #[serde(serialize_with = "serialize_field_map")]
+    pub extra: FieldMap,
+}
+
+impl ApplicationTemplate {
+    /// Priority from the Application Priority Indicator (low nibble of 0x87), 1 being the highest.
+    /// `None` if the card didn't give a priority.
+    pub fn priority_order(&self) -> Option<u8> {
+        self.priority
+            .map(|priority| priority & 0x0f)
+            .filter(|&order| order != 0)
+    }
 }
 
 impl TryFrom<FieldMap> for ApplicationTemplate {
@@ -35,22 +62,29 @@ impl TryFrom<FieldMap> for ApplicationTemplate {
             .and_then(Value::into_binary)
             .and_then(|v| v.first().cloned());
 
+        let kernel_id = template
+            .remove(&0x9f2a)
+            .and_then(|v| v.into_iter().next())
+            .and_then(Value::into_binary);
+
         let (country, iin) = if let Some(mut inner_map) = template
             .remove(&0x73)
             .and_then(|v| v.into_iter().next())
             .and_then(Value::into_template)
         {
-            (
-                inner_map
-                    .remove(&0x5f55)
-                    .and_then(|v| v.into_iter().next())
-                    .and_then(Value::into_alphabetic),
-                inner_map
-                    .remove(&0x42)
-                    .and_then(|v| v.into_iter().next())
-                    .and_then(Value::into_numeric)
-                    .map(|n| n as u32),
-            )
+            let country = inner_map
+                .remove(&0x5f55)
+                .and_then(|v| v.into_iter().next())
+                .and_then(Value::into_alphabetic);
+            let iin = inner_map
+                .remove(&0x42)
+                .and_then(|v| v.into_iter().next())
+                .and_then(Value::into_numeric)
+                .map(|n| n as u32);
+            if !inner_map.is_empty() {
+                template.insert(0x73, Value::Template(inner_map));
+            }
+            (country, iin)
         } else {
             (None, None)
         };
@@ -59,19 +93,63 @@ impl TryFrom<FieldMap> for ApplicationTemplate {
             aid,
             label,
             priority,
+            confirmation_required: priority.is_some_and(|priority| priority & 0x80 != 0),
             country,
             iin,
+            kernel_id,
+            extra: template,
+        })
+    }
+}
+
+/// How the applications on a card were found
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApplicationSource {
+    /// The Payment System Environment directory, 1PAY.SYS.DDF01
+    Pse,
+    /// The contactless Proximity Payment System Environment, 2PAY.SYS.DDF01
+    Ppse,
+    /// Selecting each of [`CANDIDATE_AIDS`] because the card has no directory
+    CandidateList,
+}
+
+impl Display for ApplicationSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ApplicationSource::Pse => "PSE",
+            ApplicationSource::Ppse => "PPSE",
+            ApplicationSource::CandidateList => "list of known AIDs",
         })
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// RIDs of the common payment systems, tried with partial selection when a card has no PSE like
+/// a terminal would with its list of supported AIDs (EMV 4.4 Book 1 section 12.3.3)
+pub const CANDIDATE_AIDS: &[(&str, &[u8])] = &[
+    ("Visa", &[0xa0, 0x00, 0x00, 0x00, 0x03]),
+    ("Mastercard", &[0xa0, 0x00, 0x00, 0x00, 0x04]),
+    ("American Express", &[0xa0, 0x00, 0x00, 0x00, 0x25]),
+    ("Discover", &[0xa0, 0x00, 0x00, 0x01, 0x52]),
+    ("JCB", &[0xa0, 0x00, 0x00, 0x00, 0x65]),
+    ("UnionPay", &[0xa0, 0x00, 0x00, 0x03, 0x33]),
+    ("Interac", &[0xa0, 0x00, 0x00, 0x02, 0x77]),
+    ("US Common Debit", &[0xa0, 0x00, 0x00, 0x00, 0x98]),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub struct PSEData {
+    pub source: ApplicationSource,
+    /// ISO 639 language codes in order of preference
     pub languages: Vec<String>,
+    /// Sorted by priority, highest first
     pub applications: Vec<ApplicationTemplate>,
+    /// Everything in the FCI Issuer Discretionary Data (0xbf0c) other than applications
+    #[serde(serialize_with = "serialize_field_map")]
+    pub issuer_discretionary_data: FieldMap,
 }
 
-fn list_from_ppse(pse_map: FieldMap) -> anyhow::Result<Vec<ApplicationTemplate>> {
+fn list_from_ppse(pse_map: FieldMap) -> anyhow::Result<(Vec<ApplicationTemplate>, FieldMap)> {
     let mut fci_data = pse_map
         .into_path(&[0xa5, 0xbf0c])
         .context("Could not find FCI in PPSE")?
@@ -87,16 +165,67 @@ fn list_from_ppse(pse_map: FieldMap) -> anyhow::Result<Vec<ApplicationTemplate>>
         applications.push(application_map.into_template().unwrap().try_into()?);
     }
 
-    Ok(applications)
+    Ok((applications, fci_data))
 }
 
-fn list_from_pse(
-    card: &mut pcsc::Card,
-    pse_map: &FieldMap,
+/// Split a Language Preference (0x5f2d) into 2 character ISO 639 codes, returning anything left
+/// over at the end
+fn split_languages(s: &str) -> (Vec<String>, &str) {
+    let mut languages = Vec::new();
+    let mut rest = s;
+    while let Some((language, remaining)) = rest.split_at_checked(2) {
+        languages.push(language.to_string());
+        rest = remaining;
+    }
+    (languages, rest)
+}
+
+/// Limit on nested DDFs so a broken card can't keep us reading directories forever
+const MAX_DDF_DEPTH: usize = 4;
+
+fn select_directory<C: CardTransport + ?Sized>(
+    card: &mut C,
+    name: &[u8],
+) -> anyhow::Result<FieldMap> {
+    let (response, sw) = exchange(card, &ADPUCommand::select(name))?;
+
+    ExchangeError::check_data_sw(sw, &response, card.fail_on_warning()).with_context(|| {
+        format!(
+            "Failure returned by card while selecting DDF {}",
+            String::from_utf8_lossy(name)
+        )
+    })?;
+
+    let (tag, ddf_value) =
+        tlv::read_field(&response).context("Failed to parse directory response")?;
+    debug!(
+        "{}:\n{:02x} => {}",
+        String::from_utf8_lossy(name),
+        tag,
+        ddf_value
+    );
+    if tag != 0x6f {
+        anyhow::bail!("Directory had incorrect root object")
+    }
+
+    ddf_value
+        .into_template()
+        .ok_or_else(|| anyhow::anyhow!("Directory root object was not a template"))
+}
+
+/// Read the applications in the directory `name`, which has already been selected and returned
+/// `ddf_map` as its FCI. Directory entries pointing to other DDFs (0x9d) are followed, skipping
+/// any in `visited`.
+fn list_from_pse<C: CardTransport + ?Sized>(
+    card: &mut C,
+    name: &[u8],
+    ddf_map: &FieldMap,
+    visited: &mut HashSet<Vec<u8>>,
+    depth: usize,
 ) -> anyhow::Result<Vec<ApplicationTemplate>> {
     let mut applications = Vec::new();
 
-    let sfi = pse_map
+    let sfi = ddf_map
         .get_path(&[0xa5, 0x88])
         .context("Could not find SFI in PSE")?
         .as_binary()
@@ -105,82 +234,393 @@ fn list_from_pse(
         anyhow::bail!("Invalid SFI {:02x}", sfi);
     }
 
-    for rec in 1..16 {
+    // Stop at the first missing record, 0xff isn't a valid record number (ISO 7816-4 section
+    // 7.3.3)
+    for rec in 1..=0xfe {
         let (sfi_response, sfi_sw) = exchange(card, &ADPUCommand::read_record(sfi, rec))?;
         debug!("SFI {:02x} rec {:02x} ({:04x})", sfi, rec, sfi_sw);
-        if sfi_sw == 0x9000 {
-            let (_tag, record) = tlv::read_field(&sfi_response).with_context(|| {
+        if is_record_not_found(sfi_sw) {
+            // We've reached the last real record
+            break;
+        }
+        if ExchangeError::check_data_sw(sfi_sw, &sfi_response, card.fail_on_warning()).is_ok() {
+            let mut record_map = tlv::parse_response(&sfi_response).with_context(|| {
                 format!("Failed to parse SFI 0x{:02x} record 0x{:02x}", sfi, rec)
             })?;
-            debug!("{}", record);
-            let record_map = record
-                .into_template()
-                .ok_or_else(|| anyhow::anyhow!("SFI record wasn't a template!"))?;
-            let template = record_map
-                .into_path(&[0x61])?
-                .into_template()
-                .ok_or(DecodeError::WrongType(0x61, "Template"))?;
-
-            applications.push(template.try_into().context("Failed to parse SFI record")?);
-        }
+            debug!("{}", record_map.display());
+            let entries = record_map
+                .remove(&0x61)
+                .ok_or(DecodeError::NoSuchMember(0x61))?;
 
-        if sfi_sw == 0x6a83 {
-            // We've reached the last real record
-            break;
+            for entry in entries {
+                let template = entry
+                    .into_template()
+                    .ok_or(DecodeError::WrongType(0x61, "Template"))?;
+
+                let Some(ddf_name) = template.get(&0x9d).and_then(Value::as_binary) else {
+                    applications.push(template.try_into().context("Failed to parse SFI record")?);
+                    continue;
+                };
+
+                if depth >= MAX_DDF_DEPTH {
+                    warn!(
+                        "Not following DDF {} past depth {}",
+                        hex::encode(ddf_name),
+                        MAX_DDF_DEPTH
+                    );
+                    continue;
+                }
+                if !visited.insert(ddf_name.to_vec()) {
+                    warn!("Skipping already visited DDF {}", hex::encode(ddf_name));
+                    continue;
+                }
+
+                let child_map = select_directory(card, ddf_name)?;
+                applications.extend(list_from_pse(
+                    card,
+                    ddf_name,
+                    &child_map,
+                    visited,
+                    depth + 1,
+                )?);
+                // Short file identifiers are relative to the current directory, so go back to it
+                select_directory(card, name)?;
+            }
         }
     }
 
     Ok(applications)
 }
 
-pub fn list_applications(card: &mut pcsc::Card, ppse: bool) -> anyhow::Result<PSEData> {
+pub fn list_applications<C: CardTransport + ?Sized>(
+    card: &mut C,
+    ppse: bool,
+) -> anyhow::Result<PSEData> {
     let pse = if ppse {
         "2PAY.SYS.DDF01"
     } else {
         "1PAY.SYS.DDF01"
     };
 
-    let (response, sw) = exchange(card, &ADPUCommand::select(pse.as_bytes()))?;
-
-    if sw != 0x9000 {
-        anyhow::bail!(
-            "Failure returned by card while selecting PSE {}: 0x{:04x}",
-            pse,
-            sw
-        );
-    }
-
-    let (tag, pse_value) = tlv::read_field(&response)
-        .context("Failed to parse Payment System Environment response")?;
-    debug!("{}:\n{:02x} => {}", pse, tag, pse_value);
-    if tag != 0x6f {
-        anyhow::bail!("PSE had incorrect root object")
-    }
-
-    let pse_map = pse_value
-        .into_template()
-        .ok_or_else(|| anyhow::anyhow!("PSE root object was not a template"))?;
+    let pse_map = match select_directory(card, pse.as_bytes()) {
+        Err(err)
+            if matches!(
+                err.downcast_ref::<ExchangeError>(),
+                Some(ExchangeError::StatusWord(0x6a82 | 0x6a83))
+            ) =>
+        {
+            info!("Card has no {}, trying known AIDs", pse);
+            return list_candidates(card);
+        }
+        res => res?,
+    };
 
-    Ok(if ppse {
+    let mut pse_data = if ppse {
+        let (applications, issuer_discretionary_data) = list_from_ppse(pse_map)?;
         PSEData {
+            source: ApplicationSource::Ppse,
             languages: Vec::new(),
-            applications: list_from_ppse(pse_map)?,
+            applications,
+            issuer_discretionary_data,
         }
     } else {
         PSEData {
+            source: ApplicationSource::Pse,
             languages: if let Some(s) = pse_map
                 .get_path(&[0xa5, 0x5f2d])
                 .ok()
                 .and_then(Value::as_alphanumeric)
             {
-                s.as_bytes()
-                    .chunks_exact(2)
-                    .filter_map(|bytes| String::from_utf8(bytes.to_vec()).ok())
-                    .collect()
+                let (languages, leftover) = split_languages(s);
+                if !leftover.is_empty() {
+                    warn!("Ignoring trailing {:?} in language preference", leftover);
+                }
+                languages
             } else {
                 Vec::new()
             },
-            applications: list_from_pse(card, &pse_map)?,
+            applications: list_from_pse(
+                card,
+                pse.as_bytes(),
+                &pse_map,
+                &mut HashSet::from([pse.as_bytes().to_vec()]),
+                0,
+            )?,
+            issuer_discretionary_data: pse_map
+                .get_path(&[0xa5, 0xbf0c])
+                .ok()
+                .and_then(Value::as_template)
+                .cloned()
+                .unwrap_or_default(),
+        }
+    };
+
+    sort_by_priority(&mut pse_data.applications);
+    Ok(pse_data)
+}
+
+/// Highest priority first, the sort is stable so ties stay in the order the card gave
+fn sort_by_priority(applications: &mut [ApplicationTemplate]) {
+    applications.sort_by_key(|app| (app.priority_order().is_none(), app.priority_order()));
+}
+
+/// Find applications by selecting each of [`CANDIDATE_AIDS`], for cards without a PSE. A
+/// candidate the card refuses is skipped.
+pub fn list_candidates<C: CardTransport + ?Sized>(card: &mut C) -> anyhow::Result<PSEData> {
+    let mut applications = Vec::new();
+    for &(name, rid) in CANDIDATE_AIDS {
+        match list_by_aid(card, rid) {
+            Ok(found) => {
+                debug!("Found {} {} applications", found.len(), name);
+                applications.extend(found);
+            }
+            Err(err) => warn!("Failed to select {} applications: {:#}", name, err),
         }
+    }
+    sort_by_priority(&mut applications);
+    Ok(PSEData {
+        source: ApplicationSource::CandidateList,
+        languages: Vec::new(),
+        applications,
+        issuer_discretionary_data: FieldMap::new(),
     })
 }
+
+/// Limit on applications matching one partial AID, in case a card keeps answering SELECT next
+const MAX_OCCURRENCES: usize = 16;
+
+/// Build a directory entry from the FCI returned when selecting an application. The label is left
+/// empty if the card didn't send one.
+fn template_from_fci(fci: &FieldMap) -> anyhow::Result<ApplicationTemplate> {
+    let aid = fci
+        .get(&0x84)
+        .and_then(Value::as_binary)
+        .context("FCI has no DF Name")?;
+    let mut template = fci
+        .get(&0xa5)
+        .and_then(Value::as_template)
+        .cloned()
+        .unwrap_or_default();
+    template.insert(0x4f, Value::Binary(aid.to_vec()));
+    if !template.contains_key(&0x50) {
+        template.insert(0x50, Value::AlphanumericSpecial(String::new()));
+    }
+    Ok(template.try_into()?)
+}
+
+/// List the applications whose AIDs start with `aid` by selecting the first one and then the next
+/// until the card says there are no more, for cards that don't list everything in a directory.
+/// See EMV 4.4 Book 1 section 12.3.3.
+pub fn list_by_aid<C: CardTransport + ?Sized>(
+    card: &mut C,
+    aid: &[u8],
+) -> anyhow::Result<Vec<ApplicationTemplate>> {
+    let mut applications: Vec<ApplicationTemplate> = Vec::new();
+    let mut occurrence = SelectOccurrence::First;
+    for _ in 0..MAX_OCCURRENCES {
+        let (response, sw) = exchange(card, &ADPUCommand::select_with(aid, occurrence))?;
+        debug!("SELECT {} {:?} ({:04x})", hex::encode(aid), occurrence, sw);
+        if sw == 0x6a82 {
+            // No more matching applications
+            break;
+        }
+        ExchangeError::check_data_sw(sw, &response, card.fail_on_warning())
+            .with_context(|| format!("Failed to select {}", hex::encode(aid)))?;
+        let fci = tlv::parse_response(&response).context("Failed to parse FCI")?;
+        let application = template_from_fci(&fci)?;
+        if applications.iter().any(|app| app.aid == application.aid) {
+            warn!(
+                "Card returned {} again, stopping",
+                hex::encode(&application.aid)
+            );
+            break;
+        }
+        applications.push(application);
+        occurrence = SelectOccurrence::Next;
+    }
+    Ok(applications)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::{ReplayTransport, TranscriptEntry};
+
+    #[test]
+    fn test_split_languages() {
+        assert_eq!(
+            split_languages("enfrdex"),
+            (
+                vec!["en".to_string(), "fr".to_string(), "de".to_string()],
+                "x"
+            )
+        );
+        assert_eq!(split_languages("en"), (vec!["en".to_string()], ""));
+    }
+
+    #[test]
+    fn test_application_template_extra() {
+        let template = tlv::decoders::template(
+            &b"\x4f\x05\xa0\x00\x00\x00\x03\x50\x04VISA\x9f\x12\x05DEBIT\
+               \x73\x0a\x5f\x55\x02US\x9f\x4d\x02\x0b\x0a"[..],
+        )
+        .unwrap();
+        let application = ApplicationTemplate::try_from(template).unwrap();
+        assert_eq!(application.country.as_deref(), Some("US"));
+
+        let mut discretionary = FieldMap::new();
+        discretionary.insert(0x9f4d, Value::Binary(vec![0x0b, 0x0a]));
+        let mut extra = FieldMap::new();
+        extra.insert(0x9f12, Value::AlphanumericSpecial("DEBIT".to_string()));
+        extra.insert(0x73, Value::Template(discretionary));
+        assert_eq!(application.extra, extra);
+    }
+
+    #[test]
+    fn test_priority_sort() {
+        let mut card = ReplayTransport::new(vec![TranscriptEntry::new(
+            &ADPUCommand::select(b"2PAY.SYS.DDF01"),
+            b"\x6f\x3f\x84\x0e2PAY.SYS.DDF01\xa5\x2d\xbf\x0c\x2a\
+              \x61\x16\x4f\x07\xa0\x00\x00\x00\x03\x10\x10\x50\x04VISA\x87\x01\x02\x9f\x2a\x01\x03\
+              \x61\x10\x4f\x07\xa0\x00\x00\x00\x04\x10\x10\x50\x02MC\x87\x01\x81\
+              \x90\x00",
+        )]);
+
+        let applications = list_applications(&mut card, true).unwrap().applications;
+        assert_eq!(
+            applications
+                .iter()
+                .map(|app| (app.label.as_str(), app.confirmation_required))
+                .collect::<Vec<_>>(),
+            vec![("MC", true), ("VISA", false)]
+        );
+        assert_eq!(applications[0].priority, Some(0x81));
+        assert_eq!(applications[1].kernel_id, Some(vec![0x03]));
+    }
+
+    #[test]
+    fn test_list_by_aid() {
+        let rid = [0xa0, 0x00, 0x00, 0x00, 0x03];
+        let mut card = ReplayTransport::new(vec![
+            TranscriptEntry::new(
+                &ADPUCommand::select(&rid),
+                b"\x6f\x11\x84\x07\xa0\x00\x00\x00\x03\x10\x10\xa5\x06\x50\x04VISA\x90\x00",
+            ),
+            TranscriptEntry::new(
+                &ADPUCommand::select_with(&rid, SelectOccurrence::Next),
+                b"\x6f\x12\x84\x07\xa0\x00\x00\x00\x03\x20\x10\xa5\x07\x50\x05V PAY\x90\x00",
+            ),
+            TranscriptEntry::new(
+                &ADPUCommand::select_with(&rid, SelectOccurrence::Next),
+                b"\x6a\x82",
+            ),
+        ]);
+
+        let applications = list_by_aid(&mut card, &rid).unwrap();
+        assert_eq!(
+            applications
+                .iter()
+                .map(|app| (hex::encode(&app.aid), app.label.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("a0000000031010".to_string(), "VISA"),
+                ("a0000000032010".to_string(), "V PAY")
+            ]
+        );
+        assert_eq!(card.remaining(), 0);
+    }
+
+    #[test]
+    fn test_candidate_fallback() {
+        let visa = CANDIDATE_AIDS[0].1;
+        let mut transcript = vec![
+            TranscriptEntry::new(&ADPUCommand::select(b"1PAY.SYS.DDF01"), b"\x6a\x82"),
+            TranscriptEntry::new(
+                &ADPUCommand::select(visa),
+                b"\x6f\x11\x84\x07\xa0\x00\x00\x00\x03\x10\x10\xa5\x06\x50\x04VISA\x90\x00",
+            ),
+            TranscriptEntry::new(
+                &ADPUCommand::select_with(visa, SelectOccurrence::Next),
+                b"\x6a\x82",
+            ),
+        ];
+        for (_, rid) in &CANDIDATE_AIDS[1..] {
+            transcript.push(TranscriptEntry::new(&ADPUCommand::select(rid), b"\x6a\x82"));
+        }
+        let mut card = ReplayTransport::new(transcript);
+
+        let pse_data = list_applications(&mut card, false).unwrap();
+        assert_eq!(pse_data.source, ApplicationSource::CandidateList);
+        assert_eq!(pse_data.applications.len(), 1);
+        assert_eq!(pse_data.applications[0].label, "VISA");
+        assert_eq!(card.remaining(), 0);
+    }
+
+    #[test]
+    fn test_many_records() {
+        let mut transcript = vec![TranscriptEntry::new(
+            &ADPUCommand::select(b"1PAY.SYS.DDF01"),
+            b"\x6f\x15\x84\x0e1PAY.SYS.DDF01\xa5\x03\x88\x01\x01\x90\x00",
+        )];
+        for rec in 1..=20 {
+            let mut response =
+                b"\x70\x11\x61\x0f\x4f\x07\xa0\x00\x00\x00\x03\x10\x00\x50\x04VISA\x90\x00"
+                    .to_vec();
+            // Give each application a different AID
+            response[12] = rec;
+            transcript.push(TranscriptEntry::new(
+                &ADPUCommand::read_record(1, rec),
+                &response,
+            ));
+        }
+        transcript.push(TranscriptEntry::new(
+            &ADPUCommand::read_record(1, 21),
+            b"\x6a\x83",
+        ));
+        let mut card = ReplayTransport::new(transcript);
+
+        let pse_data = list_applications(&mut card, false).unwrap();
+        assert_eq!(pse_data.applications.len(), 20);
+        assert_eq!(pse_data.applications[19].aid[6], 20);
+        assert_eq!(card.remaining(), 0);
+    }
+
+    #[test]
+    fn test_nested_ddf() {
+        let pse_fci = b"\x6f\x15\x84\x0e1PAY.SYS.DDF01\xa5\x03\x88\x01\x01\x90\x00";
+        let ddf_entry = b"\x70\x0c\x61\x0a\x9d\x08DDF2NAME\x90\x00";
+        let mut card = ReplayTransport::new(vec![
+            TranscriptEntry::new(&ADPUCommand::select(b"1PAY.SYS.DDF01"), pse_fci),
+            TranscriptEntry::new(&ADPUCommand::read_record(1, 1), ddf_entry),
+            TranscriptEntry::new(
+                &ADPUCommand::select(b"DDF2NAME"),
+                b"\x6f\x0f\x84\x08DDF2NAME\xa5\x03\x88\x01\x02\x90\x00",
+            ),
+            TranscriptEntry::new(
+                &ADPUCommand::read_record(2, 1),
+                b"\x70\x11\x61\x0f\x4f\x07\xa0\x00\x00\x00\x03\x10\x10\x50\x04VISA\x90\x00",
+            ),
+            TranscriptEntry::new(&ADPUCommand::read_record(2, 2), b"\x6a\x83"),
+            TranscriptEntry::new(&ADPUCommand::select(b"1PAY.SYS.DDF01"), pse_fci),
+            // Points back at a directory we've already read
+            TranscriptEntry::new(&ADPUCommand::read_record(1, 2), ddf_entry),
+            TranscriptEntry::new(&ADPUCommand::read_record(1, 3), b"\x6a\x83"),
+        ]);
+
+        let pse_data = list_applications(&mut card, false).unwrap();
+        assert_eq!(
+            pse_data.applications,
+            vec![ApplicationTemplate {
+                aid: vec![0xa0, 0x00, 0x00, 0x00, 0x03, 0x10, 0x10],
+                label: "VISA".to_string(),
+                priority: None,
+                confirmation_required: false,
+                country: None,
+                iin: None,
+                kernel_id: None,
+                extra: FieldMap::new(),
+            }]
+        );
+        assert_eq!(card.remaining(), 0);
+    }
+}
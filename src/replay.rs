@@ -0,0 +1,187 @@
+use std::{collections::VecDeque, fs::File, io::BufWriter, path::Path};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::exchange::{ADPUCommand, CardTransport, ExchangeError};
+
+/// A single raw command and the card's raw response, including the status word
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TranscriptEntry {
+    #[serde(rename = "command_hex", with = "hex::serde")]
+    pub command: Vec<u8>,
+    #[serde(rename = "response_hex", with = "hex::serde")]
+    pub response: Vec<u8>,
+}
+
+impl TranscriptEntry {
+    /// The entry for the card answering `command` with `response`. Panics if `command` can't be
+    /// encoded, see [`ADPUCommand::encode`].
+    pub fn new(command: &ADPUCommand, response: &[u8]) -> Self {
+        Self {
+            command: command
+                .encode()
+                .expect("transcript command can't be encoded")
+                .into(),
+            response: response.to_vec(),
+        }
+    }
+}
+
+/// Plays back a recorded transcript in place of a real card, failing if the commands sent differ
+/// from the ones that were recorded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayTransport {
+    entries: VecDeque<TranscriptEntry>,
+    position: usize,
+    fail_on_warning: bool,
+}
+
+impl ReplayTransport {
+    pub fn new(entries: Vec<TranscriptEntry>) -> Self {
+        Self {
+            entries: entries.into(),
+            position: 0,
+            fail_on_warning: false,
+        }
+    }
+
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open transcript {}", path.display()))?;
+        let entries = serde_json::from_reader(file)
+            .with_context(|| format!("Failed to parse transcript {}", path.display()))?;
+        Ok(Self::new(entries))
+    }
+
+    /// See [`CardTransport::fail_on_warning`]
+    pub fn set_fail_on_warning(&mut self, fail: bool) {
+        self.fail_on_warning = fail;
+    }
+
+    /// Number of recorded exchanges which have not been replayed yet
+    pub fn remaining(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+impl CardTransport for ReplayTransport {
+    fn transmit<'buf>(
+        &mut self,
+        command: &[u8],
+        recieve_buffer: &'buf mut [u8],
+    ) -> Result<&'buf [u8], ExchangeError> {
+        let Some(entry) = self.entries.pop_front() else {
+            return Err(ExchangeError::Transcript(format!(
+                "Transcript ended after {} commands, but got {}",
+                self.position,
+                hex::encode(command)
+            )));
+        };
+        if entry.command != command {
+            return Err(ExchangeError::Transcript(format!(
+                "Command {} in transcript was {}, but got {}",
+                self.position,
+                hex::encode(entry.command),
+                hex::encode(command)
+            )));
+        }
+        if entry.response.len() > recieve_buffer.len() {
+            return Err(ExchangeError::Transcript(format!(
+                "Response {} in transcript is {} bytes, buffer is only {}",
+                self.position,
+                entry.response.len(),
+                recieve_buffer.len()
+            )));
+        }
+
+        self.position += 1;
+        let response = &mut recieve_buffer[..entry.response.len()];
+        response.copy_from_slice(&entry.response);
+        Ok(response)
+    }
+
+    fn fail_on_warning(&self) -> bool {
+        self.fail_on_warning
+    }
+}
+
+/// Passes commands through to another transport while recording a transcript for later replay
+pub struct RecordingTransport<'a, C: CardTransport + ?Sized> {
+    inner: &'a mut C,
+    entries: Vec<TranscriptEntry>,
+}
+
+impl<'a, C: CardTransport + ?Sized> RecordingTransport<'a, C> {
+    pub fn new(inner: &'a mut C) -> Self {
+        Self {
+            inner,
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn entries(&self) -> &[TranscriptEntry] {
+        &self.entries
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create transcript {}", path.display()))?;
+        serde_json::to_writer_pretty(BufWriter::new(file), &self.entries)
+            .with_context(|| format!("Failed to write transcript {}", path.display()))
+    }
+}
+
+impl<C: CardTransport + ?Sized> CardTransport for RecordingTransport<'_, C> {
+    fn transmit<'buf>(
+        &mut self,
+        command: &[u8],
+        recieve_buffer: &'buf mut [u8],
+    ) -> Result<&'buf [u8], ExchangeError> {
+        let response = self.inner.transmit(command, recieve_buffer)?;
+        self.entries.push(TranscriptEntry {
+            command: command.to_vec(),
+            response: response.to_vec(),
+        });
+        Ok(response)
+    }
+
+    fn fail_on_warning(&self) -> bool {
+        self.inner.fail_on_warning()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_mismatch() {
+        let mut card = ReplayTransport::new(vec![TranscriptEntry {
+            command: vec![0x00, 0xb2, 0x01, 0x0c, 0x00],
+            response: vec![0x6a, 0x83],
+        }]);
+        let mut buffer = [0u8; 256];
+        let err = card
+            .transmit(&[0x00, 0xb2, 0x02, 0x0c, 0x00], &mut buffer)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Command 0 in transcript was 00b2010c00, but got 00b2020c00"
+        );
+    }
+
+    #[test]
+    fn test_transcript_json() {
+        let entries: Vec<TranscriptEntry> =
+            serde_json::from_str(r#"[{"command_hex": "00b2010c00", "response_hex": "6a83"}]"#)
+                .unwrap();
+        assert_eq!(
+            entries,
+            vec![TranscriptEntry {
+                command: vec![0x00, 0xb2, 0x01, 0x0c, 0x00],
+                response: vec![0x6a, 0x83],
+            }]
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use std::fmt::Display;
+
+use anyhow::Context;
+use log::warn;
+
+use crate::{
+    exchange::{describe_sw, exchange, ADPUCommand, CardTransport},
+    tlv::{FieldMap, Value},
+};
+
+/// Whether the card accepted an issuer script command. Warnings count as success, see EMV 4.4
+/// Book 3 section 10.10.
+fn command_succeeded(sw: u16) -> bool {
+    matches!(sw >> 8, 0x90 | 0x62 | 0x63)
+}
+
+/// One Issuer Script Command (0x86) and the status word the card returned for it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptCommandResult {
+    pub command: Vec<u8>,
+    pub sw: u16,
+}
+
+impl ScriptCommandResult {
+    pub fn succeeded(&self) -> bool {
+        command_succeeded(self.sw)
+    }
+}
+
+/// Outcome of running an Issuer Script Template (0x71 or 0x72) with [`run_issuer_script`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IssuerScriptResult {
+    /// Issuer Script Identifier (0x9f18)
+    pub identifier: Option<Vec<u8>>,
+    /// Commands sent in order. Processing stops after the first failure, so any commands after
+    /// it are missing.
+    pub commands: Vec<ScriptCommandResult>,
+    /// Number of commands in the script
+    pub total: usize,
+}
+
+impl IssuerScriptResult {
+    pub fn succeeded(&self) -> bool {
+        self.commands.len() == self.total && self.commands.iter().all(|c| c.succeeded())
+    }
+
+    /// The 5 byte Issuer Script Results (0x9f5b) entry a terminal reports for this script: the
+    /// result and failed command number, then the identifier or zeroes. See EMV 4.4 Book 4 Annex
+    /// A5.
+    pub fn script_results(&self) -> [u8; 5] {
+        let result = if self.succeeded() {
+            0x20
+        } else {
+            // Commands after the 15th are all reported as 15
+            0x10 | self.commands.len().min(0x0f) as u8
+        };
+        let mut results = [result, 0, 0, 0, 0];
+        if let Some(identifier) = self.identifier.as_ref().filter(|id| id.len() == 4) {
+            results[1..].copy_from_slice(identifier);
+        }
+        results
+    }
+}
+
+impl Display for IssuerScriptResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.identifier {
+            Some(identifier) => write!(f, "Issuer script {}", hex::encode(identifier))?,
+            None => write!(f, "Issuer script")?,
+        }
+        for (i, result) in self.commands.iter().enumerate() {
+            write!(
+                f,
+                "\n    {}. {} => {:04x} ({})",
+                i + 1,
+                hex::encode(&result.command),
+                result.sw,
+                describe_sw(result.sw)
+            )?;
+        }
+        if self.commands.len() < self.total {
+            write!(
+                f,
+                "\n    Stopped after {} of {} commands",
+                self.commands.len(),
+                self.total
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Send each Issuer Script Command (0x86) in `script`, the contents of an Issuer Script Template,
+/// to the card in order. Like a terminal, this stops at the first command the card rejects. The
+/// application the script is for must already be selected.
+pub fn run_issuer_script<C: CardTransport + ?Sized>(
+    card: &mut C,
+    script: &FieldMap,
+) -> anyhow::Result<IssuerScriptResult> {
+    let identifier = script
+        .get(&0x9f18)
+        .and_then(Value::as_binary)
+        .map(<[u8]>::to_vec);
+    let raw_commands: Vec<&[u8]> = script
+        .get_vec(&0x86)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_binary)
+        .collect();
+
+    let mut commands = Vec::new();
+    for (i, &raw) in raw_commands.iter().enumerate() {
+        let command = ADPUCommand::decode(raw)
+            .with_context(|| format!("Issuer script command {} is not a valid command", i + 1))?;
+        let (_, sw) = exchange(card, &command)?;
+        commands.push(ScriptCommandResult {
+            command: raw.to_vec(),
+            sw,
+        });
+        if !command_succeeded(sw) {
+            warn!(
+                "Issuer script command {} failed: {}",
+                i + 1,
+                describe_sw(sw)
+            );
+            break;
+        }
+    }
+
+    Ok(IssuerScriptResult {
+        identifier,
+        commands,
+        total: raw_commands.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::{ReplayTransport, TranscriptEntry};
+
+    #[test]
+    fn test_run_issuer_script() {
+        let first = ADPUCommand::put_data(0x9f14, &[0x03, 0x11, 0x22, 0x33, 0x44]);
+        let second = ADPUCommand::put_data(0x9f23, &[0x05, 0x11, 0x22, 0x33, 0x44]);
+        let third = ADPUCommand::put_data(0x9f53, &[0x01, 0x11, 0x22, 0x33, 0x44]);
+        let mut script = FieldMap::new();
+        script.insert(0x9f18, Value::Binary(vec![0x00, 0x00, 0x00, 0x07]));
+        for command in [first, second, third] {
+            script.insert(0x86, Value::Binary(command.encode().unwrap().into()));
+        }
+
+        let mut card = ReplayTransport::new(vec![
+            TranscriptEntry::new(&first, b"\x90\x00"),
+            TranscriptEntry::new(&second, b"\x69\x88"),
+        ]);
+        let result = run_issuer_script(&mut card, &script).unwrap();
+        assert_eq!(card.remaining(), 0);
+        assert!(!result.succeeded());
+        assert_eq!(
+            result.commands.iter().map(|c| c.sw).collect::<Vec<_>>(),
+            [0x9000, 0x6988]
+        );
+        assert_eq!(result.script_results(), [0x12, 0x00, 0x00, 0x00, 0x07]);
+        assert!(result
+            .to_string()
+            .ends_with("Stopped after 2 of 3 commands"));
+    }
+}
@@ -0,0 +1,95 @@
+use std::fmt::Display;
+
+/// The three digit Service Code (0x5f30) from ISO 7813, also found in Track 2
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ServiceCode {
+    /// Interchange and technology
+    pub interchange: u8,
+    /// Authorization processing
+    pub authorization: u8,
+    /// Allowed services and PIN requirements
+    pub services: u8,
+}
+
+pub fn decode_service_code(code: u16) -> ServiceCode {
+    ServiceCode {
+        interchange: (code / 100 % 10) as u8,
+        authorization: (code / 10 % 10) as u8,
+        services: (code % 10) as u8,
+    }
+}
+
+impl ServiceCode {
+    pub fn interchange_description(&self) -> &'static str {
+        match self.interchange {
+            1 => "international interchange",
+            2 => "international interchange, use chip where feasible",
+            5 => "national interchange only",
+            6 => "national interchange only, use chip where feasible",
+            7 => "no interchange except under bilateral agreement",
+            9 => "test card",
+            _ => "reserved interchange",
+        }
+    }
+
+    pub fn authorization_description(&self) -> &'static str {
+        match self.authorization {
+            0 => "normal authorization",
+            2 => "online authorization",
+            4 => "online authorization except under bilateral agreement",
+            _ => "reserved authorization",
+        }
+    }
+
+    pub fn services_description(&self) -> &'static str {
+        match self.services {
+            0 => "no restrictions, PIN required",
+            1 => "no restrictions",
+            2 => "goods and services only",
+            3 => "ATM only, PIN required",
+            4 => "cash only",
+            5 => "goods and services only, PIN required",
+            6 => "no restrictions, PIN where feasible",
+            7 => "goods and services only, PIN where feasible",
+            _ => "reserved services",
+        }
+    }
+}
+
+impl Display for ServiceCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}, {}, {}",
+            self.interchange_description(),
+            self.authorization_description(),
+            self.services_description()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_service_code() {
+        assert_eq!(
+            decode_service_code(201),
+            ServiceCode {
+                interchange: 2,
+                authorization: 0,
+                services: 1
+            }
+        );
+        assert_eq!(
+            decode_service_code(101).to_string(),
+            "international interchange, normal authorization, no restrictions"
+        );
+        assert_eq!(
+            decode_service_code(620).to_string(),
+            "national interchange only, use chip where feasible, online authorization, \
+             no restrictions, PIN required"
+        );
+    }
+}
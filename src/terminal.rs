@@ -0,0 +1,263 @@
+use std::{fmt::Display, path::Path, str::FromStr};
+
+use anyhow::Context;
+
+use crate::{
+    tlv::{serialize::fields_from_json, OptionsMap, Value},
+    util::bit_names,
+};
+
+fn parse_hex<const N: usize>(s: &str, name: &str) -> anyhow::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    hex::decode_to_slice(s, &mut bytes)
+        .with_context(|| format!("{} must be {} bytes of hex", name, N))?;
+    Ok(bytes)
+}
+
+/// Terminal Type (0x9f35), two digits, see EMV 4.4 Book 4 Annex A1
+///
+/// The first digit is who operates the terminal: 1 financial institution, 2 merchant,
+/// 3 cardholder. The second is the environment: 1 attended online only, 2 attended offline with
+/// online capability, 3 attended offline only, 4-6 the same but unattended.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TerminalType(pub u8);
+
+impl Default for TerminalType {
+    /// Attended merchant terminal, offline with online capability
+    fn default() -> Self {
+        TerminalType(0x22)
+    }
+}
+
+impl FromStr for TerminalType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let [typ] = parse_hex(s, "Terminal type")?;
+        if !matches!(typ >> 4, 1..=3) || !matches!(typ & 0x0f, 1..=6) {
+            anyhow::bail!("Terminal type must be 11-36, got {:02x}", typ);
+        }
+        Ok(TerminalType(typ))
+    }
+}
+
+/// Terminal Capabilities (0x9f33), see EMV 4.4 Book 4 Annex A2
+///
+/// * Byte 1, card data input: 0x80 manual key entry, 0x40 magnetic stripe, 0x20 IC with contacts
+/// * Byte 2, CVM: 0x80 plaintext PIN for ICC verification, 0x40 enciphered PIN for online
+///   verification, 0x20 signature, 0x10 enciphered PIN for offline verification, 0x08 no CVM
+///   required
+/// * Byte 3, security: 0x80 SDA, 0x40 DDA, 0x20 card capture, 0x08 CDA
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TerminalCapabilities(pub [u8; 3]);
+
+impl Default for TerminalCapabilities {
+    /// All input methods, every CVM, and every offline data authentication method
+    fn default() -> Self {
+        TerminalCapabilities([0xe0, 0xf8, 0xc8])
+    }
+}
+
+impl FromStr for TerminalCapabilities {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_hex(s, "Terminal capabilities").map(TerminalCapabilities)
+    }
+}
+
+/// Meaning of each bit of the Additional Terminal Capabilities (0x9f40) as (byte, mask, name), see
+/// EMV 4.4 Book 4 Annex A3. Bytes 1-2 are transaction types, byte 3 data input, and bytes 4-5 data
+/// output.
+const ADDITIONAL_CAPABILITIES_FLAGS: &[(usize, u8, &str)] = &[
+    (0, 0x80, "Cash"),
+    (0, 0x40, "Goods"),
+    (0, 0x20, "Services"),
+    (0, 0x10, "Cashback"),
+    (0, 0x08, "Inquiry"),
+    (0, 0x04, "Transfer"),
+    (0, 0x02, "Payment"),
+    (0, 0x01, "Administrative"),
+    (1, 0x80, "Cash deposit"),
+    (2, 0x80, "Numeric keys"),
+    (2, 0x40, "Alphabetic and special characters keys"),
+    (2, 0x20, "Command keys"),
+    (2, 0x10, "Function keys"),
+    (3, 0x80, "Print, attendant"),
+    (3, 0x40, "Print, cardholder"),
+    (3, 0x20, "Display, attendant"),
+    (3, 0x10, "Display, cardholder"),
+    (3, 0x02, "Code table 10"),
+    (3, 0x01, "Code table 9"),
+    (4, 0x80, "Code table 8"),
+    (4, 0x40, "Code table 7"),
+    (4, 0x20, "Code table 6"),
+    (4, 0x10, "Code table 5"),
+    (4, 0x08, "Code table 4"),
+    (4, 0x04, "Code table 3"),
+    (4, 0x02, "Code table 2"),
+    (4, 0x01, "Code table 1"),
+];
+
+/// Names of the bits set in `bits`, which uses the Additional Terminal Capabilities layout
+pub fn additional_capabilities_names(bits: &[u8]) -> Vec<&'static str> {
+    bit_names(ADDITIONAL_CAPABILITIES_FLAGS, bits)
+}
+
+/// Additional Terminal Capabilities (0x9f40): supported transaction types and the terminal's data
+/// input and output capabilities
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AdditionalTerminalCapabilities(pub [u8; 5]);
+
+impl Default for AdditionalTerminalCapabilities {
+    /// Goods and services, numeric, command, and function keys, printer and displays for both
+    /// parties, and ISO 8859-1
+    fn default() -> Self {
+        AdditionalTerminalCapabilities([0x60, 0x00, 0xb0, 0xf0, 0x01])
+    }
+}
+
+impl AdditionalTerminalCapabilities {
+    pub fn flags(&self) -> Vec<&'static str> {
+        additional_capabilities_names(&self.0)
+    }
+}
+
+impl Display for AdditionalTerminalCapabilities {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Additional terminal capabilities {}",
+            hex::encode(self.0)
+        )?;
+        for flag in self.flags() {
+            write!(f, "\n    {}", flag)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for AdditionalTerminalCapabilities {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_hex(s, "Additional terminal capabilities").map(AdditionalTerminalCapabilities)
+    }
+}
+
+/// One of the Terminal Action Codes (Default, Denial, or Online), 5 bytes in the TVR layout. See
+/// [`crate::tvr::ActionCodes`].
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct TerminalActionCode(pub [u8; 5]);
+
+impl FromStr for TerminalActionCode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_hex(s, "Terminal action code").map(TerminalActionCode)
+    }
+}
+
+/// What the terminal tells the card about itself through the PDOL and CDOLs. The default is an
+/// attended, online capable merchant terminal.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct TerminalConfig {
+    pub terminal_type: TerminalType,
+    pub capabilities: TerminalCapabilities,
+    pub additional_capabilities: AdditionalTerminalCapabilities,
+}
+
+impl TerminalConfig {
+    /// Add the terminal data elements to the values available to DOLs
+    pub fn insert_into(&self, state: &mut OptionsMap) {
+        state.insert(0x9f35, Value::Binary(vec![self.terminal_type.0]));
+        state.insert(0x9f33, Value::Binary(self.capabilities.0.to_vec()));
+        state.insert(
+            0x9f40,
+            Value::Binary(self.additional_capabilities.0.to_vec()),
+        );
+    }
+}
+
+/// Terminal values from a JSON object mapping hex tags to values, in the format of `decode --json`.
+/// Each value must match the type of its tag. A tag given a list of values keeps the last one.
+pub fn values_from_json(json: &serde_json::Value) -> anyhow::Result<OptionsMap> {
+    let fields = fields_from_json(json)?;
+    Ok(fields
+        .into_iter()
+        .filter_map(|(tag, mut values)| Some((tag, values.pop()?)))
+        .collect())
+}
+
+/// Read terminal values for DOLs from a JSON file, see [`values_from_json`]
+pub fn load_values(path: &Path) -> anyhow::Result<OptionsMap> {
+    let input = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let json: serde_json::Value = serde_json::from_str(&input)
+        .with_context(|| format!("{} is not valid JSON", path.display()))?;
+    values_from_json(&json)
+        .with_context(|| format!("Invalid terminal values in {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("25".parse::<TerminalType>().unwrap(), TerminalType(0x25));
+        assert!("27".parse::<TerminalType>().is_err());
+        assert!("2".parse::<TerminalType>().is_err());
+        assert_eq!(
+            "e0b0c8".parse::<TerminalCapabilities>().unwrap(),
+            TerminalCapabilities([0xe0, 0xb0, 0xc8])
+        );
+        assert!("e0b0".parse::<TerminalCapabilities>().is_err());
+        assert_eq!(
+            "0010000000".parse::<TerminalActionCode>().unwrap(),
+            TerminalActionCode([0x00, 0x10, 0x00, 0x00, 0x00])
+        );
+        assert!("00100000".parse::<TerminalActionCode>().is_err());
+    }
+
+    #[test]
+    fn test_additional_capabilities() {
+        let capabilities = AdditionalTerminalCapabilities::default();
+        assert_eq!(
+            capabilities.flags(),
+            [
+                "Goods",
+                "Services",
+                "Numeric keys",
+                "Command keys",
+                "Function keys",
+                "Print, attendant",
+                "Print, cardholder",
+                "Display, attendant",
+                "Display, cardholder",
+                "Code table 1",
+            ]
+        );
+        assert_eq!(
+            AdditionalTerminalCapabilities([0x80, 0x80, 0, 0x01, 0]).to_string(),
+            "Additional terminal capabilities 8080000100\n    Cash\n    Cash deposit\n    \
+             Code table 9"
+        );
+        assert_eq!(additional_capabilities_names(&[0x01]), ["Administrative"]);
+    }
+
+    #[test]
+    fn test_values_from_json() {
+        let json = serde_json::json!({
+            "9f1a": "0840",
+            "9f1b": ["00001000", "00002000"],
+            "df01": "abcd",
+        });
+        let values = values_from_json(&json).unwrap();
+        assert_eq!(values.len(), 3);
+        assert_eq!(values[&0x9f1a], Value::Numeric(840));
+        assert_eq!(values[&0x9f1b], Value::Binary(vec![0, 0, 0x20, 0]));
+        assert_eq!(values[&0xdf01], Value::Binary(vec![0xab, 0xcd]));
+        assert!(values_from_json(&serde_json::json!({ "9f1a": "abc" })).is_err());
+    }
+}
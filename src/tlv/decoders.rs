@@ -1,7 +1,7 @@
 use crate::util::left_pad_slice;
 
 use super::dol::Dol;
-use super::elements::{ElementType, ELEMENTS};
+use super::elements::{is_constructed, toggle_constructed, ElementType, ELEMENTS};
 /// Decode what EMV calls "BER-TLV"
 /// This is a TLV (Tag, Length, Value) format where
 ///  * The tag is 1 or 2 bytes and represents the interpretation of the data, not just the type
@@ -14,7 +14,44 @@ use super::elements::{ElementType, ELEMENTS};
 /// For more information read EMV 4.4 Book 3 annex B1 and then cry.
 use super::{errors, DecodeError, FieldMap, Value};
 
-use std::str;
+use std::{cell::RefCell, collections::BTreeSet, str};
+
+thread_local! {
+    static UNKNOWN_TAGS: RefCell<Option<BTreeSet<u16>>> = const { RefCell::new(None) };
+}
+
+/// Start recording tags that are missing from [`ELEMENTS`] when decoded on this thread, see
+/// [`take_unknown_tags`]. Off by default.
+pub fn track_unknown_tags() {
+    UNKNOWN_TAGS.with_borrow_mut(|tags| *tags = Some(BTreeSet::new()));
+}
+
+/// Stop recording unknown tags and return the ones seen since [`track_unknown_tags`], sorted and
+/// without duplicates
+pub fn take_unknown_tags() -> Vec<u16> {
+    UNKNOWN_TAGS
+        .with_borrow_mut(Option::take)
+        .map(|tags| tags.into_iter().collect())
+        .unwrap_or_default()
+}
+
+fn record_unknown_tag(tag: u16) {
+    UNKNOWN_TAGS.with_borrow_mut(|tags| {
+        if let Some(tags) = tags {
+            tags.insert(tag);
+        }
+    });
+}
+
+/// Length of the tag starting at `first_byte`
+pub(super) fn tag_len(first_byte: u8) -> usize {
+    // If the bottom 5 bits are set this is supposed to be a 2 byte tag
+    if first_byte & 0b11111 == 0b11111 {
+        2
+    } else {
+        1
+    }
+}
 
 /// Decode the tag and length of a TLV string. This is only useful in template,
 /// as it will use this to cut down the data to the proper size.
@@ -24,8 +61,7 @@ pub fn read_tl(raw: &[u8]) -> Result<(u16, usize, usize), DecodeError> {
         return Err(DecodeError::MessageTooShort(2, raw.len()));
     }
 
-    // If the bottom 5 bits are set this is supposed to be a 2 byte tag
-    let tag_len = if raw[0] & 0b11111 == 0b11111 { 2 } else { 1 };
+    let tag_len = tag_len(raw[0]);
 
     // Length is always at least 1 byte
     if raw.len() < tag_len + 1 {
@@ -65,13 +101,11 @@ pub fn read_tl(raw: &[u8]) -> Result<(u16, usize, usize), DecodeError> {
     Ok((tag, len, tag_len + len_len))
 }
 
-fn decode_with_type(typ: ElementType, raw: &[u8]) -> Result<Value, DecodeError> {
+pub(super) fn decode_with_type(typ: ElementType, raw: &[u8]) -> Result<Value, DecodeError> {
     match typ {
-        ElementType::Alphabetic => alphabetic(raw).map(Value::Alphabetic),
-        ElementType::Alphanumeric => alphanumeric(raw).map(Value::Alphanumeric),
-        ElementType::AlphanumericSpecial => {
-            alphanumeric_special(raw).map(Value::AlphanumericSpecial)
-        }
+        ElementType::Alphabetic => alphabetic(raw),
+        ElementType::Alphanumeric => alphanumeric(raw),
+        ElementType::AlphanumericSpecial => alphanumeric_special(raw),
         ElementType::Binary => binary(raw).map(Value::Binary),
         ElementType::DigitString => compressed_numeric(raw).map(Value::DigitString),
         ElementType::Numeric => numeric(raw).map(Value::Numeric),
@@ -80,15 +114,59 @@ fn decode_with_type(typ: ElementType, raw: &[u8]) -> Result<Value, DecodeError>
     }
 }
 
-fn read_tlv(raw: &[u8]) -> Result<(u16, usize, Value), DecodeError> {
+/// Split off the tag and raw value of the TLV at the start of `raw` without decoding the value.
+/// Returns the tag, the value, and the length of the whole TLV.
+pub fn read_value(raw: &[u8]) -> Result<(u16, &[u8], usize), DecodeError> {
     let (tag, len, tl_len) = read_tl(raw)?;
-    let typ = ELEMENTS
-        .get(&tag)
-        .map(|&elem| elem.typ)
-        .unwrap_or(ElementType::Binary);
-    let value = decode_with_type(typ, &raw[tl_len..][..len])
-        .map_err(|err| DecodeError::TemplateInternal(tag, Box::new(err)))?;
-    Ok((tag, tl_len + len, value))
+    let field_len = tl_len.saturating_add(len);
+    if raw.len() < field_len {
+        return Err(DecodeError::MessageTooShort(field_len, raw.len()));
+    }
+    Ok((tag, &raw[tl_len..field_len], field_len))
+}
+
+/// Free-form text that cards don't always keep printable. These fall back to binary instead of
+/// failing the whole template.
+const BINARY_FALLBACK_TAGS: &[u16] = &[
+    0x5f50, // Issuer URL
+    0x5f54, // Bank Identifier Code (BIC)
+    0x9f4e, // Merchant Name and Location
+];
+
+/// Decode the value of a field according to the type of its tag, unknown tags are binary
+pub(super) fn decode_value(tag: u16, value_bytes: &[u8]) -> Result<Value, DecodeError> {
+    let typ = match ELEMENTS.get(&tag) {
+        Some(elem) => elem.typ,
+        // A constructed tag must hold TLVs. If it doesn't, this is most likely a known primitive
+        // field with the constructed bit set by mistake. Primitive tags can hold anything.
+        None if is_constructed(tag) && ELEMENTS.contains_key(&toggle_constructed(tag)) => {
+            record_unknown_tag(tag);
+            if template(value_bytes).is_err() {
+                return Err(DecodeError::TagEncodingMismatch(tag));
+            }
+            ElementType::Binary
+        }
+        None => {
+            record_unknown_tag(tag);
+            ElementType::Binary
+        }
+    };
+    match decode_with_type(typ, value_bytes) {
+        Err(err) if BINARY_FALLBACK_TAGS.contains(&tag) => {
+            log::debug!("Keeping 0x{:04x} as binary: {}", tag, err);
+            Ok(Value::Binary(value_bytes.to_vec()))
+        }
+        res => res.map_err(|err| DecodeError::TemplateInternal(tag, Box::new(err))),
+    }
+}
+
+/// Decode the TLV at the start of `raw`, returning the tag, the number of bytes it took up, and the
+/// value. Use the length to step through concatenated TLVs.
+pub fn read_tlv(raw: &[u8]) -> Result<(u16, usize, Value), DecodeError> {
+    let (tag, value_bytes, field_len) = read_value(raw)?;
+    let value = decode_value(tag, value_bytes)
+        .map_err(|err| err.offset_by(field_len - value_bytes.len()))?;
+    Ok((tag, field_len, value))
 }
 
 pub fn read_field(raw: &[u8]) -> Result<(u16, Value), DecodeError> {
@@ -96,6 +174,15 @@ pub fn read_field(raw: &[u8]) -> Result<(u16, Value), DecodeError> {
     Ok((tag, value))
 }
 
+/// Read a card response that is a single template, such as the `0x6f` FCI or a `0x77` response
+/// message, and return its contents
+pub fn parse_response(raw: &[u8]) -> Result<FieldMap, DecodeError> {
+    let (tag, value) = read_field(raw)?;
+    value
+        .into_template()
+        .ok_or(DecodeError::WrongType(tag, "Template"))
+}
+
 fn restricted_charset(
     raw: &[u8],
     predicate: impl Fn(&u8) -> bool,
@@ -108,11 +195,17 @@ fn restricted_charset(
     }
 }
 
-pub fn alphabetic(raw: &[u8]) -> Result<String, DecodeError> {
+pub fn alphabetic(raw: &[u8]) -> Result<Value, DecodeError> {
     restricted_charset(raw, u8::is_ascii_alphabetic, errors::StringType::Alphabetic)
+        .map(Value::Alphabetic)
+}
+
+pub fn alphanumeric(raw: &[u8]) -> Result<Value, DecodeError> {
+    alphanumeric_string(raw).map(Value::Alphanumeric)
 }
 
-pub fn alphanumeric(raw: &[u8]) -> Result<String, DecodeError> {
+/// [`alphanumeric`] for values that aren't stored as a [`Value`], such as the IBAN
+pub(crate) fn alphanumeric_string(raw: &[u8]) -> Result<String, DecodeError> {
     restricted_charset(
         raw,
         u8::is_ascii_alphanumeric,
@@ -120,7 +213,7 @@ pub fn alphanumeric(raw: &[u8]) -> Result<String, DecodeError> {
     )
 }
 
-pub fn alphanumeric_special(raw: &[u8]) -> Result<String, DecodeError> {
+pub fn alphanumeric_special(raw: &[u8]) -> Result<Value, DecodeError> {
     let mut s = String::with_capacity(raw.len());
     for &b in raw {
         // I don't even care anymore.
@@ -139,7 +232,7 @@ pub fn alphanumeric_special(raw: &[u8]) -> Result<String, DecodeError> {
         }
         s.push(b as char);
     }
-    Ok(s)
+    Ok(Value::AlphanumericSpecial(s))
 }
 
 pub fn binary(raw: &[u8]) -> Result<Vec<u8>, DecodeError> {
@@ -169,27 +262,100 @@ pub fn compressed_numeric(raw: &[u8]) -> Result<Vec<u8>, DecodeError> {
 }
 
 pub fn numeric(raw: &[u8]) -> Result<u128, DecodeError> {
+    // 38 digits always fit in a u128, 39 might not
+    if raw.len() > 19 {
+        return Err(DecodeError::LengthTooLong(19, raw.len()));
+    }
+
     raw.iter()
         .flat_map(|byte| [byte >> 4, byte & 0x0f])
         .try_fold(0, |acc, digit| {
             if digit <= 9 {
-                Ok(acc * 10 + digit as u128) //TODO handle overflow
+                Ok(acc * 10 + digit as u128)
             } else {
                 Err(DecodeError::BadBcd(digit))
             }
         })
 }
 
+/// Skip the 0x00 and 0xff filler some cards put between fields. Neither is a valid first tag
+/// byte. (Book 3 Annex B1)
+fn skip_padding(raw: &[u8]) -> &[u8] {
+    let start = raw
+        .iter()
+        .position(|&b| b != 0x00 && b != 0xff)
+        .unwrap_or(raw.len());
+    &raw[start..]
+}
+
 pub fn template(mut raw: &[u8]) -> Result<FieldMap, DecodeError> {
+    let template_len = raw.len();
     let mut fields = FieldMap::new();
-    while !raw.is_empty() {
-        let (tag, len, value) = read_tlv(raw)?;
+    loop {
+        raw = skip_padding(raw);
+        if raw.is_empty() {
+            break;
+        }
+        let offset = template_len - raw.len();
+        let (tag, len, value) = read_tlv(raw)
+            .map_err(|err| DecodeError::AtOffset(offset, Box::new(err.offset_by(offset))))?;
         raw = &raw[len..];
         fields.insert(tag, value);
     }
     Ok(fields)
 }
 
+/// Iterator over the fields of a template that borrows the values instead of decoding them, for
+/// read-only paths that only need a few fields. See [`raw_fields`].
+#[derive(Debug, Clone)]
+pub struct RawFields<'a> {
+    raw: &'a [u8],
+}
+
+impl<'a> Iterator for RawFields<'a> {
+    type Item = Result<(u16, &'a [u8]), DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.raw = skip_padding(self.raw);
+        if self.raw.is_empty() {
+            return None;
+        }
+        match read_value(self.raw) {
+            Ok((tag, value, field_len)) => {
+                self.raw = &self.raw[field_len..];
+                Some(Ok((tag, value)))
+            }
+            Err(err) => {
+                // There's no way to find the next field after a bad one
+                self.raw = &[];
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// Iterate over the tags and raw values in a template without copying anything. Nested templates
+/// are returned as their raw contents and can be walked with another call.
+pub fn raw_fields(raw: &[u8]) -> RawFields<'_> {
+    RawFields { raw }
+}
+
+/// Decode a list of tags with no lengths or values, such as the Static Data Authentication Tag
+/// List (0x9f4a)
+pub fn tag_list(mut raw: &[u8]) -> Result<Vec<u16>, DecodeError> {
+    let mut tags = Vec::new();
+    while let Some(&first_byte) = raw.first() {
+        let tag_len = tag_len(first_byte);
+        if raw.len() < tag_len {
+            return Err(DecodeError::MessageTooShort(tag_len, raw.len()));
+        }
+        let (tag_bytes, rest) = raw.split_at(tag_len);
+        tags.push(u16::from_be_bytes(left_pad_slice(tag_bytes)));
+        raw = rest;
+    }
+    Ok(tags)
+}
+
 pub fn dol(raw: &[u8]) -> Result<Dol, DecodeError> {
     Dol::try_from(raw)
 }
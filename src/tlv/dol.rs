@@ -1,6 +1,10 @@
 use std::{cmp::min, fmt::Display};
 
-use super::{decoders::read_tl, DecodeError, OptionsMap, Value};
+use super::{
+    decoders::{decode_with_type, read_tl},
+    elements::{ElementType, ELEMENTS},
+    encoders, DecodeError, OptionsMap, TagMap, Value,
+};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct DOLEntry {
@@ -28,67 +32,27 @@ impl Dol {
         &self.entries
     }
 
-    pub fn encode(&self, tag: Option<u16>, data: &OptionsMap) -> Vec<u8> {
-        let mut encoded;
-        let mut encoded_slice;
-
+    /// Encode the values requested by this DOL. If `tag` is present the output is wrapped in a
+    /// TLV with that tag, as GET PROCESSING OPTIONS needs for its command template (0x83).
+    pub fn encode(&self, tag: Option<u16>, data: &impl TagMap) -> Vec<u8> {
+        let mut encoded = Vec::with_capacity(self.size + 6);
         if let Some(t) = tag {
-            let tag_len = if t.leading_zeros() < 8 {
-                2usize
-            } else {
-                1usize
-            };
-            let len_len = if self.size < 256 {
-                1usize
-            } else {
-                self.size.ilog2() as usize / 8 + 2
-            };
-
-            encoded = vec![0; tag_len + len_len + self.size];
-            if tag_len == 1 {
-                encoded[0] = t as u8;
-            } else {
-                encoded[0..2].copy_from_slice(&t.to_be_bytes());
-            }
-            if len_len == 1 {
-                encoded[tag_len] = self.size as u8;
-            } else {
-                encoded[tag_len] = 0x80 | (len_len - 1) as u8;
-                let size_be = self.size.to_be_bytes();
-                encoded[tag_len + 1..tag_len + len_len]
-                    .copy_from_slice(&size_be[size_be.len() - len_len + 1..]);
-            }
-            encoded_slice = &mut encoded.as_mut_slice()[tag_len + len_len..];
-        } else {
-            encoded = vec![0; self.size];
-            encoded_slice = encoded.as_mut_slice();
+            encoders::write_tl(t, self.size, &mut encoded);
         }
+        let header_len = encoded.len();
+        encoded.resize(header_len + self.size, 0);
+
+        let mut encoded_slice = &mut encoded[header_len..];
         for entry in &self.entries {
             let (dest, remaining) = encoded_slice.split_at_mut(entry.size);
-            if let Some(value) = data.get(&entry.tag) {
+            if let Some(value) = data.get_tag(entry.tag) {
                 match value {
                     Value::Alphabetic(s) => Self::copy_bytes(s.as_bytes(), dest),
                     Value::Alphanumeric(s) => Self::copy_bytes(s.as_bytes(), dest),
                     Value::AlphanumericSpecial(s) => Self::copy_bytes(s.as_bytes(), dest),
                     Value::Binary(b) => Self::copy_bytes(b, dest),
-                    Value::DigitString(s) => {
-                        dest.fill(0xff);
-                        for (digits, dest) in s.chunks(2).zip(dest.iter_mut()) {
-                            *dest = match digits {
-                                [single] => single << 4 | 0x0f,
-                                [higher, lower] => higher << 4 | lower,
-                                [..] => unreachable!(), // slice::chunks(2) cannot return any other sizes
-                            }
-                        }
-                    }
-                    Value::Numeric(mut number) => {
-                        for dest in dest.iter_mut().rev() {
-                            let digits: u8 = (number % 100).try_into().unwrap();
-                            number /= 100;
-
-                            *dest = (digits / 10) << 4 | (digits % 10);
-                        }
-                    }
+                    Value::DigitString(s) => encoders::pack_digit_string(s, dest),
+                    Value::Numeric(number) => encoders::pack_numeric(*number, dest),
                     // Templates should just be all zeroes
                     Value::Template(_) => {}
                     // Technically this would be binary to the card but it should never ask
@@ -102,9 +66,51 @@ impl Dol {
         encoded
     }
 
+    /// Encode the values requested by this DOL as a bare concatenation with no tag or length, as
+    /// used by INTERNAL AUTHENTICATE and GENERATE AC. Equivalent to `encode(None, data)`.
+    pub fn encode_bare(&self, data: &impl TagMap) -> Vec<u8> {
+        self.encode(None, data)
+    }
+
+    /// Split data built from this DOL back into values, the inverse of [`Dol::encode_bare`].
+    /// Strings have their zero padding removed. If a tag is requested more than once only the
+    /// last value is kept.
+    pub fn decode(&self, data: &[u8]) -> Result<OptionsMap, DecodeError> {
+        if data.len() < self.size {
+            return Err(DecodeError::MessageTooShort(self.size, data.len()));
+        } else if data.len() > self.size {
+            return Err(DecodeError::LengthTooLong(self.size, data.len()));
+        }
+
+        let mut values = OptionsMap::with_capacity(self.entries.len());
+        let mut remaining = data;
+        for entry in &self.entries {
+            let (raw, rest) = remaining.split_at(entry.size);
+            let typ = ELEMENTS
+                .get(&entry.tag)
+                .map_or(ElementType::Binary, |elem| elem.typ);
+            let value = match typ {
+                ElementType::Alphabetic
+                | ElementType::Alphanumeric
+                | ElementType::AlphanumericSpecial => {
+                    let len = raw.iter().rposition(|&b| b != 0).map_or(0, |idx| idx + 1);
+                    decode_with_type(typ, &raw[..len])
+                }
+                // We never send these, see encode
+                ElementType::Template | ElementType::Dol => Ok(Value::Binary(raw.to_vec())),
+                _ => decode_with_type(typ, raw),
+            }
+            .map_err(|err| DecodeError::TemplateInternal(entry.tag, Box::new(err)))?;
+            values.insert(entry.tag, value);
+            remaining = rest;
+        }
+
+        Ok(values)
+    }
+
     fn copy_bytes(b: &[u8], out: &mut [u8]) {
         let copied_len = min(b.len(), out.len());
-        out[..copied_len].copy_from_slice(b);
+        out[..copied_len].copy_from_slice(&b[..copied_len]);
     }
 }
 
@@ -15,6 +15,52 @@ pub enum ElementType {
     Dol,
 }
 
+impl ElementType {
+    pub fn name(self) -> &'static str {
+        match self {
+            ElementType::Alphabetic => "Alphabetic",
+            ElementType::Alphanumeric => "Alphanumeric",
+            ElementType::AlphanumericSpecial => "AlphanumericSpecial",
+            ElementType::Binary => "Binary",
+            ElementType::DigitString => "DigitString",
+            ElementType::Numeric => "Numeric",
+            ElementType::Template => "Template",
+            ElementType::Dol => "Dol",
+        }
+    }
+}
+
+/// Whether `tag` has the constructed bit set in its first byte, meaning its value is a sequence
+/// of TLVs, see EMV 4.4 Book 3 Annex B2
+pub fn is_constructed(tag: u16) -> bool {
+    tag & constructed_bit(tag) != 0
+}
+
+/// `tag` with the constructed bit flipped, the same tag number with the other encoding
+pub fn toggle_constructed(tag: u16) -> u16 {
+    tag ^ constructed_bit(tag)
+}
+
+/// Length in bytes of the numeric (BCD) elements in [`ELEMENTS`], which are all fixed length and
+/// left padded with zeroes
+pub fn numeric_length(tag: u16) -> Option<usize> {
+    match tag {
+        0x009c | 0x5f34 => Some(1),
+        0x5f28 | 0x5f2a | 0x5f30 | 0x9f1a | 0x9f25 | 0x9f3c | 0x9f42 => Some(2),
+        0x0042 | 0x009a => Some(3),
+        0x9f02 | 0x9f03 => Some(6),
+        _ => None,
+    }
+}
+
+fn constructed_bit(tag: u16) -> u16 {
+    if tag > 0xff {
+        0x2000
+    } else {
+        0x20
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct DataElement {
     pub tag: u16,
@@ -58,6 +104,7 @@ lazy_static! {
         0x0042 => "Issuer Identification Number (IIN)": Numeric,
         0x004f => "Application Dedicated File (ADF) Name": Binary,
         0x0050 => "Application Label": AlphanumericSpecial,
+        0x0056 => "Track 1 Data": Binary,
         0x0057 => "Track 2 Equivalent Data": Binary,
         0x005a => "Application Primary Account Number (PAN)" ("PAN"): DigitString,
         0x0061 => "Application Template": Template,
@@ -89,29 +136,29 @@ lazy_static! {
         0x0095 => "Terminal Verification Results": Binary,
         0x0097 => "Transaction Certificate Data Object List (TDOL)": Dol,
         0x0098 => "Transaction Certificate (TC) Hash Value": Binary,
-        0x009a => "Transaction Date": Binary,
+        0x009a => "Transaction Date": Numeric,
         0x009b => "Transaction Status Information": Binary,
-        0x009c => "Transaction Type": Binary,
+        0x009c => "Transaction Type": Numeric,
         0x009d => "Directory Definition File (DDF) Name": Binary,
         0x00a5 => "File Control Information (FCI) Proprietary Template": Template,
         0x5f20 => "Cardholder Name": AlphanumericSpecial,
         0x5f24 => "Application Expiration Date": Binary,
         0x5f25 => "Application Effective Date": Binary,
-        0x5f28 => "Issuer Country Code": Binary,
+        0x5f28 => "Issuer Country Code": Numeric,
         0x5f2a => "Transaction Currency Code": Numeric,
         0x5f2d => "Language Preference": Alphanumeric,
-        0x5f30 => "Service Code": Binary,
+        0x5f30 => "Service Code": Numeric,
         0x5f34 => "Application Primary Account Number (PAN) Sequence Number": Numeric,
         0x5f36 => "Transaction Currency Exponent": Binary,
-        0x5f50 => "Issuer URL": Binary,
+        0x5f50 => "Issuer URL": AlphanumericSpecial,
         0x5f53 => "International Bank Account Number (IBAN)": Binary,
-        0x5f54 => "Bank Identifier Code (BIC)": Binary,
+        0x5f54 => "Bank Identifier Code (BIC)": Alphanumeric,
         0x5f55 => "Issuer Country Code (alpha2 format)": Alphabetic,
         0x5f56 => "Issuer Country Code (alpha3 format)": Alphabetic,
         0x5f57 => "Account Type": Binary,
         0x9f01 => "Acquirer Identifier": Binary,
-        0x9f02 => "Amount, Authorised (Numeric)": Binary,
-        0x9f03 => "Amount, Other (Numeric)": Binary,
+        0x9f02 => "Amount, Authorised (Numeric)": Numeric,
+        0x9f03 => "Amount, Other (Numeric)": Numeric,
         0x9f04 => "Amount, Other (Binary)": Binary,
         0x9f05 => "Application Discretionary Data": Binary,
         0x9f06 => "Application Identifier (AID) - terminal": Binary,
@@ -125,12 +172,13 @@ lazy_static! {
         0x9f10 => "Issuer Application Data": Binary,
         0x9f11 => "Issuer Code Table Index": Binary,
         0x9f12 => "Application Preferred Name": AlphanumericSpecial,
+        0x9f13 => "Last Online Application Transaction Counter (ATC) Register": Binary,
         0x9f14 => "Lower Consecutive Offline Limit": Binary,
         0x9f15 => "Merchant Category Code": Binary,
         0x9f16 => "Merchant Identifier": Binary,
         0x9f17 => "Personal Identification Number (PIN) Try Counter": Binary,
         0x9f18 => "Issuer Script Identifier": Binary,
-        0x9f1a => "Terminal Country Code": Binary,
+        0x9f1a => "Terminal Country Code": Numeric,
         0x9f1b => "Terminal Floor Limit": Binary,
         0x9f1c => "Terminal Identification": Binary,
         0x9f1d => "Terminal Risk Management Data": Binary,
@@ -144,6 +192,7 @@ lazy_static! {
         0x9f25 => "Last 4 Digits of PAN": Numeric,
         0x9f26 => "Application Cryptogram": Binary,
         0x9f27 => "Cryptogram Information Data": Binary,
+        0x9f2a => "Kernel Identifier": Binary,
         0x9f2d => "ICC PIN Encipherment Public Key Certificate": Binary,
         0x9f2e => "ICC PIN Encipherment Public Key Exponent": Binary,
         0x9f2f => "ICC PIN Encipherment Public Key Remainder": Binary,
@@ -157,11 +206,11 @@ lazy_static! {
         0x9f39 => "Point-of-Service (POS) Entry Mode": Binary,
         0x9f3a => "Amount, Reference Currency": Binary,
         0x9f3b => "Application Reference Currency": Binary,
-        0x9f3c => "Transaction Reference Currency Code": Binary,
+        0x9f3c => "Transaction Reference Currency Code": Numeric,
         0x9f3d => "Transaction Reference Currency Exponent": Binary,
         0x9f40 => "Additional Terminal Capabilities": Binary,
         0x9f41 => "Transaction Sequence Counter": Binary,
-        0x9f42 => "Application Currency Code": Binary,
+        0x9f42 => "Application Currency Code": Numeric,
         0x9f43 => "Application Reference Currency Exponent": Binary,
         0x9f44 => "Application Currency Exponent": Binary,
         0x9f45 => "Data Authentication Code": Binary,
@@ -173,8 +222,13 @@ lazy_static! {
         0x9f4b => "Signed Dynamic Application Data": Binary,
         0x9f4c => "ICC Dynamic Number": Binary,
         0x9f4d => "Log Entry": Binary,
-        0x9f4e => "Merchant Name and Location": Binary,
+        0x9f4e => "Merchant Name and Location": AlphanumericSpecial,
         0x9f4f => "Log Format": Binary,
+        0x9f5b => "Issuer Script Results": Binary,
+        0x9f66 => "Terminal Transaction Qualifiers (TTQ)": Binary,
+        0x9f6b => "Track 2 Data": Binary,
+        0x9f6c => "Card Transaction Qualifiers (CTQ)": Binary,
+        0x9f6e => "Form Factor Indicator": Binary,
         0xbf0c => "FCI Issuer Discretionary Data": Template,
     ];
 }
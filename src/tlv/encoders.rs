@@ -0,0 +1,115 @@
+//! Encode values back into BER-TLV, the inverse of [`super::decoders`].
+//!
+//! Since a [`FieldMap`] does not keep track of the order fields were read in, templates are encoded
+//! with their fields sorted by tag.
+use super::{dol::Dol, elements::numeric_length, FieldMap, Value};
+
+/// Encode a tag and length. Lengths of 128 bytes or more use the multi-byte form.
+pub fn write_tl(tag: u16, len: usize, out: &mut Vec<u8>) {
+    if tag > 0xff {
+        out.extend_from_slice(&tag.to_be_bytes());
+    } else {
+        out.push(tag as u8);
+    }
+
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let len_bytes = len.to_be_bytes();
+        let skip = len_bytes.iter().take_while(|&&b| b == 0).count();
+        out.push(0x80 | (len_bytes.len() - skip) as u8);
+        out.extend_from_slice(&len_bytes[skip..]);
+    }
+}
+
+/// Pack digits two per byte, padding the rest of `dest` with 0xf nibbles
+pub fn pack_digit_string(digits: &[u8], dest: &mut [u8]) {
+    dest.fill(0xff);
+    for (digits, dest) in digits.chunks(2).zip(dest.iter_mut()) {
+        *dest = match digits {
+            [single] => single << 4 | 0x0f,
+            [higher, lower] => higher << 4 | lower,
+            [..] => unreachable!(), // slice::chunks(2) cannot return any other sizes
+        }
+    }
+}
+
+/// Pack a number as BCD right-aligned in `dest`, truncating the most significant digits if it
+/// doesn't fit
+pub fn pack_numeric(mut number: u128, dest: &mut [u8]) {
+    for dest in dest.iter_mut().rev() {
+        let digits: u8 = (number % 100).try_into().unwrap();
+        number /= 100;
+
+        *dest = (digits / 10) << 4 | (digits % 10);
+    }
+}
+
+pub fn compressed_numeric(digits: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![0; digits.len().div_ceil(2)];
+    pack_digit_string(digits, &mut encoded);
+    encoded
+}
+
+/// Encode a number as BCD using as few bytes as possible
+pub fn numeric(number: u128) -> Vec<u8> {
+    let digits = number.checked_ilog10().unwrap_or(0) as usize + 1;
+    let mut encoded = vec![0; digits.div_ceil(2)];
+    pack_numeric(number, &mut encoded);
+    encoded
+}
+
+/// Encode a number as BCD at the fixed length of `tag`, or as few bytes as possible if the tag has
+/// no fixed length or the number doesn't fit
+pub fn numeric_field(tag: u16, number: u128) -> Vec<u8> {
+    let encoded = numeric(number);
+    match numeric_length(tag) {
+        Some(len) if len > encoded.len() => {
+            let mut padded = vec![0; len];
+            padded[len - encoded.len()..].copy_from_slice(&encoded);
+            padded
+        }
+        _ => encoded,
+    }
+}
+
+pub fn dol(dol: &Dol) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    for entry in dol.get_entries() {
+        write_tl(entry.tag, entry.size, &mut encoded);
+    }
+    encoded
+}
+
+/// Encode just the value part of a TLV with tag `tag`
+pub fn value(tag: u16, value: &Value) -> Vec<u8> {
+    match value {
+        Value::Alphabetic(s) | Value::Alphanumeric(s) | Value::AlphanumericSpecial(s) => {
+            s.as_bytes().to_vec()
+        }
+        Value::Binary(b) => b.clone(),
+        Value::DigitString(digits) => compressed_numeric(digits),
+        Value::Numeric(n) => numeric_field(tag, *n),
+        Value::Template(fields) => template(fields),
+        Value::Dol(d) => dol(d),
+    }
+}
+
+pub fn write_field(tag: u16, field_value: &Value, out: &mut Vec<u8>) {
+    let encoded = value(tag, field_value);
+    write_tl(tag, encoded.len(), out);
+    out.extend_from_slice(&encoded);
+}
+
+pub fn template(fields: &FieldMap) -> Vec<u8> {
+    let mut sorted: Vec<_> = fields.iter_all().collect();
+    sorted.sort_by_key(|(tag, _)| **tag);
+
+    let mut encoded = Vec::new();
+    for (&tag, values) in sorted {
+        for field_value in values {
+            write_field(tag, field_value, &mut encoded);
+        }
+    }
+    encoded
+}
@@ -1,5 +1,8 @@
 use std::error::Error;
 use std::fmt::Display;
+use std::io::ErrorKind;
+
+use super::elements::toggle_constructed;
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum StringType {
@@ -18,6 +21,42 @@ pub enum DecodeError {
     NoPathRequested,
     WrongType(u16, &'static str),
     NoSuchMember(u16),
+    InvalidTag(String),
+    InvalidAfl(usize, &'static str),
+    Io(ErrorKind),
+    /// An error in the field starting at this many bytes into the data being decoded. Offsets in
+    /// nested templates count from the start of the outermost one too.
+    AtOffset(usize, Box<DecodeError>),
+    /// The tag is unknown and constructed, but its value isn't a template and the same tag is
+    /// known as primitive, so the card set the constructed bit by mistake
+    TagEncodingMismatch(u16),
+}
+
+impl DecodeError {
+    /// The underlying error without the [`DecodeError::AtOffset`] and
+    /// [`DecodeError::TemplateInternal`] context around it
+    pub fn root_cause(&self) -> &DecodeError {
+        match self {
+            DecodeError::AtOffset(_, err) | DecodeError::TemplateInternal(_, err) => {
+                err.root_cause()
+            }
+            err => err,
+        }
+    }
+
+    /// Move the offsets of errors inside nested templates `by` bytes further along, for when the
+    /// template they were found in starts that far into its parent
+    pub(super) fn offset_by(self, by: usize) -> DecodeError {
+        match self {
+            DecodeError::AtOffset(offset, err) => {
+                DecodeError::AtOffset(offset + by, Box::new(err.offset_by(by)))
+            }
+            DecodeError::TemplateInternal(tag, err) => {
+                DecodeError::TemplateInternal(tag, Box::new(err.offset_by(by)))
+            }
+            err => err,
+        }
+    }
 }
 
 impl Display for DecodeError {
@@ -45,6 +84,24 @@ impl Display for DecodeError {
             DecodeError::NoSuchMember(tag) => {
                 write!(f, "No member of template with tag 0x{:04x}", tag)
             }
+            DecodeError::InvalidTag(ref tag) => write!(f, "Invalid tag \"{}\"", tag),
+            DecodeError::InvalidAfl(entry, reason) => {
+                write!(f, "Invalid AFL entry {}: {}", entry, reason)
+            }
+            DecodeError::Io(kind) => write!(f, "Failed to read TLV: {}", kind),
+            DecodeError::TagEncodingMismatch(tag) => write!(
+                f,
+                "Tag 0x{:04x} is constructed but not a template, and 0x{:04x} is a known \
+                 primitive tag",
+                tag,
+                toggle_constructed(tag)
+            ),
+            DecodeError::AtOffset(offset, ref err) => match **err {
+                DecodeError::TemplateInternal(tag, ref err) => {
+                    write!(f, "At byte {}, tag 0x{:04x}: {}", offset, tag, err)
+                }
+                ref err => write!(f, "At byte {}: {}", offset, err),
+            },
         }
     }
 }
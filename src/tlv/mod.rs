@@ -1,11 +1,15 @@
 pub mod decoders;
 pub mod dol;
 pub mod elements;
+pub mod encoders;
 pub mod errors;
+pub mod reader;
+pub mod serialize;
 #[cfg(test)]
 mod tests;
 mod types;
 
-pub use self::decoders::read_field;
+pub use self::decoders::{parse_response, read_field, read_tlv};
 pub use self::errors::DecodeError;
+pub use self::reader::TlvReader;
 pub use self::types::*;
@@ -0,0 +1,82 @@
+use std::io::{ErrorKind, Read};
+
+use super::decoders::{decode_value, read_tl, tag_len};
+use super::{DecodeError, Value};
+
+/// Reads fields one at a time from a stream of concatenated TLVs, such as captured responses
+/// piped in from another tool. Only one field is held in memory at a time. Iteration stops at the
+/// end of the stream or after the first error.
+#[derive(Debug)]
+pub struct TlvReader<R> {
+    inner: R,
+    done: bool,
+}
+
+impl<R: Read> TlvReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner, done: false }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Fill `buf`, where `offset` bytes of the field have already been read
+    fn read_exact(&mut self, buf: &mut [u8], offset: usize) -> Result<(), DecodeError> {
+        self.inner.read_exact(buf).map_err(|err| match err.kind() {
+            ErrorKind::UnexpectedEof => DecodeError::MessageTooShort(offset + buf.len(), offset),
+            kind => DecodeError::Io(kind),
+        })
+    }
+
+    fn read_field(&mut self, first_byte: u8) -> Result<(u16, Value), DecodeError> {
+        // At most 2 bytes of tag, then 1 + 4 bytes of length. Longer lengths are rejected by
+        // read_tl before we need the bytes.
+        let mut header = [first_byte, 0, 0, 0, 0, 0, 0];
+        let tag_len = tag_len(first_byte);
+        self.read_exact(&mut header[1..tag_len + 1], 1)?;
+        let mut header_len = tag_len + 1;
+        let len_byte = header[tag_len];
+        if len_byte & 0x80 == 0x80 {
+            let num_bytes = ((len_byte & 0x7f) as usize).min(4);
+            self.read_exact(&mut header[header_len..header_len + num_bytes], header_len)?;
+            header_len += num_bytes;
+        }
+        let (tag, len, _) = read_tl(&header[..header_len])?;
+
+        // Grows as data arrives instead of trusting the length for the allocation
+        let mut value_bytes = Vec::new();
+        (&mut self.inner)
+            .take(len as u64)
+            .read_to_end(&mut value_bytes)
+            .map_err(|err| DecodeError::Io(err.kind()))?;
+        if value_bytes.len() < len {
+            return Err(DecodeError::MessageTooShort(
+                header_len + len,
+                header_len + value_bytes.len(),
+            ));
+        }
+        Ok((tag, decode_value(tag, &value_bytes)?))
+    }
+}
+
+impl<R: Read> Iterator for TlvReader<R> {
+    type Item = Result<(u16, Value), DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let mut first_byte = [0];
+        let result = match self.inner.read_exact(&mut first_byte) {
+            Ok(()) => self.read_field(first_byte[0]),
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => {
+                self.done = true;
+                return None;
+            }
+            Err(err) => Err(DecodeError::Io(err.kind())),
+        };
+        self.done = result.is_err();
+        Some(result)
+    }
+}
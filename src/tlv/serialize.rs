@@ -0,0 +1,284 @@
+use serde::{ser::SerializeMap, ser::SerializeStruct, Serialize, Serializer};
+
+use crate::pan::{mask_pan_in, masked_value};
+
+use super::{
+    decoders,
+    dol::{DOLEntry, Dol},
+    elements::{ElementType, ELEMENTS},
+    DecodeError, FieldMap, Value,
+};
+
+/// Serializes a [`FieldMap`] as a map from hex tag (e.g. `"9f37"`) to value. Tags which appear more
+/// than once are serialized as a list of values. Values of [`crate::pan::PAN_TAGS`] are masked unless
+/// [`FieldMapSerialize::show_pan`] is set.
+pub struct FieldMapSerialize<'a> {
+    fields: &'a FieldMap,
+    show_pan: bool,
+}
+
+impl<'a> FieldMapSerialize<'a> {
+    pub(super) fn new(fields: &'a FieldMap) -> Self {
+        Self {
+            fields,
+            show_pan: false,
+        }
+    }
+
+    /// Serialize full PANs instead of masking them
+    pub fn show_pan(self, show_pan: bool) -> Self {
+        Self { show_pan, ..self }
+    }
+}
+
+/// A single value of a [`FieldMapSerialize`], which needs the tag and settings for masking
+struct FieldSerialize<'a> {
+    tag: u16,
+    value: &'a Value,
+    show_pan: bool,
+}
+
+impl Serialize for FieldSerialize<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if let Value::Template(fields) = self.value {
+            return FieldMapSerialize::new(fields)
+                .show_pan(self.show_pan)
+                .serialize(serializer);
+        }
+        match masked_value(self.tag, self.value) {
+            Some(masked) if !self.show_pan => serializer.serialize_str(&masked),
+            _ => self.value.serialize(serializer),
+        }
+    }
+}
+
+impl Serialize for FieldMapSerialize<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut fields: Vec<_> = self.fields.iter_all().collect();
+        fields.sort_by_key(|(tag, _)| **tag);
+
+        let mut map = serializer.serialize_map(Some(fields.len()))?;
+        for (&tag, values) in fields {
+            let key = format!("{:02x}", tag);
+            let values: Vec<_> = values
+                .iter()
+                .map(|value| FieldSerialize {
+                    tag,
+                    value,
+                    show_pan: self.show_pan,
+                })
+                .collect();
+            match values.as_slice() {
+                [value] => map.serialize_entry(&key, value)?,
+                values => map.serialize_entry(&key, values)?,
+            }
+        }
+        map.end()
+    }
+}
+
+/// For use with `#[serde(serialize_with)]` on [`FieldMap`] struct fields
+pub fn serialize_field_map<S: Serializer>(
+    fields: &FieldMap,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    FieldMapSerialize::new(fields).serialize(serializer)
+}
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Alphabetic(s) | Value::Alphanumeric(s) | Value::AlphanumericSpecial(s) => {
+                serializer.serialize_str(s)
+            }
+            Value::Binary(data) => serializer.serialize_str(&hex::encode(data)),
+            Value::DigitString(digits) => serializer.collect_str(
+                &digits
+                    .iter()
+                    .map(|&digit| char::from_digit(digit as u32, 10).unwrap())
+                    .collect::<String>(),
+            ),
+            Value::Numeric(n) => serializer.serialize_u128(*n),
+            Value::Template(fields) => FieldMapSerialize::new(fields).serialize(serializer),
+            Value::Dol(dol) => dol.serialize(serializer),
+        }
+    }
+}
+
+impl Serialize for Dol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.get_entries())
+    }
+}
+
+impl Serialize for DOLEntry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut entry = serializer.serialize_struct("DOLEntry", 2)?;
+        entry.serialize_field("tag", &format!("{:02x}", self.tag))?;
+        entry.serialize_field("size", &self.size)?;
+        entry.end()
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Alphabetic(_) => ElementType::Alphabetic,
+        Value::Alphanumeric(_) => ElementType::Alphanumeric,
+        Value::AlphanumericSpecial(_) => ElementType::AlphanumericSpecial,
+        Value::Binary(_) => ElementType::Binary,
+        Value::DigitString(_) => ElementType::DigitString,
+        Value::Numeric(_) => ElementType::Numeric,
+        Value::Template(_) => ElementType::Template,
+        Value::Dol(_) => ElementType::Dol,
+    }
+    .name()
+}
+
+/// Describe a field as a node of a JSON tree for TLV viewers. Every node has the `tag`, the `name`
+/// from [`ELEMENTS`] (or `null`), and the `type`. Templates have a list of `children`, sorted by
+/// tag, and other fields the decoded `value` and the `raw` value bytes as hex. PANs are masked in
+/// both unless `show_pan` is set.
+pub fn to_json_tree(
+    tag: u16,
+    raw: &[u8],
+    show_pan: bool,
+) -> Result<serde_json::Value, DecodeError> {
+    let value = decoders::decode_value(tag, raw)?;
+    let mut node = serde_json::json!({
+        "tag": format!("0x{:02x}", tag),
+        "name": ELEMENTS.get(&tag).map(|elem| elem.name),
+        "type": type_name(&value),
+    });
+    match value {
+        Value::Template(_) => node["children"] = fields_to_json_tree(raw, show_pan)?,
+        value => match masked_value(tag, &value) {
+            Some(masked) if !show_pan => {
+                node["raw"] = match value {
+                    Value::DigitString(ref pan) => mask_pan_in(&hex::encode(raw), pan),
+                    _ => masked.clone(),
+                }
+                .into();
+                node["value"] = masked.into();
+            }
+            _ => {
+                node["value"] = serde_json::to_value(value).unwrap();
+                node["raw"] = hex::encode(raw).into();
+            }
+        },
+    }
+    Ok(node)
+}
+
+/// Describe each field of the template contents `raw` with [`to_json_tree`], sorted by tag
+pub fn fields_to_json_tree(raw: &[u8], show_pan: bool) -> Result<serde_json::Value, DecodeError> {
+    let mut fields = decoders::raw_fields(raw).collect::<Result<Vec<_>, _>>()?;
+    fields.sort_by_key(|(tag, _)| *tag);
+    fields
+        .into_iter()
+        .map(|(tag, value)| to_json_tree(tag, value, show_pan))
+        .collect()
+}
+
+fn tag_from_json(key: &str) -> Result<u16, DecodeError> {
+    let digits = key.strip_prefix("0x").unwrap_or(key);
+    if digits.is_empty() || digits.len() > 4 {
+        return Err(DecodeError::InvalidTag(key.to_string()));
+    }
+    u16::from_str_radix(digits, 16).map_err(|_| DecodeError::InvalidTag(key.to_string()))
+}
+
+fn digits_from_json(tag: u16, typ: ElementType, s: &str) -> Result<Vec<u8>, DecodeError> {
+    s.chars()
+        .map(|ch| ch.to_digit(10).map(|digit| digit as u8))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or(DecodeError::WrongType(tag, typ.name()))
+}
+
+fn value_from_json(
+    tag: u16,
+    typ: ElementType,
+    json: &serde_json::Value,
+) -> Result<Value, DecodeError> {
+    use serde_json::Value as Json;
+
+    let wrong_type = DecodeError::WrongType(tag, typ.name());
+    match (typ, json) {
+        (ElementType::Alphabetic, Json::String(s)) => decoders::alphabetic(s.as_bytes()),
+        (ElementType::Alphanumeric, Json::String(s)) => decoders::alphanumeric(s.as_bytes()),
+        (ElementType::AlphanumericSpecial, Json::String(s)) => {
+            decoders::alphanumeric_special(s.as_bytes())
+        }
+        (ElementType::Binary, Json::String(s)) => {
+            hex::decode(s).map(Value::Binary).map_err(|_| wrong_type)
+        }
+        (ElementType::DigitString, Json::String(s)) => {
+            digits_from_json(tag, typ, s).map(Value::DigitString)
+        }
+        (ElementType::Numeric, Json::Number(n)) => n
+            .as_u64()
+            .map(|n| Value::Numeric(n as u128))
+            .ok_or(wrong_type),
+        (ElementType::Numeric, Json::String(s)) => {
+            let digits = digits_from_json(tag, typ, s)?;
+            digits
+                .iter()
+                .try_fold(0u128, |acc, &digit| {
+                    acc.checked_mul(10)?.checked_add(digit as u128)
+                })
+                .map(Value::Numeric)
+                .ok_or(wrong_type)
+        }
+        (ElementType::Template, Json::Object(_)) => fields_from_json(json).map(Value::Template),
+        (ElementType::Dol, Json::Array(entries)) => entries
+            .iter()
+            .map(|entry| {
+                let tag = entry
+                    .get("tag")
+                    .and_then(Json::as_str)
+                    .ok_or(DecodeError::WrongType(tag, "DOL entry"))
+                    .and_then(tag_from_json)?;
+                let size = entry
+                    .get("size")
+                    .and_then(Json::as_u64)
+                    .ok_or(DecodeError::WrongType(tag, "DOL entry"))?;
+                Ok(DOLEntry {
+                    tag,
+                    size: size as usize,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(|entries| Value::Dol(Dol::new_from_entries(entries))),
+        _ => Err(wrong_type),
+    }
+    .map_err(|err| match err {
+        DecodeError::WrongType(..) | DecodeError::TemplateInternal(..) => err,
+        err => DecodeError::TemplateInternal(tag, Box::new(err)),
+    })
+}
+
+/// Build a [`FieldMap`] from a JSON object in the format produced by [`FieldMapSerialize`],
+/// checking that each value matches the type of its tag in [`ELEMENTS`]. Unknown tags are
+/// treated as binary.
+pub fn fields_from_json(json: &serde_json::Value) -> Result<FieldMap, DecodeError> {
+    let object = json
+        .as_object()
+        .ok_or(DecodeError::WrongType(0, "Template"))?;
+
+    let mut fields = FieldMap::new();
+    for (key, value) in object {
+        let tag = tag_from_json(key)?;
+        let typ = ELEMENTS
+            .get(&tag)
+            .map(|&elem| elem.typ)
+            .unwrap_or(ElementType::Binary);
+        match value {
+            serde_json::Value::Array(values) if typ != ElementType::Dol => {
+                for value in values {
+                    fields.insert(tag, value_from_json(tag, typ, value)?);
+                }
+            }
+            value => fields.insert(tag, value_from_json(tag, typ, value)?),
+        }
+    }
+    Ok(fields)
+}
@@ -1,3 +1,5 @@
+use proptest::prelude::*;
+
 use super::*;
 
 #[test]
@@ -59,22 +61,13 @@ fn test_read_alphanumeric_special_unsupported_char() {
 
 #[test]
 fn test_parse_ddt() {
+    let mut expected = FieldMap::new();
+    expected.insert(0x5f55, Value::Alphabetic("US".to_string()));
+    expected.insert(0x42, Value::Numeric(440393));
     assert_eq!(
         // Hnadwritten example of what a Directory Discretionary Template could be
         super::read_field(&b"\x73\x0b\x5f\x55\x02US\x42\x04\x00\x44\x03\x93"[..]).unwrap(),
-        Field {
-            tag: 0x73,
-            value: Value::Template(vec![
-                Field {
-                    tag: 0x5f55,
-                    value: Value::Alphabetic("US".to_string()),
-                },
-                Field {
-                    tag: 0x42,
-                    value: Value::Numeric(440393),
-                }
-            ])
-        }
+        (0x73, Value::Template(expected))
     )
 }
 
@@ -109,3 +102,627 @@ fn test_read_tl_lorge() {
         (0x7f99, 0xffff_ffff, 7)
     )
 }
+
+#[test]
+fn test_parse_response() {
+    let fields = parse_response(&hex::decode("6f098407a0000000031010").unwrap()).unwrap();
+    assert_eq!(
+        fields.get(&0x84),
+        Some(&Value::Binary(vec![0xa0, 0, 0, 0, 0x03, 0x10, 0x10]))
+    );
+    assert_eq!(
+        parse_response(&hex::decode("5a084111111111111111").unwrap()),
+        Err(DecodeError::WrongType(0x5a, "Template"))
+    );
+}
+
+#[test]
+fn test_tlv_reader() {
+    let raw = hex::decode("5f2a0208405a0841111111111111119f10820003010203").unwrap();
+    let fields: Vec<_> = TlvReader::new(std::io::Cursor::new(&raw))
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(
+        fields,
+        [
+            (0x5f2a, Value::Numeric(840)),
+            (
+                0x5a,
+                Value::DigitString(vec![4, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1])
+            ),
+            (0x9f10, Value::Binary(vec![0x01, 0x02, 0x03])),
+        ]
+    );
+
+    let mut reader = TlvReader::new(std::io::Cursor::new(&raw[..9]));
+    assert_eq!(reader.next(), Some(Ok((0x5f2a, Value::Numeric(840)))));
+    assert_eq!(
+        reader.next(),
+        Some(Err(DecodeError::MessageTooShort(10, 4)))
+    );
+    assert_eq!(reader.next(), None);
+}
+
+#[test]
+fn test_template_padding() {
+    let record = hex::decode("70105f2a0208400000005a02123400ffffff").unwrap();
+    let (tag, value) = read_field(&record).unwrap();
+    assert_eq!(tag, 0x70);
+    let fields = value.into_template().unwrap();
+    assert_eq!(fields.len(), 2);
+    assert_eq!(fields.get(&0x5f2a), Some(&Value::Numeric(840)));
+    assert_eq!(
+        fields.get(&0x5a),
+        Some(&Value::DigitString(vec![1, 2, 3, 4]))
+    );
+
+    let raw = hex::decode("005a021234ff").unwrap();
+    assert_eq!(
+        decoders::raw_fields(&raw).collect::<Vec<_>>(),
+        [Ok((0x5a, &[0x12, 0x34][..]))]
+    );
+}
+
+#[test]
+fn test_read_field_truncated() {
+    assert_eq!(
+        decoders::read_field(&b"\x5a\x08\x41\x11"[..]),
+        Err(DecodeError::MessageTooShort(10, 4))
+    );
+    // A length that fits in the outer message but not in the template
+    let err = decoders::template(&b"\x70\x04\x5a\x08\x41\x11"[..]).unwrap_err();
+    assert_eq!(
+        err,
+        DecodeError::AtOffset(
+            0,
+            Box::new(DecodeError::TemplateInternal(
+                0x70,
+                Box::new(DecodeError::AtOffset(
+                    2,
+                    Box::new(DecodeError::MessageTooShort(10, 4))
+                ))
+            ))
+        )
+    );
+    assert_eq!(err.root_cause(), &DecodeError::MessageTooShort(10, 4));
+    // The needed length saturates where usize is 32 bits
+    assert_eq!(
+        decoders::read_field(&b"\x9f\x4b\x84\xff\xff\xff\xff\x00"[..]),
+        Err(DecodeError::MessageTooShort(
+            0xffff_ffff_usize.saturating_add(7),
+            8
+        ))
+    );
+}
+
+#[test]
+fn test_error_offset() {
+    // The second Cardholder Name has a control character
+    let err = decoders::template(&b"\x5f\x20\x02AB\x00\x00\x5f\x20\x02A\x01"[..]).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "At byte 7, tag 0x5f20: Unsupported character 0x01 in AlphanumericSpecial string"
+    );
+    // Offsets inside nested templates count from the start of the outer one
+    let err = decoders::template(
+        &b"\x5a\x01\x11\x70\x07\x5f\x20\x01A\x5f\x20\x00\x77\x05\x70\x03\x5f\x20\x01"[..],
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "At byte 12, tag 0x0077: At byte 14, tag 0x0070: At byte 16: Message too short, needed \
+         4, got 3"
+    );
+}
+
+#[test]
+fn test_tag_encoding_mismatch() {
+    for elem in elements::ELEMENTS.values() {
+        assert_eq!(
+            elem.typ == elements::ElementType::Template,
+            elements::is_constructed(elem.tag),
+            "0x{:04x} has the wrong type",
+            elem.tag
+        );
+    }
+
+    // Primitive tags whose constructed twin is known are just unknown tags, e.g. 0x9f0c is used
+    // by some issuers next to the 0xbf0c template
+    let fields =
+        decoders::template(&b"\x70\x0b\x9f\x0c\x04\x12\x34\x56\x78\x5a\x02\x41\x11"[..]).unwrap();
+    let record = fields.get(&0x70).unwrap().as_template().unwrap();
+    assert_eq!(
+        record.get(&0x9f0c),
+        Some(&Value::Binary(vec![0x12, 0x34, 0x56, 0x78]))
+    );
+    assert_eq!(
+        read_field(&b"\x41\x03\x50\x01A"[..]).unwrap(),
+        (0x41, Value::Binary(b"\x50\x01A".to_vec()))
+    );
+
+    // Cardholder Name with the constructed bit set
+    let err = read_field(&b"\x7f\x20\x01A"[..]).unwrap_err();
+    assert_eq!(err, DecodeError::TagEncodingMismatch(0x7f20));
+    assert_eq!(
+        err.to_string(),
+        "Tag 0x7f20 is constructed but not a template, and 0x5f20 is a known primitive tag"
+    );
+    // but may still hold TLVs
+    assert!(read_field(&b"\x7f\x20\x03\x50\x01A"[..]).is_ok());
+}
+
+#[test]
+fn test_read_tlv() {
+    let mut raw = &b"\x9f\x36\x02\x00\x2a\x5f\x20\x03ABC"[..];
+    let mut fields = Vec::new();
+    while !raw.is_empty() {
+        let (tag, len, value) = read_tlv(raw).unwrap();
+        fields.push((tag, value));
+        raw = &raw[len..];
+    }
+    assert_eq!(
+        fields,
+        [
+            (0x9f36, Value::Binary(vec![0x00, 0x2a])),
+            (0x5f20, Value::AlphanumericSpecial("ABC".to_string())),
+        ]
+    );
+}
+
+#[test]
+fn test_raw_fields() {
+    let raw = b"\x5a\x02\x41\x11\x70\x03\x9f\x36\x00\x5f\x20";
+    let mut fields = decoders::raw_fields(&raw[..]);
+    assert_eq!(fields.next(), Some(Ok((0x5a, &b"\x41\x11"[..]))));
+    assert_eq!(fields.next(), Some(Ok((0x70, &b"\x9f\x36\x00"[..]))));
+    assert_eq!(fields.next(), Some(Err(DecodeError::MessageTooShort(3, 2))));
+    assert_eq!(fields.next(), None);
+}
+
+#[test]
+fn test_read_numeric_too_long() {
+    assert_eq!(
+        decoders::numeric(&[0x99; 19]),
+        Ok(99_999_999_999_999_999_999_999_999_999_999_999_999)
+    );
+    assert_eq!(
+        decoders::numeric(&[0x99; 20]),
+        Err(DecodeError::LengthTooLong(19, 20))
+    );
+}
+
+#[test]
+fn test_get_dol() {
+    let (_, value) =
+        super::read_field(&b"\x70\x0a\x9f\x49\x03\x9f\x37\x04\x9f\x36\x01\x00"[..]).unwrap();
+    assert_eq!(
+        value.get_dol(0x9f49).unwrap().get_entries(),
+        &[dol::DOLEntry {
+            tag: 0x9f37,
+            size: 4
+        }]
+    );
+    assert_eq!(
+        value.get_dol(0x9f36),
+        Err(DecodeError::WrongType(0x9f36, "Dol"))
+    );
+    assert_eq!(value.get_dol(0x8c), Err(DecodeError::NoSuchMember(0x8c)));
+}
+
+#[test]
+fn test_tag_map() {
+    let (_, value) =
+        super::read_field(&b"\x70\x0a\x9f\x49\x03\x9f\x37\x04\x9f\x36\x01\x00"[..]).unwrap();
+    let fields = value.into_template().unwrap();
+    let state: OptionsMap = fields
+        .iter()
+        .map(|(&tag, value)| (tag, value.clone()))
+        .collect();
+    let outer = OptionsMap::from([(0x70, Value::Template(fields.clone()))]);
+
+    assert_eq!(state.get_dol(0x9f49), fields.get_dol(0x9f49));
+    assert_eq!(outer.get_path(&[0x70, 0x9f36]), fields.get_path(&[0x9f36]));
+    assert_eq!(
+        outer.get_path(&[0x70, 0x9f36, 0x9f37]),
+        Err(DecodeError::WrongType(0x9f36, "Template"))
+    );
+    assert_eq!(state.get_path(&[]), Err(DecodeError::NoPathRequested));
+
+    let dol = fields.get_dol(0x9f49).unwrap();
+    let fields = FieldMap::from_iter([(0x9f37, Value::Binary(vec![1, 2, 3, 4]))]);
+    assert_eq!(dol.encode_bare(&fields), [1, 2, 3, 4]);
+}
+
+#[test]
+fn test_serialize_json() {
+    let fields = decoders::template(
+        &b"\x73\x0b\x5f\x55\x02US\x42\x04\x00\x44\x03\x93\x9f\x49\x03\x9f\x37\x04"[..],
+    )
+    .unwrap();
+    assert_eq!(
+        serde_json::to_value(fields.serialize()).unwrap(),
+        serde_json::json!({
+            "73": {"42": 440393, "5f55": "US"},
+            "9f49": [{"tag": "9f37", "size": 4}],
+        })
+    )
+}
+
+#[test]
+fn test_json_tree() {
+    let raw = b"\x73\x0b\x5f\x55\x02US\x42\x04\x00\x44\x03\x93\xdf\x01\x01\xaa";
+    assert_eq!(
+        serialize::fields_to_json_tree(&raw[..], false).unwrap(),
+        serde_json::json!([
+            {
+                "tag": "0x73",
+                "name": "Directory Discretionary Template",
+                "type": "Template",
+                "children": [
+                    {
+                        "tag": "0x42",
+                        "name": "Issuer Identification Number (IIN)",
+                        "type": "Numeric",
+                        "value": 440393,
+                        "raw": "00440393",
+                    },
+                    {
+                        "tag": "0x5f55",
+                        "name": "Issuer Country Code (alpha2 format)",
+                        "type": "Alphabetic",
+                        "value": "US",
+                        "raw": "5553",
+                    },
+                ],
+            },
+            {
+                "tag": "0xdf01",
+                "name": null,
+                "type": "Binary",
+                "value": "aa",
+                "raw": "aa",
+            },
+        ])
+    );
+}
+
+#[test]
+fn test_encode_roundtrip() {
+    let raw = b"\x73\x0a\x42\x03\x44\x03\x93\x5f\x55\x02US\x9f\x49\x03\x9f\x37\x04";
+    let fields = decoders::template(&raw[..]).unwrap();
+    assert_eq!(fields.encode(), &raw[..]);
+
+    // Numeric fields keep their fixed length
+    let raw = b"\x77\x0e\x5f\x2a\x02\x08\x40\x9f\x02\x06\x00\x00\x00\x00\x12\x34";
+    let fields = decoders::template(&raw[..]).unwrap();
+    assert_eq!(fields.encode(), &raw[..]);
+}
+
+#[test]
+fn test_encode_packing() {
+    assert_eq!(encoders::numeric(840), &[0x08, 0x40]);
+    assert_eq!(encoders::numeric(0), &[0x00]);
+    assert_eq!(
+        encoders::numeric_field(0x9f02, 1234),
+        &[0x00, 0x00, 0x00, 0x00, 0x12, 0x34]
+    );
+    assert_eq!(encoders::numeric_field(0xdf01, 1234), &[0x12, 0x34]);
+    assert_eq!(encoders::compressed_numeric(&[4, 1, 2]), &[0x41, 0x2f]);
+
+    let mut long = Vec::new();
+    encoders::write_tl(0x90, 0x90, &mut long);
+    assert_eq!(long, &[0x90, 0x81, 0x90]);
+    assert_eq!(decoders::read_tl(&long).unwrap(), (0x90, 0x90, 3));
+}
+
+#[test]
+fn test_encode_amounts() {
+    // Amount, Authorised (Numeric) and (Binary), then Amount, Other (Numeric) and (Binary)
+    let dol = dol::Dol::try_from(&b"\x9f\x02\x06\x81\x04\x9f\x03\x06\x9f\x04\x04"[..]).unwrap();
+    let state = OptionsMap::from([
+        (0x9f02, Value::Numeric(1234)),
+        (0x81, Value::Binary(1234u32.to_be_bytes().to_vec())),
+        (0x9f03, Value::Numeric(0)),
+    ]);
+    assert_eq!(
+        dol.encode_bare(&state),
+        [
+            0x00, 0x00, 0x00, 0x00, 0x12, 0x34, // 9f02
+            0x00, 0x00, 0x04, 0xd2, // 81
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // 9f03
+            0x00, 0x00, 0x00, 0x00, // 9f04, missing
+        ]
+    );
+    assert_eq!(
+        dol.decode(&dol.encode_bare(&state)).unwrap()[&0x9f02],
+        Value::Numeric(1234)
+    );
+}
+
+#[test]
+fn test_fields_from_json() {
+    let fields = serialize::fields_from_json(&serde_json::json!({
+        "73": {"42": 440393, "5f55": "US"},
+        "0x9f49": [{"tag": "9f37", "size": 4}],
+    }))
+    .unwrap();
+    assert_eq!(
+        fields.encode(),
+        &b"\x73\x0a\x42\x03\x44\x03\x93\x5f\x55\x02US\x9f\x49\x03\x9f\x37\x04"[..]
+    );
+}
+
+#[test]
+fn test_fields_from_json_wrong_type() {
+    assert_eq!(
+        serialize::fields_from_json(&serde_json::json!({"5f2a": "USD"})),
+        Err(DecodeError::WrongType(0x5f2a, "Numeric"))
+    );
+    assert_eq!(
+        serialize::fields_from_json(&serde_json::json!({"9f02": "9".repeat(40)})),
+        Err(DecodeError::WrongType(0x9f02, "Numeric"))
+    );
+    assert_eq!(
+        serialize::fields_from_json(&serde_json::json!({"9f49": "9f3704"})),
+        Err(DecodeError::WrongType(0x9f49, "Dol"))
+    );
+    assert_eq!(
+        serialize::fields_from_json(&serde_json::json!({"5f55": "U5"})),
+        Err(DecodeError::TemplateInternal(
+            0x5f55,
+            Box::new(DecodeError::UnsupportedChar(
+                errors::StringType::Alphabetic,
+                b'5'
+            ))
+        ))
+    );
+    assert_eq!(
+        serialize::fields_from_json(&serde_json::json!({"zz": "00"})),
+        Err(DecodeError::InvalidTag("zz".to_string()))
+    );
+}
+
+#[test]
+fn test_tag_list() {
+    assert_eq!(
+        decoders::tag_list(&b"\x82\x9f\x37\x5a"[..]),
+        Ok(vec![0x82, 0x9f37, 0x5a])
+    );
+    assert_eq!(
+        decoders::tag_list(&b"\x82\x9f"[..]),
+        Err(DecodeError::MessageTooShort(2, 1))
+    );
+}
+
+#[test]
+fn test_unknown_tags() {
+    let raw = b"\xdf\x01\x00\x5a\x01\x12\xdf\x01\x00\x9f\x7c\x00";
+    decoders::template(&raw[..]).unwrap();
+    assert!(decoders::take_unknown_tags().is_empty());
+
+    decoders::track_unknown_tags();
+    decoders::template(&raw[..]).unwrap();
+    assert_eq!(decoders::take_unknown_tags(), [0x9f7c, 0xdf01]);
+    // Taking the tags stops tracking
+    decoders::template(&raw[..]).unwrap();
+    assert!(decoders::take_unknown_tags().is_empty());
+}
+
+#[test]
+fn test_display_painter() {
+    let fields = decoders::template(&b"\x70\x05\x5f\x55\x02US"[..]).unwrap();
+    assert!(!fields.display().to_string().contains('\x1b'));
+    // Nested templates are colored too
+    assert!(fields
+        .display()
+        .with_painter(crate::color::Painter::new(true))
+        .to_string()
+        .contains("\x1b[32ma\"US\"\x1b[0m"));
+}
+
+#[test]
+fn test_mask_pan() {
+    let raw = b"\x70\x15\x5a\x08\x49\x16\x55\x00\x00\x00\x00\x09\x9f\x6b\x08\x49\x16\x55\x00\x00\x00\x00\x09";
+    let fields = decoders::template(&raw[..]).unwrap();
+
+    let display = fields.display().to_string();
+    assert!(display.contains("=> cn491655******0009,"));
+    assert!(display.contains("=> 0x491655******0009,"));
+    assert!(!display.contains("4916550000000009"));
+    assert!(fields
+        .display()
+        .show_pan(true)
+        .to_string()
+        .contains("=> cn4916550000000009,"));
+
+    let json = serde_json::to_string(&fields.serialize()).unwrap();
+    assert_eq!(
+        json,
+        r#"{"70":{"5a":"491655******0009","9f6b":"491655******0009"}}"#
+    );
+    let json = serde_json::to_string(&fields.serialize().show_pan(true)).unwrap();
+    assert!(json.contains(r#""5a":"4916550000000009""#));
+
+    let tree = serialize::fields_to_json_tree(&raw[..], false).unwrap();
+    assert_eq!(tree[0]["children"][0]["value"], "491655******0009");
+    assert_eq!(tree[0]["children"][0]["raw"], "491655******0009");
+
+    assert!(format!("{:?}", fields.get(&0x70).unwrap()).contains(r#"Masked("491655******0009")"#));
+}
+
+#[test]
+fn test_display_issuer_url() {
+    let fields = decoders::template(&b"\x5f\x50\x13https://example.com"[..]).unwrap();
+    assert_eq!(
+        fields.get(&0x5f50),
+        Some(&Value::AlphanumericSpecial(
+            "https://example.com".to_string()
+        ))
+    );
+    assert!(fields
+        .display()
+        .to_string()
+        .contains("0x5f50 (\"Issuer URL\") => https://example.com,"));
+
+    // Cards don't always keep this printable
+    let fields = decoders::template(&b"\x5f\x50\x04\x61\x00\x62\x63"[..]).unwrap();
+    assert_eq!(
+        fields.get(&0x5f50),
+        Some(&Value::Binary(b"\x61\x00\x62\x63".to_vec()))
+    );
+}
+
+#[test]
+fn test_amount_types() {
+    for (tag, typ) in [
+        (0x81, elements::ElementType::Binary),
+        (0x9f04, elements::ElementType::Binary),
+        (0x9f02, elements::ElementType::Numeric),
+        (0x9f03, elements::ElementType::Numeric),
+    ] {
+        assert_eq!(elements::ELEMENTS[&tag].typ, typ, "0x{:04x}", tag);
+    }
+    let fields =
+        decoders::template(&b"\x81\x04\x00\x00\x04\xd2\x9f\x02\x06\x00\x00\x00\x00\x12\x34"[..])
+            .unwrap();
+    assert_eq!(
+        fields.get(&0x81),
+        Some(&Value::Binary(vec![0x00, 0x00, 0x04, 0xd2]))
+    );
+    assert_eq!(fields.get(&0x9f02), Some(&Value::Numeric(1234)));
+}
+
+#[test]
+fn test_merchant_name() {
+    let (_, value) = read_field(&b"\x9f\x4e\x0dCORNER SHOP 1"[..]).unwrap();
+    assert_eq!(
+        value,
+        Value::AlphanumericSpecial("CORNER SHOP 1".to_string())
+    );
+    // Not printable, so shown as hex instead of failing
+    let (_, value) = read_field(&b"\x9f\x4e\x03AB\x00"[..]).unwrap();
+    assert_eq!(value, Value::Binary(vec![0x41, 0x42, 0x00]));
+}
+
+#[test]
+fn test_display_iban_bic() {
+    let fields =
+        decoders::template(&b"\x5f\x53\x16GB83WEST12345698765432\x5f\x54\x08DEUTDEFF"[..]).unwrap();
+    assert!(fields.display().to_string().contains("DEUTDEFF"));
+
+    // Cards don't always keep this alphanumeric
+    let fields = decoders::template(&b"\x5f\x54\x02\x00\xff"[..]).unwrap();
+    assert_eq!(fields.get(&0x5f54), Some(&Value::Binary(vec![0x00, 0xff])));
+}
+
+/// Known tags followed by a length and value that may or may not agree, so the fuzzer reaches
+/// the typed decoders and nested templates instead of stopping at unknown tags
+fn tlv_like() -> impl Strategy<Value = Vec<u8>> {
+    let tags: Vec<u16> = elements::ELEMENTS.keys().copied().collect();
+    proptest::collection::vec(
+        (
+            proptest::sample::select(tags),
+            any::<u8>(),
+            proptest::collection::vec(any::<u8>(), 0..48),
+        ),
+        0..8,
+    )
+    .prop_map(|fields| {
+        let mut raw = Vec::new();
+        for (tag, len, value) in fields {
+            if tag > 0xff {
+                raw.push((tag >> 8) as u8);
+            }
+            raw.push(tag as u8);
+            raw.push(len);
+            raw.extend(value);
+        }
+        raw
+    })
+}
+
+proptest! {
+    #[test]
+    fn fuzz_decoders(raw in proptest::collection::vec(any::<u8>(), 0..256)) {
+        // Only checking that nothing panics, errors are fine
+        let _ = decoders::read_tl(&raw);
+        let _ = decoders::read_field(&raw);
+        let _ = decoders::template(&raw);
+        let _ = decoders::tag_list(&raw);
+        let _ = decoders::dol(&raw);
+        let _ = decoders::numeric(&raw);
+        let _ = decoders::compressed_numeric(&raw);
+    }
+
+    #[test]
+    fn fuzz_template(raw in tlv_like()) {
+        let _ = decoders::read_field(&raw);
+        let _ = decoders::template(&raw);
+    }
+}
+
+/// A DOL entry size and a value for it. Values may be shorter or longer than the entry to
+/// exercise the padding and truncation in [`dol::Dol::encode`].
+fn dol_value(typ: elements::ElementType) -> BoxedStrategy<(usize, Value)> {
+    use elements::ElementType;
+
+    match typ {
+        ElementType::Alphabetic => (0..16usize, "[A-Za-z]{0,20}")
+            .prop_map(|(size, s)| (size, Value::Alphabetic(s)))
+            .boxed(),
+        ElementType::Alphanumeric => (0..16usize, "[A-Za-z0-9]{0,20}")
+            .prop_map(|(size, s)| (size, Value::Alphanumeric(s)))
+            .boxed(),
+        ElementType::AlphanumericSpecial => (0..16usize, "[ -~]{0,20}")
+            .prop_map(|(size, s)| (size, Value::AlphanumericSpecial(s)))
+            .boxed(),
+        // compressed_numeric only accepts 10 bytes
+        ElementType::DigitString => (0..=10usize, proptest::collection::vec(0..=9u8, 0..24))
+            .prop_map(|(size, digits)| (size, Value::DigitString(digits)))
+            .boxed(),
+        ElementType::Numeric => (0..=12usize, any::<u64>())
+            .prop_map(|(size, number)| (size, Value::Numeric(number.into())))
+            .boxed(),
+        _ => (0..16usize, proptest::collection::vec(any::<u8>(), 0..20))
+            .prop_map(|(size, b)| (size, Value::Binary(b)))
+            .boxed(),
+    }
+}
+
+proptest! {
+    #[test]
+    fn dol_roundtrip(
+        fields in proptest::sample::subsequence(
+            elements::ELEMENTS
+                .values()
+                .filter(|elem| !matches!(
+                    elem.typ,
+                    elements::ElementType::Template | elements::ElementType::Dol
+                ))
+                .map(|elem| (elem.tag, elem.typ))
+                .collect::<Vec<_>>(),
+            0..8,
+        )
+        .prop_flat_map(|tags| {
+            tags.into_iter()
+                .map(|(tag, typ)| dol_value(typ).prop_map(move |value| (tag, value)))
+                .collect::<Vec<_>>()
+        })
+    ) {
+        let dol = dol::Dol::new_from_entries(
+            fields
+                .iter()
+                .map(|&(tag, (size, _))| dol::DOLEntry { tag, size })
+                .collect(),
+        );
+        let values: OptionsMap = fields
+            .into_iter()
+            .map(|(tag, (_, value))| (tag, value))
+            .collect();
+
+        let encoded = dol.encode(None, &values);
+        prop_assert_eq!(encoded.len(), dol.get_size());
+        let decoded = dol.decode(&encoded).unwrap();
+        prop_assert_eq!(dol.encode(None, &decoded), encoded);
+    }
+}
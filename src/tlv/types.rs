@@ -6,10 +6,15 @@ use std::{
 
 use multimap::MultiMap;
 
-use super::{dol::Dol, errors::DecodeError};
+use crate::{
+    color::{Painter, Style},
+    pan::masked_value,
+};
+
+use super::{dol::Dol, errors::DecodeError, serialize::FieldMapSerialize};
 
 /// A TLV value, see EMV 4.3 Book 3 section 4.3
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(PartialEq, Eq, Clone)]
 pub enum Value {
     Alphabetic(String),
     Alphanumeric(String),
@@ -24,17 +29,93 @@ pub enum Value {
 pub type FieldMap = MultiMap<u16, Value>;
 pub type OptionsMap = HashMap<u16, Value>;
 
-pub trait FieldMapExt {
-    fn get_path(&self, path: &[u16]) -> Result<&Value, DecodeError>;
+/// Lookup by tag, implemented for both [`FieldMap`] and [`OptionsMap`] so that paths, DOLs, and
+/// [`Dol::encode`] work the same on data from the card and on terminal state
+pub trait TagMap {
+    /// The value for `tag`, or the first one if the tag appears more than once
+    fn get_tag(&self, tag: u16) -> Option<&Value>;
+
+    /// Follow `path` through nested templates and return the value of the last tag
+    fn get_path(&self, path: &[u16]) -> Result<&Value, DecodeError> {
+        let Some((&tag, rest)) = path.split_first() else {
+            return Err(DecodeError::NoPathRequested);
+        };
+        let value = self.get_tag(tag).ok_or(DecodeError::NoSuchMember(tag))?;
+        if rest.is_empty() {
+            return Ok(value);
+        }
+        value
+            .as_template()
+            .ok_or(DecodeError::WrongType(tag, "Template"))?
+            .get_path(rest)
+    }
+
+    fn get_dol(&self, tag: u16) -> Result<&Dol, DecodeError> {
+        self.get_path(&[tag])?
+            .as_dol()
+            .ok_or(DecodeError::WrongType(tag, "Dol"))
+    }
+}
+
+impl TagMap for FieldMap {
+    fn get_tag(&self, tag: u16) -> Option<&Value> {
+        self.get(&tag)
+    }
+}
+
+impl TagMap for OptionsMap {
+    fn get_tag(&self, tag: u16) -> Option<&Value> {
+        self.get(&tag)
+    }
+}
+
+pub trait FieldMapExt: TagMap {
     fn into_path(self, path: &[u16]) -> Result<Value, DecodeError>;
-    fn display(&self) -> FieldMapDisplay;
+    fn display(&self) -> FieldMapDisplay<'_>;
+    fn serialize(&self) -> FieldMapSerialize<'_>;
+    fn encode(&self) -> Vec<u8>;
+}
+
+/// Human readable meaning of a value, such as the currency for an ISO 4217 code, shown after it by
+/// [`FieldMapDisplay`]. `fields` holds the siblings of the value for meanings that depend on other
+/// tags.
+pub type NoteFn = fn(tag: u16, value: &Value, fields: &FieldMap) -> Option<String>;
+
+/// [`NoteFn`]s by tag. The TLV code doesn't know what any values mean, see [`crate::notes::NOTES`]
+/// for the ones this crate does.
+pub type NoteRegistry = HashMap<u16, NoteFn>;
+
+/// Displays a [`FieldMap`] with the names of the fields, see [`FieldMapExt::display`]
+pub struct FieldMapDisplay<'a> {
+    fields: &'a FieldMap,
+    painter: Painter,
+    show_pan: bool,
+    notes: Option<&'a NoteRegistry>,
 }
 
-pub struct FieldMapDisplay<'a>(&'a FieldMap);
+impl<'a> FieldMapDisplay<'a> {
+    /// Follow values with their meaning from `notes`
+    pub fn with_notes(self, notes: &'a NoteRegistry) -> Self {
+        Self {
+            notes: Some(notes),
+            ..self
+        }
+    }
+
+    /// Color the output with `painter`, plain text by default
+    pub fn with_painter(self, painter: Painter) -> Self {
+        Self { painter, ..self }
+    }
+
+    /// Show full PANs instead of masking them, see [`crate::pan::PAN_TAGS`]
+    pub fn show_pan(self, show_pan: bool) -> Self {
+        Self { show_pan, ..self }
+    }
+}
 
 impl Display for FieldMapDisplay<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.0.is_empty() {
+        if self.fields.is_empty() {
             write!(f, "{{}}")
         } else {
             let mut adapter = PadAdapter {
@@ -42,14 +123,64 @@ impl Display for FieldMapDisplay<'_> {
                 on_newline: false,
             };
             writeln!(adapter, "{{")?;
-            for (tag, value) in self.0.flat_iter() {
+            for (tag, value) in self.fields.flat_iter() {
                 let tag_name = super::elements::ELEMENTS.get(tag).map(|elem| elem.name);
                 let tag_name = if let Some(tag_name) = tag_name {
                     format!("\"{}\"", tag_name)
                 } else {
                     "<unknown tag>".to_string()
                 };
-                writeln!(adapter, "0x{:04x} ({}) => {},", tag, tag_name, value)?;
+                let tag_name = self.painter.paint(Style::Tag, tag_name);
+                let masked = match (self.show_pan, value) {
+                    (true, _) => None,
+                    (false, Value::DigitString(_)) => {
+                        masked_value(*tag, value).map(|pan| format!("cn{}", pan))
+                    }
+                    (false, _) => masked_value(*tag, value).map(|track| format!("0x{}", track)),
+                };
+                match (tag, value, masked) {
+                    (_, _, Some(masked)) => write!(
+                        adapter,
+                        "0x{:04x} ({}) => {}",
+                        tag,
+                        tag_name,
+                        self.painter.paint(Style::Value, masked)
+                    )?,
+                    // Unquoted so terminals can pick out the link
+                    (0x5f50, Value::AlphanumericSpecial(url), _) => write!(
+                        adapter,
+                        "0x{:04x} ({}) => {}",
+                        tag,
+                        tag_name,
+                        self.painter.paint(Style::Value, url)
+                    )?,
+                    // Nested output spans several lines and colors its own fields
+                    (_, Value::Template(fields), _) => write!(
+                        adapter,
+                        "0x{:04x} ({}) => {}",
+                        tag,
+                        tag_name,
+                        FieldMapDisplay { fields, ..*self }
+                    )?,
+                    (_, Value::Dol(_), _) => {
+                        write!(adapter, "0x{:04x} ({}) => {}", tag, tag_name, value)?
+                    }
+                    _ => write!(
+                        adapter,
+                        "0x{:04x} ({}) => {}",
+                        tag,
+                        tag_name,
+                        self.painter.paint(Style::Value, value)
+                    )?,
+                }
+                let note = self
+                    .notes
+                    .and_then(|notes| notes.get(tag))
+                    .and_then(|note| note(*tag, value, self.fields));
+                if let Some(note) = note {
+                    write!(adapter, " ({})", self.painter.paint(Style::Note, note))?;
+                }
+                writeln!(adapter, ",")?;
             }
             write!(f, "}}")
         }
@@ -57,29 +188,6 @@ impl Display for FieldMapDisplay<'_> {
 }
 
 impl FieldMapExt for FieldMap {
-    fn get_path(&self, path: &[u16]) -> Result<&Value, DecodeError> {
-        let mut curr_map = self;
-
-        if path.is_empty() {
-            return Err(DecodeError::NoPathRequested);
-        }
-        for tag in &path[..path.len() - 1] {
-            let Some(field) = curr_map.get(tag) else {
-                return Err(DecodeError::NoSuchMember(*tag));
-            };
-
-            let Some(next_map) = field.as_template() else {
-                return Err(DecodeError::WrongType(*tag, "Template"));
-            };
-
-            curr_map = next_map;
-        }
-
-        curr_map
-            .get(&path[path.len() - 1])
-            .ok_or(DecodeError::NoSuchMember(path[path.len() - 1]))
-    }
-
     fn into_path(self, path: &[u16]) -> Result<Value, DecodeError> {
         let mut curr_map = self;
 
@@ -105,8 +213,56 @@ impl FieldMapExt for FieldMap {
             .ok_or(DecodeError::NoSuchMember(path[path.len() - 1]))
     }
 
-    fn display(&self) -> FieldMapDisplay {
-        FieldMapDisplay(self)
+    fn display(&self) -> FieldMapDisplay<'_> {
+        FieldMapDisplay {
+            fields: self,
+            painter: Painter::default(),
+            show_pan: false,
+            notes: None,
+        }
+    }
+
+    fn serialize(&self) -> FieldMapSerialize<'_> {
+        FieldMapSerialize::new(self)
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        super::encoders::template(self)
+    }
+}
+
+/// Like the derived implementation, except PANs in templates are masked
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Alphabetic(s) => f.debug_tuple("Alphabetic").field(s).finish(),
+            Value::Alphanumeric(s) => f.debug_tuple("Alphanumeric").field(s).finish(),
+            Value::AlphanumericSpecial(s) => f.debug_tuple("AlphanumericSpecial").field(s).finish(),
+            Value::Binary(data) => f.debug_tuple("Binary").field(data).finish(),
+            Value::DigitString(digits) => f.debug_tuple("DigitString").field(digits).finish(),
+            Value::Numeric(n) => f.debug_tuple("Numeric").field(n).finish(),
+            Value::Template(fields) => f
+                .debug_tuple("Template")
+                .field(&DebugFields(fields))
+                .finish(),
+            Value::Dol(dol) => f.debug_tuple("Dol").field(dol).finish(),
+        }
+    }
+}
+
+/// Debug output for a [`FieldMap`] with the PANs masked
+struct DebugFields<'a>(&'a FieldMap);
+
+impl fmt::Debug for DebugFields<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut map = f.debug_map();
+        for (tag, value) in self.0.flat_iter() {
+            match masked_value(*tag, value) {
+                Some(masked) => map.entry(tag, &format_args!("Masked({:?})", masked)),
+                None => map.entry(tag, value),
+            };
+        }
+        map.finish()
     }
 }
 
@@ -160,7 +316,7 @@ impl Display for Value {
                 Ok(())
             }
             Value::Numeric(n) => write!(f, "n{}", n),
-            Value::Template(fields) => FieldMapDisplay(fields).fmt(f),
+            Value::Template(fields) => fields.display().fmt(f),
             Value::Dol(dol) => {
                 if dol.get_entries().is_empty() {
                     write!(f, "{{}}")
@@ -305,6 +461,12 @@ impl Value {
             .ok_or(DecodeError::WrongType(path[path.len() - 1], "Binary"))
     }
 
+    pub fn get_dol(&self, tag: u16) -> Result<&Dol, DecodeError> {
+        self.get_path(&[tag])?
+            .as_dol()
+            .ok_or(DecodeError::WrongType(tag, "Dol"))
+    }
+
     pub fn get_path_owned(self, path: &[u16]) -> Result<Value, DecodeError> {
         self.into_template()
             .ok_or(DecodeError::WrongType(0, "Template"))
@@ -1,26 +1,208 @@
-use std::collections::HashMap;
+use std::fmt::Display;
 
 use anyhow::Context;
 use log::debug;
 
 use crate::{
-    exchange::{exchange, ADPUCommand},
-    tlv::{self, FieldMap, Value},
+    aip::OfflineAuthentication,
+    exchange::{exchange, ADPUCommand, AcType, CardTransport, ExchangeError},
+    pan::{display_pan, DisplayPan},
+    tlv::{self, FieldMap, OptionsMap, TagMap, Value},
+    tvr::Decision,
 };
 
-pub fn do_transaction(
-    card: &mut pcsc::Card,
+/// Response to GENERATE AC, see EMV 4.4 Book 3 section 6.5.5.4
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApplicationCryptogram {
+    /// Cryptogram Information Data (0x9f27)
+    pub cid: u8,
+    /// Application Transaction Counter (0x9f36)
+    pub atc: u16,
+    /// Application Cryptogram (0x9f26). With CDA this is inside the signed dynamic data instead.
+    pub cryptogram: Option<[u8; 8]>,
+    /// Signed Dynamic Application Data (0x9f4b), only sent with CDA
+    pub signed_dynamic_data: Option<Vec<u8>>,
+    /// Issuer Application Data (0x9f10), if the card sent any
+    pub issuer_application_data: Option<Vec<u8>>,
+    /// Contents of the response template, needed to verify CDA
+    pub response_data: Vec<u8>,
+}
+
+impl ApplicationCryptogram {
+    pub fn cryptogram_information(&self) -> CryptogramInformation {
+        decode_cid(self.cid)
+    }
+}
+
+/// Reason or advice code in the low bits of the Cryptogram Information Data
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CidReason {
+    NoInformation,
+    ServiceNotAllowed,
+    PinTryLimitExceeded,
+    IssuerAuthenticationFailed,
+    Rfu(u8),
+}
+
+impl Display for CidReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CidReason::NoInformation => write!(f, "no information given"),
+            CidReason::ServiceNotAllowed => write!(f, "service not allowed"),
+            CidReason::PinTryLimitExceeded => write!(f, "PIN Try Limit exceeded"),
+            CidReason::IssuerAuthenticationFailed => write!(f, "issuer authentication failed"),
+            CidReason::Rfu(code) => write!(f, "RFU reason {}", code),
+        }
+    }
+}
+
+/// Cryptogram Information Data (0x9f27), see EMV 4.4 Book 3 section 6.5.5.4
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CryptogramInformation {
+    /// Type of cryptogram the card returned, `None` for the RFU value
+    pub ac_type: Option<AcType>,
+    /// Payment system-specific cryptogram bits
+    pub payment_system: u8,
+    pub advice_required: bool,
+    pub reason: CidReason,
+}
+
+pub fn decode_cid(byte: u8) -> CryptogramInformation {
+    CryptogramInformation {
+        ac_type: match byte >> 6 {
+            0b00 => Some(AcType::Aac),
+            0b01 => Some(AcType::Tc),
+            0b10 => Some(AcType::Arqc),
+            _ => None,
+        },
+        payment_system: (byte >> 4) & 0x03,
+        advice_required: byte & 0x08 != 0,
+        reason: match byte & 0x07 {
+            0 => CidReason::NoInformation,
+            1 => CidReason::ServiceNotAllowed,
+            2 => CidReason::PinTryLimitExceeded,
+            3 => CidReason::IssuerAuthenticationFailed,
+            code => CidReason::Rfu(code),
+        },
+    }
+}
+
+impl Display for CryptogramInformation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.ac_type {
+            Some(AcType::Aac) => write!(f, "AAC (declined)")?,
+            Some(AcType::Tc) => write!(f, "TC (approved offline)")?,
+            Some(AcType::Arqc) => write!(f, "ARQC (go online)")?,
+            None => write!(f, "RFU cryptogram type")?,
+        }
+        if self.payment_system != 0 {
+            write!(f, ", payment system-specific {}", self.payment_system)?;
+        }
+        if self.advice_required {
+            write!(f, ", advice required")?;
+        }
+        if self.reason != CidReason::NoInformation {
+            write!(f, ", {}", self.reason)?;
+        }
+        Ok(())
+    }
+}
+
+/// Report of what happened during a test transaction
+#[derive(Clone, PartialEq, Eq)]
+pub struct TransactionSummary {
+    /// Application PAN (0x5a) as one digit per byte, masked when displayed
+    pub pan: Option<Vec<u8>>,
+    pub authentication: OfflineAuthentication,
+    /// Whether offline data authentication passed, `None` if it wasn't checked
+    pub authenticated: Option<bool>,
+    /// Outcome of terminal action analysis, `None` if it wasn't done
+    pub decision: Option<Decision>,
+    pub cryptogram: CryptogramInformation,
+    pub atc: u16,
+}
+
+impl TransactionSummary {
+    /// Summarize a transaction from the card's records and its GENERATE AC response
+    pub fn new(
+        fields: &FieldMap,
+        authentication: OfflineAuthentication,
+        authenticated: Option<bool>,
+        decision: Option<Decision>,
+        cryptogram: &ApplicationCryptogram,
+    ) -> TransactionSummary {
+        TransactionSummary {
+            pan: fields
+                .get(&0x5a)
+                .and_then(Value::as_digit_string)
+                .map(<[u8]>::to_vec),
+            authentication,
+            authenticated,
+            decision,
+            cryptogram: cryptogram.cryptogram_information(),
+            atc: cryptogram.atc,
+        }
+    }
+}
+
+/// Like the derived implementation, except the PAN is masked
+impl std::fmt::Debug for TransactionSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TransactionSummary")
+            .field(
+                "pan",
+                &self.pan.as_deref().map(|pan| display_pan(pan, false)),
+            )
+            .field("authentication", &self.authentication)
+            .field("authenticated", &self.authenticated)
+            .field("decision", &self.decision)
+            .field("cryptogram", &self.cryptogram)
+            .field("atc", &self.atc)
+            .finish()
+    }
+}
+
+impl Display for TransactionSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_pan(f, false)
+    }
+}
+
+impl DisplayPan for TransactionSummary {
+    fn fmt_pan(&self, f: &mut std::fmt::Formatter<'_>, show_pan: bool) -> std::fmt::Result {
+        writeln!(f, "Transaction summary")?;
+        if let Some(pan) = &self.pan {
+            writeln!(f, "    PAN: {}", display_pan(pan, show_pan))?;
+        }
+        write!(f, "    Authentication: {}", self.authentication)?;
+        match (self.authentication, self.authenticated) {
+            (OfflineAuthentication::None, _) => writeln!(f)?,
+            (_, Some(true)) => writeln!(f, ", verified")?,
+            (_, Some(false)) => writeln!(f, ", failed")?,
+            (_, None) => writeln!(f, ", not checked")?,
+        }
+        if let Some(decision) = self.decision {
+            writeln!(f, "    Terminal decision: {}", decision)?;
+        }
+        writeln!(f, "    Cryptogram: {}", self.cryptogram)?;
+        write!(f, "    ATC: {}", self.atc)
+    }
+}
+
+/// Run INTERNAL AUTHENTICATE with the data requested by the DDOL and return the Signed Dynamic
+/// Application Data
+pub fn do_transaction<C: CardTransport + ?Sized>(
+    card: &mut C,
     options: &FieldMap,
-    state: &mut HashMap<u16, Value>,
-) -> anyhow::Result<()> {
-    let ddol = options
-        .get(&0x9f49)
-        .and_then(Value::as_dol)
-        .ok_or_else(|| anyhow::anyhow!("Could not get ddol"))?;
+    state: &mut OptionsMap,
+) -> anyhow::Result<Vec<u8>> {
+    let ddol = options.get_dol(0x9f49).context("Could not get ddol")?;
     let (authenticate_resp_bytes, sw) = exchange(
         card,
-        &ADPUCommand::internal_authenticate(&ddol.encode(None, state)),
+        &ADPUCommand::internal_authenticate(&ddol.encode_bare(state)),
     )?;
+    ExchangeError::check_sw(sw)
+        .context("Failure returned by card while running INTERNAL AUTHENTICATE")?;
     let (tag, value) = tlv::read_field(&authenticate_resp_bytes)
         .context("Failed to parse internal authenticate")?;
 
@@ -33,5 +215,195 @@ pub fn do_transaction(
 
     debug!("{}, {:04x}", hex::encode(sdad), sw);
 
-    Ok(())
+    Ok(sdad.to_vec())
+}
+
+/// Read a single data object with GET DATA, e.g. the ATC (0x9f36) or PIN Try Counter (0x9f17)
+pub fn get_data<C: CardTransport + ?Sized>(card: &mut C, tag: u16) -> anyhow::Result<Value> {
+    let (response, sw) = exchange(card, &ADPUCommand::get_data(tag))?;
+    ExchangeError::check_sw(sw).with_context(|| {
+        format!(
+            "Failure returned by card while running GET DATA for {:04x}",
+            tag
+        )
+    })?;
+
+    let (response_tag, value) = tlv::read_field(&response)
+        .with_context(|| format!("Failed to parse GET DATA response for {:04x}", tag))?;
+    if response_tag != tag {
+        anyhow::bail!(
+            "Requested {:04x} with GET DATA but got {:04x}",
+            tag,
+            response_tag
+        );
+    }
+    Ok(value)
+}
+
+/// Read a binary counter with GET DATA, such as the ATC (0x9f36), Last Online ATC Register
+/// (0x9f13), or PIN Try Counter (0x9f17)
+pub fn get_counter<C: CardTransport + ?Sized>(card: &mut C, tag: u16) -> anyhow::Result<u64> {
+    let value = get_data(card, tag)?;
+    let bytes = value
+        .as_binary()
+        .ok_or(tlv::DecodeError::WrongType(tag, "Binary"))?;
+    if bytes.len() > 8 {
+        anyhow::bail!("Counter {:04x} is {} bytes long", tag, bytes.len());
+    }
+    Ok(bytes.iter().fold(0, |n, &b| n << 8 | b as u64))
+}
+
+pub fn generate_ac<C: CardTransport + ?Sized>(
+    card: &mut C,
+    options: &FieldMap,
+    state: &OptionsMap,
+    ac_type: AcType,
+    cda: bool,
+) -> anyhow::Result<ApplicationCryptogram> {
+    let cdol1 = options.get_dol(0x8c).context("Could not get CDOL1")?;
+    let cdol_data = cdol1.encode_bare(state);
+    let (response, sw) = exchange(card, &ADPUCommand::generate_ac(ac_type, cda, &cdol_data))?;
+    ExchangeError::check_sw(sw).with_context(|| {
+        format!(
+            "Failure returned by card while running GENERATE AC with {}",
+            hex::encode(cdol_data)
+        )
+    })?;
+
+    let (tag, value) = tlv::read_field(&response).context("Failed to parse GENERATE AC")?;
+    debug!("{:04x} => {}", tag, value);
+    let (_, response_data, _) = tlv::decoders::read_value(&response)?;
+    let response_data = response_data.to_vec();
+
+    match tag {
+        0x77 => {
+            let cid = value.get_path_binary(&[0x9f27])?;
+            let atc = value.get_path_binary(&[0x9f36])?;
+            let cryptogram = value.get_path_binary(&[0x9f26]).ok();
+            let signed_dynamic_data = value.get_path_binary(&[0x9f4b]).ok();
+            if cid.len() != 1 || atc.len() != 2 || cryptogram.is_some_and(|ac| ac.len() != 8) {
+                anyhow::bail!("GENERATE AC response had fields of the wrong length");
+            }
+            if cryptogram.is_none() && signed_dynamic_data.is_none() {
+                anyhow::bail!("GENERATE AC response had no cryptogram or signed dynamic data");
+            }
+            Ok(ApplicationCryptogram {
+                cid: cid[0],
+                atc: u16::from_be_bytes(atc.try_into().unwrap()),
+                cryptogram: cryptogram.map(|ac| ac.try_into().unwrap()),
+                signed_dynamic_data: signed_dynamic_data.map(<[u8]>::to_vec),
+                issuer_application_data: value.get_path_binary(&[0x9f10]).ok().map(<[u8]>::to_vec),
+                response_data,
+            })
+        }
+        0x80 => {
+            // CID, ATC, and AC, optionally followed by the IAD. (Book 3 section 6.5.5.4)
+            let resp = value
+                .as_binary()
+                .ok_or(tlv::DecodeError::WrongType(0x80, "Binary"))?;
+            if resp.len() < 11 {
+                anyhow::bail!("GENERATE AC response too short: {}", hex::encode(resp));
+            }
+            Ok(ApplicationCryptogram {
+                cid: resp[0],
+                atc: u16::from_be_bytes(resp[1..3].try_into().unwrap()),
+                cryptogram: Some(resp[3..11].try_into().unwrap()),
+                signed_dynamic_data: None,
+                issuer_application_data: (resp.len() > 11).then(|| resp[11..].to_vec()),
+                response_data,
+            })
+        }
+        tag => anyhow::bail!("Got tag {:04x} in response to GENERATE AC", tag),
+    }
+}
+
+/// Build the data hashed into the Transaction Data Hash Code for CDA: the PDOL data, the CDOL data,
+/// then every field of the GENERATE AC response except the Signed Dynamic Application Data (0x9f4b)
+/// in the order the card sent them. (Book 2 section 6.6.2 step 10)
+pub fn cda_transaction_data(
+    pdol_data: &[u8],
+    cdol_data: &[u8],
+    response_data: &[u8],
+) -> Result<Vec<u8>, tlv::DecodeError> {
+    let mut data = [pdol_data, cdol_data].concat();
+    let mut rest = response_data;
+    while !rest.is_empty() {
+        let (tag, _, field_len) = tlv::decoders::read_value(rest)?;
+        if tag != 0x9f4b {
+            data.extend_from_slice(&rest[..field_len]);
+        }
+        rest = &rest[field_len..];
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::{ReplayTransport, TranscriptEntry};
+
+    #[test]
+    fn test_get_counter() {
+        let mut card = ReplayTransport::new(vec![
+            TranscriptEntry::new(
+                &ADPUCommand::get_data(0x9f36),
+                b"\x9f\x36\x02\x01\x2c\x90\x00",
+            ),
+            TranscriptEntry::new(&ADPUCommand::get_data(0x9f17), b"\x9f\x17\x01\x03\x90\x00"),
+            TranscriptEntry::new(&ADPUCommand::get_data(0x9f13), b"\x6a\x88"),
+        ]);
+        assert_eq!(get_counter(&mut card, 0x9f36).unwrap(), 300);
+        assert_eq!(get_counter(&mut card, 0x9f17).unwrap(), 3);
+        assert!(get_counter(&mut card, 0x9f13).is_err());
+    }
+
+    #[test]
+    fn test_decode_cid() {
+        assert_eq!(
+            decode_cid(0x80),
+            CryptogramInformation {
+                ac_type: Some(AcType::Arqc),
+                payment_system: 0,
+                advice_required: false,
+                reason: CidReason::NoInformation,
+            }
+        );
+        assert_eq!(decode_cid(0x40).to_string(), "TC (approved offline)");
+        assert_eq!(
+            decode_cid(0x0a).to_string(),
+            "AAC (declined), advice required, PIN Try Limit exceeded"
+        );
+        assert_eq!(decode_cid(0xd5).ac_type, None);
+        assert_eq!(decode_cid(0xd5).payment_system, 1);
+        assert_eq!(decode_cid(0xd5).reason, CidReason::Rfu(5));
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut fields = FieldMap::new();
+        fields.insert(
+            0x5a,
+            Value::DigitString(vec![4, 7, 6, 1, 7, 3, 9, 0, 0, 1, 0, 1, 0, 1, 1, 9]),
+        );
+        let cryptogram = ApplicationCryptogram {
+            cid: 0x80,
+            atc: 42,
+            cryptogram: Some([0; 8]),
+            signed_dynamic_data: None,
+            issuer_application_data: None,
+            response_data: Vec::new(),
+        };
+        let summary = TransactionSummary::new(
+            &fields,
+            OfflineAuthentication::Dda,
+            Some(true),
+            Some(Decision::Online),
+            &cryptogram,
+        );
+        assert_eq!(
+            summary.to_string(),
+            "Transaction summary\n    PAN: 476173******0119\n    Authentication: DDA, verified\n    \
+             Terminal decision: go online\n    Cryptogram: ARQC (go online)\n    ATC: 42"
+        );
+    }
 }
@@ -0,0 +1,262 @@
+use std::fmt::Display;
+
+use crate::{
+    exchange::AcType,
+    tlv::{FieldMap, Value},
+    util::bit_names,
+};
+
+/// Meaning of each bit of the Terminal Verification Results (0x95) as (byte, mask, name), see EMV
+/// 4.4 Book 3 Annex C5. Issuer and Terminal Action Codes use the same layout.
+const TVR_FLAGS: &[(usize, u8, &str)] = &[
+    (0, 0x80, "Offline data authentication was not performed"),
+    (0, 0x40, "SDA failed"),
+    (0, 0x20, "ICC data missing"),
+    (0, 0x10, "Card appears on terminal exception file"),
+    (0, 0x08, "DDA failed"),
+    (0, 0x04, "CDA failed"),
+    (0, 0x02, "SDA selected"),
+    (
+        1,
+        0x80,
+        "ICC and terminal have different application versions",
+    ),
+    (1, 0x40, "Expired application"),
+    (1, 0x20, "Application not yet effective"),
+    (1, 0x10, "Requested service not allowed for card product"),
+    (1, 0x08, "New card"),
+    (2, 0x80, "Cardholder verification was not successful"),
+    (2, 0x40, "Unrecognised CVM"),
+    (2, 0x20, "PIN Try Limit exceeded"),
+    (
+        2,
+        0x10,
+        "PIN entry required and PIN pad not present or not working",
+    ),
+    (
+        2,
+        0x08,
+        "PIN entry required, PIN pad present, but PIN was not entered",
+    ),
+    (2, 0x04, "Online PIN entered"),
+    (3, 0x80, "Transaction exceeds floor limit"),
+    (3, 0x40, "Lower consecutive offline limit exceeded"),
+    (3, 0x20, "Upper consecutive offline limit exceeded"),
+    (
+        3,
+        0x10,
+        "Transaction selected randomly for online processing",
+    ),
+    (3, 0x08, "Merchant forced transaction online"),
+    (4, 0x80, "Default TDOL used"),
+    (4, 0x40, "Issuer authentication failed"),
+    (4, 0x20, "Script processing failed before final GENERATE AC"),
+    (4, 0x10, "Script processing failed after final GENERATE AC"),
+];
+
+/// Names of the bits set in `bits`, which uses the TVR layout. Missing bytes are treated as 0.
+pub fn flag_names(bits: &[u8]) -> Vec<&'static str> {
+    bit_names(TVR_FLAGS, bits)
+}
+
+/// Terminal Verification Results (0x95)
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Tvr(pub [u8; 5]);
+
+impl Tvr {
+    pub const OFFLINE_DATA_AUTHENTICATION_NOT_PERFORMED: (usize, u8) = (0, 0x80);
+    pub const SDA_FAILED: (usize, u8) = (0, 0x40);
+    pub const DDA_FAILED: (usize, u8) = (0, 0x08);
+    pub const CDA_FAILED: (usize, u8) = (0, 0x04);
+    pub const SDA_SELECTED: (usize, u8) = (0, 0x02);
+
+    /// Set a flag, one of the associated constants
+    pub fn set(&mut self, (byte, mask): (usize, u8)) {
+        self.0[byte] |= mask;
+    }
+
+    pub fn flags(&self) -> Vec<&'static str> {
+        flag_names(&self.0)
+    }
+}
+
+impl Display for Tvr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "TVR {}", hex::encode(self.0))?;
+        for flag in self.flags() {
+            write!(f, "\n    {}", flag)?;
+        }
+        Ok(())
+    }
+}
+
+/// Issuer Action Codes (0x9f0d, 0x9f0e, 0x9f0f) or Terminal Action Codes, in the TVR layout
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct ActionCodes {
+    pub default: [u8; 5],
+    pub denial: [u8; 5],
+    pub online: [u8; 5],
+}
+
+impl ActionCodes {
+    /// Read the Issuer Action Codes from the card. Missing codes are all zeroes as specified in
+    /// Book 3 section 10.7, except IAC Default and Online which default to all ones.
+    pub fn from_options(options: &FieldMap) -> ActionCodes {
+        let get = |tag, missing| {
+            options
+                .get(&tag)
+                .and_then(Value::as_binary)
+                .and_then(|code| code.try_into().ok())
+                .unwrap_or(missing)
+        };
+        ActionCodes {
+            default: get(0x9f0d, [0xff; 5]),
+            denial: get(0x9f0e, [0x00; 5]),
+            online: get(0x9f0f, [0xff; 5]),
+        }
+    }
+}
+
+impl Display for ActionCodes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (name, code) in [
+            ("Default", &self.default),
+            ("Denial", &self.denial),
+            ("Online", &self.online),
+        ] {
+            write!(f, "{} {}", name, hex::encode(code))?;
+            for flag in flag_names(code) {
+                write!(f, "\n    {}", flag)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of terminal action analysis
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Decision {
+    /// Approve offline, request a TC
+    Approve,
+    /// Decline offline, request an AAC
+    Decline,
+    /// Ask the issuer, request an ARQC
+    Online,
+}
+
+impl Decision {
+    /// Cryptogram the terminal should ask for in the first GENERATE AC
+    pub fn ac_type(self) -> AcType {
+        match self {
+            Decision::Approve => AcType::Tc,
+            Decision::Decline => AcType::Aac,
+            Decision::Online => AcType::Arqc,
+        }
+    }
+}
+
+impl Display for Decision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Decision::Approve => write!(f, "approve offline"),
+            Decision::Decline => write!(f, "decline offline"),
+            Decision::Online => write!(f, "go online"),
+        }
+    }
+}
+
+/// Terminal action analysis as in EMV 4.4 Book 3 section 10.7, for a terminal that can go
+/// online. The transaction is declined if any TVR bit is set in either denial code, otherwise it
+/// goes online if any bit is set in either online code, otherwise it is approved. The default
+/// codes only matter when the terminal can't reach the issuer, which we don't model.
+pub fn terminal_decision(tvr: &Tvr, iac: &ActionCodes, tac: &ActionCodes) -> Decision {
+    let matches = |iac: &[u8; 5], tac: &[u8; 5]| {
+        tvr.0
+            .iter()
+            .zip(iac.iter().zip(tac))
+            .any(|(tvr, (iac, tac))| tvr & (iac | tac) != 0)
+    };
+    if matches(&iac.denial, &tac.denial) {
+        Decision::Decline
+    } else if matches(&iac.online, &tac.online) {
+        Decision::Online
+    } else {
+        Decision::Approve
+    }
+}
+
+/// Names of the conditions that trigger an action when either the issuer or the terminal asks
+/// for it, i.e. `iac | tac`
+pub fn combined_action(iac: &[u8], tac: &[u8]) -> Vec<&'static str> {
+    let combined: Vec<u8> = (0..iac.len().max(tac.len()))
+        .map(|idx| iac.get(idx).unwrap_or(&0) | tac.get(idx).unwrap_or(&0))
+        .collect();
+    flag_names(&combined)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flags() {
+        assert_eq!(
+            Tvr([0x80, 0x00, 0x80, 0x00, 0x40]).flags(),
+            vec![
+                "Offline data authentication was not performed",
+                "Cardholder verification was not successful",
+                "Issuer authentication failed"
+            ]
+        );
+        assert!(Tvr::default().flags().is_empty());
+    }
+
+    #[test]
+    fn test_combined_action() {
+        assert_eq!(
+            combined_action(&[0x40, 0x00, 0x00, 0x00, 0x00], &[0x08, 0x00, 0x00, 0x80]),
+            vec![
+                "SDA failed",
+                "DDA failed",
+                "Transaction exceeds floor limit"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_terminal_decision() {
+        let mut tvr = Tvr::default();
+        let iac = ActionCodes {
+            default: [0; 5],
+            denial: [0x40, 0, 0, 0, 0],
+            online: [0x88, 0, 0, 0, 0],
+        };
+        let tac = ActionCodes {
+            denial: [0x08, 0, 0, 0, 0],
+            ..ActionCodes::default()
+        };
+        assert_eq!(terminal_decision(&tvr, &iac, &tac), Decision::Approve);
+
+        tvr.set(Tvr::OFFLINE_DATA_AUTHENTICATION_NOT_PERFORMED);
+        assert_eq!(terminal_decision(&tvr, &iac, &tac), Decision::Online);
+
+        // The denial codes win over the online codes
+        tvr.set(Tvr::DDA_FAILED);
+        assert_eq!(terminal_decision(&tvr, &iac, &tac), Decision::Decline);
+    }
+
+    #[test]
+    fn test_action_codes_from_options() {
+        let mut options = FieldMap::new();
+        options.insert(0x9f0e, Value::Binary(vec![0x00, 0x10, 0x00, 0x00, 0x00]));
+        assert_eq!(
+            ActionCodes::from_options(&options),
+            ActionCodes {
+                default: [0xff; 5],
+                denial: [0x00, 0x10, 0x00, 0x00, 0x00],
+                online: [0xff; 5],
+            }
+        );
+    }
+}
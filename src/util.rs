@@ -3,3 +3,13 @@ pub fn left_pad_slice<const LEN: usize>(slice: &[u8]) -> [u8; LEN] {
     s[LEN - slice.len()..].copy_from_slice(slice);
     s
 }
+
+/// Names of the bits set in `bits` from a table of (byte, mask, name). Missing bytes are treated
+/// as 0.
+pub fn bit_names(table: &[(usize, u8, &'static str)], bits: &[u8]) -> Vec<&'static str> {
+    table
+        .iter()
+        .filter(|&&(byte, mask, _)| bits.get(byte).is_some_and(|b| b & mask != 0))
+        .map(|&(_, _, name)| name)
+        .collect()
+}
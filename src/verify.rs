@@ -0,0 +1,392 @@
+use std::fmt::Display;
+
+use anyhow::Context;
+use serde::{ser::SerializeStruct, Serialize};
+
+use crate::{
+    aip::Aip,
+    color::{Painter, Style},
+    crypto::{
+        chain::{verify_sda, ICCPublicKey, IssuerPublicKey},
+        dynamic::{verify_cda, verify_dda},
+    },
+    exchange::{AcType, CardTransport},
+    pan::display_pan,
+    processing_options::{self, ProcessingResult},
+    tlv::{FieldMap, OptionsMap, TagMap, Value},
+    transaction,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckResult {
+    Pass(String),
+    Fail(String),
+    /// The card doesn't support this, or an earlier step it depends on failed
+    Skip(String),
+}
+
+impl CheckResult {
+    pub fn is_fail(&self) -> bool {
+        matches!(self, CheckResult::Fail(_))
+    }
+}
+
+impl CheckResult {
+    /// Show the result with a status label colored by `painter`
+    pub fn display(&self, painter: Painter) -> CheckResultDisplay<'_> {
+        CheckResultDisplay {
+            result: self,
+            painter,
+        }
+    }
+}
+
+pub struct CheckResultDisplay<'a> {
+    result: &'a CheckResult,
+    painter: Painter,
+}
+
+impl Display for CheckResultDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (style, label, detail) = match self.result {
+            CheckResult::Pass(detail) => (Style::Pass, "[PASS]", detail),
+            CheckResult::Fail(detail) => (Style::Error, "[FAIL]", detail),
+            CheckResult::Skip(detail) => (Style::Warning, "[SKIP]", detail),
+        };
+        write!(f, "{} {}", self.painter.paint(style, label), detail)
+    }
+}
+
+impl Display for CheckResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.display(Painter::default()).fmt(f)
+    }
+}
+
+/// The checks done by [`verify_card`], in order
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Step {
+    /// Recovering the issuer public key from its certificate
+    IssuerCert,
+    /// Recovering the ICC public key from its certificate
+    IccCert,
+    Sda,
+    Dda,
+    Cda,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepResult {
+    pub step: Step,
+    pub result: CheckResult,
+}
+
+impl Serialize for StepResult {
+    /// `{"step": "issuer_cert", "ok": true, "status": "pass", "detail": "..."}`, where `ok` is
+    /// only false for failures
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (status, detail) = match &self.result {
+            CheckResult::Pass(detail) => ("pass", detail),
+            CheckResult::Fail(detail) => ("fail", detail),
+            CheckResult::Skip(detail) => ("skip", detail),
+        };
+        let mut state = serializer.serialize_struct("StepResult", 4)?;
+        state.serialize_field("step", &self.step)?;
+        state.serialize_field("ok", &!self.result.is_fail())?;
+        state.serialize_field("status", status)?;
+        state.serialize_field("detail", detail)?;
+        state.end()
+    }
+}
+
+/// Everything found by [`verify_card`]. Serializes to the JSON report of `verify --json`.
+#[derive(Clone, PartialEq, Eq, Serialize)]
+pub struct VerifyReport {
+    /// Application PAN (0x5a) as one digit per byte, masked when serialized
+    #[serde(serialize_with = "serialize_pan")]
+    pub pan: Option<Vec<u8>>,
+    pub passed: bool,
+    pub steps: Vec<StepResult>,
+    /// SHA-1 of the recovered issuer key, see [`crate::crypto::export::spki_fingerprint`]
+    #[serde(serialize_with = "serialize_fingerprint")]
+    pub issuer_key_fingerprint: Option<[u8; 20]>,
+    #[serde(serialize_with = "serialize_fingerprint")]
+    pub icc_key_fingerprint: Option<[u8; 20]>,
+}
+
+fn serialize_pan<S: serde::Serializer>(
+    pan: &Option<Vec<u8>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    pan.as_deref()
+        .map(|pan| display_pan(pan, false))
+        .serialize(serializer)
+}
+
+/// Like the derived implementation, except the PAN is masked
+impl std::fmt::Debug for VerifyReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VerifyReport")
+            .field(
+                "pan",
+                &self.pan.as_deref().map(|pan| display_pan(pan, false)),
+            )
+            .field("passed", &self.passed)
+            .field("steps", &self.steps)
+            .field("issuer_key_fingerprint", &self.issuer_key_fingerprint)
+            .field("icc_key_fingerprint", &self.icc_key_fingerprint)
+            .finish()
+    }
+}
+
+fn serialize_fingerprint<S: serde::Serializer>(
+    fingerprint: &Option<[u8; 20]>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    fingerprint.map(hex::encode).serialize(serializer)
+}
+
+fn check<T, E: Display>(
+    name: &str,
+    result: Result<T, E>,
+    describe: impl FnOnce(&T) -> String,
+) -> (CheckResult, Option<T>) {
+    match result {
+        Ok(value) => (
+            CheckResult::Pass(format!("{}: {}", name, describe(&value))),
+            Some(value),
+        ),
+        Err(err) => (CheckResult::Fail(format!("{}: {:#}", name, err)), None),
+    }
+}
+
+/// Select the application `aid`, recover its keys, and run every offline data authentication
+/// method the AIP says it supports. Errors are only returned if the application can't be read at
+/// all, failures of individual steps are reported as [`CheckResult::Fail`] and make the report
+/// not pass.
+///
+/// CDA is checked with a GENERATE AC requesting an ARQC, so this increments the ATC.
+pub fn verify_card<C: CardTransport + ?Sized>(
+    card: &mut C,
+    aid: &[u8],
+    state: &mut OptionsMap,
+) -> anyhow::Result<VerifyReport> {
+    let fci = processing_options::select_application(card, aid)?;
+    let ProcessingResult {
+        fields: options,
+        sda_data,
+        ..
+    } = processing_options::read_application_data(card, &fci, state)?;
+    let aip = Aip::try_from(
+        options
+            .get(&0x82)
+            .and_then(Value::as_binary)
+            .context("Card did not send an AIP")?,
+    )?;
+
+    let mut results = Vec::new();
+
+    let rid: [u8; 5] = aid.get(..5).context("AID too short")?.try_into().unwrap();
+    let (result, issuer_key) = check(
+        "Issuer public key",
+        IssuerPublicKey::from_options(rid, &options),
+        ToString::to_string,
+    );
+    results.push(StepResult {
+        step: Step::IssuerCert,
+        result,
+    });
+
+    let icc_key = match &issuer_key {
+        Some(issuer_key) if aip.dda_supported() || aip.cda_supported() => {
+            let (result, icc_key) = check(
+                "ICC public key",
+                ICCPublicKey::from_options(issuer_key, &sda_data, &options),
+                ToString::to_string,
+            );
+            results.push(StepResult {
+                step: Step::IccCert,
+                result,
+            });
+            icc_key
+        }
+        Some(_) => {
+            results.push(StepResult {
+                step: Step::IccCert,
+                result: CheckResult::Skip(
+                    "ICC public key: card does not support DDA or CDA".to_string(),
+                ),
+            });
+            None
+        }
+        None => {
+            results.push(StepResult {
+                step: Step::IccCert,
+                result: CheckResult::Skip("ICC public key: no issuer public key".to_string()),
+            });
+            None
+        }
+    };
+
+    results.push(StepResult {
+        step: Step::Sda,
+        result: match (&issuer_key, aip.sda_supported()) {
+            (_, false) => CheckResult::Skip("SDA: not supported by card".to_string()),
+            (None, true) => CheckResult::Skip("SDA: no issuer public key".to_string()),
+            (Some(issuer_key), true) => {
+                check("SDA", verify_sda(issuer_key, &options, &sda_data), |dac| {
+                    format!("Data Authentication Code {}", hex::encode(dac))
+                })
+                .0
+            }
+        },
+    });
+
+    results.push(StepResult {
+        step: Step::Dda,
+        result: match (&icc_key, aip.dda_supported()) {
+            (_, false) => CheckResult::Skip("DDA: not supported by card".to_string()),
+            (None, true) => CheckResult::Skip("DDA: no ICC public key".to_string()),
+            (Some(icc_key), true) => {
+                check(
+                    "DDA",
+                    verify_dynamic(card, icc_key, &options, state),
+                    |number| format!("ICC Dynamic Number {}", hex::encode(number)),
+                )
+                .0
+            }
+        },
+    });
+
+    results.push(StepResult {
+        step: Step::Cda,
+        result: match (&icc_key, aip.cda_supported()) {
+            (_, false) => CheckResult::Skip("CDA: not supported by card".to_string()),
+            (None, true) => CheckResult::Skip("CDA: no ICC public key".to_string()),
+            (Some(icc_key), true) => {
+                check(
+                    "CDA",
+                    verify_combined(card, icc_key, &fci, &options, state),
+                    |cryptogram| format!("Application Cryptogram {}", hex::encode(cryptogram)),
+                )
+                .0
+            }
+        },
+    });
+
+    Ok(VerifyReport {
+        pan: options
+            .get(&0x5a)
+            .and_then(Value::as_digit_string)
+            .map(<[u8]>::to_vec),
+        passed: !results.iter().any(|step| step.result.is_fail()),
+        steps: results,
+        issuer_key_fingerprint: issuer_key.as_ref().map(IssuerPublicKey::fingerprint),
+        icc_key_fingerprint: icc_key.as_ref().map(ICCPublicKey::fingerprint),
+    })
+}
+
+fn verify_dynamic<C: CardTransport + ?Sized>(
+    card: &mut C,
+    icc_key: &ICCPublicKey,
+    options: &FieldMap,
+    state: &mut OptionsMap,
+) -> anyhow::Result<Vec<u8>> {
+    let sdad = transaction::do_transaction(card, options, state)?;
+    let ddol_data = options.get_dol(0x9f49)?.encode_bare(state);
+    Ok(verify_dda(icc_key, &sdad, &ddol_data)?)
+}
+
+fn verify_combined<C: CardTransport + ?Sized>(
+    card: &mut C,
+    icc_key: &ICCPublicKey,
+    fci: &FieldMap,
+    options: &FieldMap,
+    state: &mut OptionsMap,
+) -> anyhow::Result<[u8; 8]> {
+    let cryptogram = transaction::generate_ac(card, options, state, AcType::Arqc, true)?;
+    let sdad = cryptogram
+        .signed_dynamic_data
+        .context("Card did not return signed dynamic data")?;
+
+    let pdol_data = fci
+        .get_path(&[0xa5, 0x9f38])
+        .ok()
+        .and_then(Value::as_dol)
+        .map(|pdol| pdol.encode_bare(state))
+        .unwrap_or_default();
+    let cdol_data = options.get_dol(0x8c)?.encode_bare(state);
+    let transaction_data =
+        transaction::cda_transaction_data(&pdol_data, &cdol_data, &cryptogram.response_data)?;
+    let unpredictable_number = state
+        .get(&0x9f37)
+        .and_then(Value::as_binary)
+        .unwrap_or_default();
+
+    Ok(verify_cda(
+        icc_key,
+        &sdad,
+        unpredictable_number,
+        cryptogram.cid,
+        &transaction_data,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_json() {
+        let report = VerifyReport {
+            pan: Some(vec![4, 7, 6, 1, 7, 3, 9, 0, 0, 1, 0, 1, 0, 1, 1, 9]),
+            passed: false,
+            steps: vec![
+                StepResult {
+                    step: Step::IssuerCert,
+                    result: CheckResult::Pass("Issuer public key: ok".to_string()),
+                },
+                StepResult {
+                    step: Step::Dda,
+                    result: CheckResult::Fail("DDA: bad signature".to_string()),
+                },
+                StepResult {
+                    step: Step::Cda,
+                    result: CheckResult::Skip("CDA: not supported by card".to_string()),
+                },
+            ],
+            issuer_key_fingerprint: Some([0xab; 20]),
+            icc_key_fingerprint: None,
+        };
+        assert_eq!(
+            serde_json::to_value(&report).unwrap(),
+            serde_json::json!({
+                "pan": "476173******0119",
+                "passed": false,
+                "steps": [
+                    {
+                        "step": "issuer_cert",
+                        "ok": true,
+                        "status": "pass",
+                        "detail": "Issuer public key: ok",
+                    },
+                    {
+                        "step": "dda",
+                        "ok": false,
+                        "status": "fail",
+                        "detail": "DDA: bad signature",
+                    },
+                    {
+                        "step": "cda",
+                        "ok": true,
+                        "status": "skip",
+                        "detail": "CDA: not supported by card",
+                    },
+                ],
+                "issuer_key_fingerprint": "abababababababababababababababababababab",
+                "icc_key_fingerprint": null,
+            })
+        );
+    }
+}